@@ -1,20 +1,22 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use reson::{MidiEvent, Note, Portamento, Synth, SynthOpts, Tuning, Voice};
+use reson::{
+    MidiEvent, MidiQueue, Note, Portamento, Synth, SynthCapacity, SynthOpts, Velocity, Voice,
+};
 use ringbuf::HeapRb;
 use std::sync::mpsc;
 use std::time::Duration;
 use reson::blep::{Sawtooth, Waveform};
 
 fn main() {
-    // A channel for sending MIDI events to the synth
-    let (midi_tx, midi_rx) = mpsc::sync_channel::<MidiEvent>(1024);
+    // A queue for sending MIDI events to the synth
+    let (mut midi_tx, mut midi_rx) = MidiQueue::new(1024).split();
     // A ring buffer for sending audio from the synth to the audio card
     let (mut audio_tx, mut audio_rx) = HeapRb::<f32>::new(2048).split();
     // A channel for the audio card to signal that it is ready for more input
     let (audio_tx2, audio_rx2) = mpsc::sync_channel::<()>(0);
 
     read_midi(move |ev| {
-        midi_tx.send(ev).ok();
+        midi_tx.push(ev).ok();
     });
 
     let sample_rate = play_audio(move |buffer| {
@@ -23,20 +25,23 @@ fn main() {
     });
 
     let mut synth = Synth::new(
+        SynthCapacity {
+            max_block_size: 256,
+            max_voices: 12,
+            max_buses: 1,
+        },
         SynthOpts {
-            tuning: Tuning::concert_pitch(),
             max_voices: 12,
-            max_block_size: 256,
             mono: true,
             portamento: Portamento::Variable(0.1),
-            max_pitch_bend: 2.0,
+            ..Default::default()
         },
         SimpleVoice::<Sawtooth>::new(),
     );
     synth.set_sample_rate(sample_rate);
+    synth.set_gain(0.1);
 
     const BLOCK_SIZE: usize = 128;
-    let [mut left, mut right] = [[0.0; BLOCK_SIZE]; 2];
     let mut stereo = [0.0; 2 * BLOCK_SIZE];
 
     loop {
@@ -46,17 +51,14 @@ fn main() {
         }
 
         // Recieve MIDI events
-        while let Ok(event) = midi_rx.try_recv() {
+        for event in &mut midi_rx {
             synth.midi_event(event);
         }
 
         // Synthesise audio
-        synth.process([&mut left, &mut right]);
-        left.iter_mut().for_each(|s| *s *= 0.1);
-        right.iter_mut().for_each(|s| *s *= 0.1);
+        synth.process_interleaved(&mut stereo);
 
-        // Interleave and write to the ring buffer
-        interleave_stereo(&left, &right, &mut stereo);
+        // Write to the ring buffer
         audio_tx.push_slice(&stereo);
     }
 }
@@ -171,12 +173,12 @@ impl<W: Waveform> Voice for SimpleVoice<W> {
         self.on = false;
     }
 
-    fn trigger(&mut self, _note: Note, velocity: u8) {
+    fn trigger(&mut self, _note: Note, velocity: Velocity) {
         self.on = true;
-        self.vel = (velocity as f32) / 127.0;
+        self.vel = velocity.normalized().get();
     }
 
-    fn release(&mut self) {
+    fn release(&mut self, _velocity: Velocity) {
         self.on = false;
     }
 
@@ -199,12 +201,3 @@ impl<W: Waveform> Voice for SimpleVoice<W> {
         }
     }
 }
-
-/// Interleaves the two channels of a stereo signal.
-pub fn interleave_stereo(left: &[f32], right: &[f32], output: &mut [f32]) {
-    let lr = left.iter().zip(right.iter());
-    for (i, (&ls, &rs)) in lr.enumerate() {
-        output[2 * i] = ls;
-        output[2 * i + 1] = rs;
-    }
-}
\ No newline at end of file
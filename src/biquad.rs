@@ -0,0 +1,122 @@
+use std::f32::consts::TAU;
+
+/// A stereo second order filter, with coefficients from the Audio EQ Cookbook.
+#[derive(Copy, Clone, Default)]
+pub(crate) struct Biquad {
+    /// The coefficients of the filter, normalised so that `a0` is 1.
+    coeffs: Coeffs,
+    /// The state of each channel, in transposed direct form II.
+    state: [[f32; 2]; 2],
+}
+
+/// The normalised coefficients of a [Biquad].
+#[derive(Copy, Clone, Default)]
+struct Coeffs {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+/// The Q of a Butterworth filter.
+pub(crate) const BUTTERWORTH_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// The kind of response of a [Biquad].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum Response {
+    Lowpass,
+    Highpass,
+    Allpass,
+    /// A bandpass with a peak gain of 0 dB.
+    Bandpass,
+    /// A bell with the given gain in decibels at its centre frequency.
+    Peaking(f32),
+    /// A high shelf with the given gain in decibels, with a slope set by the Q.
+    HighShelf(f32),
+}
+
+impl Biquad {
+    /// Sets the response of the filter, keeping its state.
+    ///
+    /// # Parameters
+    /// * `response` - The kind of response.
+    /// * `freq` - The cutoff or centre frequency in Hz, which is kept below the Nyquist frequency.
+    /// * `q` - The quality factor.
+    /// * `sample_rate` - The sample rate in Hz.
+    pub fn set(&mut self, response: Response, freq: f32, q: f32, sample_rate: u32) {
+        let freq = freq.clamp(1.0, 0.49 * sample_rate as f32);
+        let w0 = TAU * freq / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let ((b0, b1, b2), (a0, a1, a2)) = match response {
+            Response::Lowpass => (
+                (0.5 * (1.0 - cos), 1.0 - cos, 0.5 * (1.0 - cos)),
+                (1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            ),
+            Response::Highpass => (
+                (0.5 * (1.0 + cos), -(1.0 + cos), 0.5 * (1.0 + cos)),
+                (1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            ),
+            Response::Allpass => (
+                (1.0 - alpha, -2.0 * cos, 1.0 + alpha),
+                (1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            ),
+            Response::Bandpass => ((alpha, 0.0, -alpha), (1.0 + alpha, -2.0 * cos, 1.0 - alpha)),
+            Response::Peaking(gain) => {
+                let a = 10f32.powf(gain / 40.0);
+                (
+                    (1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a),
+                    (1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a),
+                )
+            }
+            Response::HighShelf(gain) => {
+                let a = 10f32.powf(gain / 40.0);
+                let k = 2.0 * a.sqrt() * alpha;
+                (
+                    (
+                        a * ((a + 1.0) + (a - 1.0) * cos + k),
+                        -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                        a * ((a + 1.0) + (a - 1.0) * cos - k),
+                    ),
+                    (
+                        (a + 1.0) - (a - 1.0) * cos + k,
+                        2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                        (a + 1.0) - (a - 1.0) * cos - k,
+                    ),
+                )
+            }
+        };
+        self.coeffs = Coeffs {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        };
+    }
+
+    /// Clears the state of the filter.
+    pub fn reset(&mut self) {
+        self.state = [[0.0; 2]; 2];
+    }
+
+    /// Filters a sample of a channel.
+    pub fn process_sample(&mut self, channel: usize, x: f32) -> f32 {
+        let Coeffs { b0, b1, b2, a1, a2 } = self.coeffs;
+        let s = &mut self.state[channel];
+        let y = b0 * x + s[0];
+        s[0] = b1 * x - a1 * y + s[1];
+        s[1] = b2 * x - a2 * y;
+        y
+    }
+
+    /// Filters a block of stereo audio in place.
+    pub fn process(&mut self, buffer: [&mut [f32]; 2]) {
+        for (channel, samples) in buffer.into_iter().enumerate() {
+            for sample in samples {
+                *sample = self.process_sample(channel, *sample);
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::Note;
+
+/// A chord identified from a set of notes, such as the notes currently held.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Chord {
+    /// The pitch class of the root, from 0 (C) to 11 (B).
+    pub root: u8,
+    /// The quality of the chord.
+    pub quality: ChordQuality,
+    /// The inversion of the chord, where 0 is root position, 1 is first inversion, and so on.
+    pub inversion: u8,
+    /// The lowest note of the chord.
+    pub bass: Note,
+}
+
+/// The quality of a [Chord].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ChordQuality {
+    /// A root and perfect fifth, with no third.
+    Power,
+    /// A major triad.
+    Major,
+    /// A minor triad.
+    Minor,
+    /// A diminished triad.
+    Diminished,
+    /// An augmented triad.
+    Augmented,
+    /// A triad with the third replaced by a major second.
+    Suspended2,
+    /// A triad with the third replaced by a perfect fourth.
+    Suspended4,
+    /// A major triad with a major seventh.
+    Major7,
+    /// A major triad with a minor seventh.
+    Dominant7,
+    /// A minor triad with a minor seventh.
+    Minor7,
+    /// A minor triad with a major seventh.
+    MinorMajor7,
+    /// A diminished triad with a minor seventh.
+    HalfDiminished7,
+    /// A diminished triad with a diminished seventh.
+    Diminished7,
+}
+
+impl ChordQuality {
+    /// Gets the intervals in semitones above the root which make up the chord.
+    pub fn intervals(&self) -> &'static [u8] {
+        match self {
+            ChordQuality::Power => &[0, 7],
+            ChordQuality::Major => &[0, 4, 7],
+            ChordQuality::Minor => &[0, 3, 7],
+            ChordQuality::Diminished => &[0, 3, 6],
+            ChordQuality::Augmented => &[0, 4, 8],
+            ChordQuality::Suspended2 => &[0, 2, 7],
+            ChordQuality::Suspended4 => &[0, 5, 7],
+            ChordQuality::Major7 => &[0, 4, 7, 11],
+            ChordQuality::Dominant7 => &[0, 4, 7, 10],
+            ChordQuality::Minor7 => &[0, 3, 7, 10],
+            ChordQuality::MinorMajor7 => &[0, 3, 7, 11],
+            ChordQuality::HalfDiminished7 => &[0, 3, 6, 10],
+            ChordQuality::Diminished7 => &[0, 3, 6, 9],
+        }
+    }
+}
+
+const QUALITIES: [ChordQuality; 13] = [
+    ChordQuality::Power,
+    ChordQuality::Major,
+    ChordQuality::Minor,
+    ChordQuality::Diminished,
+    ChordQuality::Augmented,
+    ChordQuality::Suspended2,
+    ChordQuality::Suspended4,
+    ChordQuality::Major7,
+    ChordQuality::Dominant7,
+    ChordQuality::Minor7,
+    ChordQuality::MinorMajor7,
+    ChordQuality::HalfDiminished7,
+    ChordQuality::Diminished7,
+];
+
+impl Chord {
+    /// Identifies the chord formed by a set of notes, ignoring octave doublings.
+    ///
+    /// Returns `None` if the notes don't form a recognised chord. Where the notes could be
+    /// named with several roots, such as for augmented and diminished seventh chords,
+    /// the bass note is preferred as the root.
+    pub fn identify(notes: impl IntoIterator<Item = Note>) -> Option<Self> {
+        // Collect the pitch classes as a bit set, and find the bass note
+        let mut pitch_classes = 0u16;
+        let mut bass = None;
+        for note in notes {
+            pitch_classes |= 1 << (note % 12);
+            bass = Some(bass.map_or(note, |bass: Note| bass.min(note)));
+        }
+        let bass = bass?;
+        let bass_class = bass % 12;
+
+        // Try each pitch class as the root, starting with the bass
+        let roots = (0..12).map(|i| (bass_class + i) % 12);
+        for root in roots.filter(|&root| pitch_classes & (1 << root) != 0) {
+            let relative = rotate_pitch_classes(pitch_classes, root);
+            for quality in QUALITIES {
+                if chord_mask(quality) != relative {
+                    continue;
+                }
+                let bass_interval = (bass_class + 12 - root) % 12;
+                let inversion = quality
+                    .intervals()
+                    .iter()
+                    .position(|&interval| interval == bass_interval)
+                    .unwrap_or(0) as u8;
+                return Some(Chord { root, quality, inversion, bass });
+            }
+        }
+
+        None
+    }
+}
+
+/// Gets the pitch classes of a chord quality with a root of C, as a bit set.
+fn chord_mask(quality: ChordQuality) -> u16 {
+    quality
+        .intervals()
+        .iter()
+        .fold(0, |mask, &interval| mask | (1 << interval))
+}
+
+/// Transposes a bit set of pitch classes down by the given number of semitones.
+fn rotate_pitch_classes(pitch_classes: u16, semitones: u8) -> u16 {
+    let semitones = semitones % 12;
+    ((pitch_classes >> semitones) | (pitch_classes << (12 - semitones))) & 0x0fff
+}
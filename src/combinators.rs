@@ -0,0 +1,758 @@
+use crate::{Note, Resources, Velocity, Voice};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The number of samples in which combinators process their additional voices at a time.
+const CHUNK_SIZE: usize = 64;
+
+/// The time in seconds over which a [Switch] crossfades between its voices.
+const SWITCH_TIME: f32 = 0.01;
+
+/// A voice which produces silence, such as for bypassing one side of a [Switch].
+#[derive(Copy, Clone, Default)]
+pub struct NullVoice;
+
+/// A voice whose output is scaled by a fixed gain.
+#[derive(Clone)]
+pub struct Gain<V> {
+    /// The wrapped voice.
+    pub voice: V,
+    /// The gain applied to the voice, as a linear amplitude.
+    pub gain: f32,
+}
+
+/// A voice which is positioned in the stereo field.
+#[derive(Clone)]
+pub struct Pan<V> {
+    /// The wrapped voice.
+    pub voice: V,
+    /// The stereo position, from -1 (left) to 1 (right).
+    /// The channel opposite the pan direction is attenuated.
+    pub pan: f32,
+}
+
+/// A voice which plays at a fixed offset from the pitch of each note.
+#[derive(Clone)]
+pub struct Detune<V> {
+    /// The wrapped voice.
+    pub voice: V,
+    /// The pitch offset in cents.
+    pub cents: f32,
+}
+
+/// A pair of voices which both play every note, with their outputs summed.
+#[derive(Clone)]
+pub struct Layer<A, B> {
+    /// The first voice.
+    pub a: A,
+    /// The second voice.
+    pub b: B,
+}
+
+/// A pair of voices split across the keyboard, where notes below the split point are played
+/// by the low voice and the remaining notes by the high voice.
+#[derive(Clone)]
+pub struct Split<A, B> {
+    /// The voice playing notes below the split point.
+    pub low: A,
+    /// The voice playing notes at or above the split point.
+    pub high: B,
+    /// The lowest note played by the high voice.
+    pub split: Note,
+    /// Whether each of the low and high voices is producing sound.
+    active: [bool; 2],
+}
+
+/// A pair of voices which both receive every note, of which only one is heard at a time,
+/// for comparing two voice implementations live.
+///
+/// The heard voice is chosen with a [SwitchControl], which is shared by every clone of the
+/// switch, and changes are crossfaded to avoid clicks. The hidden voice is processed along
+/// with the heard one, so switching to it picks up the notes exactly where they are.
+#[derive(Clone)]
+pub struct Switch<A, B> {
+    /// The voice heard when the switch is off.
+    pub a: A,
+    /// The voice heard when the switch is on.
+    pub b: B,
+    /// Whether voice B is selected.
+    select: Arc<AtomicBool>,
+    /// The current crossfade position, from 0 (only A) to 1 (only B).
+    mix: f32,
+    /// The change in crossfade position per sample.
+    step: f32,
+}
+
+/// Selects which voice of a [Switch] is heard. Clones control the same switches.
+#[derive(Clone)]
+pub struct SwitchControl(Arc<AtomicBool>);
+
+/// Any number of voices of the same type which all play every note, each with its own gain,
+/// pan and transposition, for building stacked patches within a single synth.
+#[derive(Clone)]
+pub struct Layers<V> {
+    /// Each layer's voice and settings.
+    layers: Vec<(V, LayerSettings)>,
+}
+
+/// The settings of a layer within [Layers].
+#[derive(Copy, Clone, Debug)]
+pub struct LayerSettings {
+    /// The gain applied to the layer, as a linear amplitude.
+    pub gain: f32,
+    /// The stereo position of the layer, from -1 (left) to 1 (right).
+    pub pan: f32,
+    /// The number of semitones by which the layer is transposed.
+    pub transpose: i8,
+}
+
+impl<V> Gain<V> {
+    /// Wraps a voice, scaling its output by the given gain.
+    pub fn new(voice: V, gain: f32) -> Self {
+        Self { voice, gain }
+    }
+}
+
+impl<V> Pan<V> {
+    /// Wraps a voice, positioning it at the given stereo position.
+    pub fn new(voice: V, pan: f32) -> Self {
+        Self { voice, pan }
+    }
+}
+
+impl<V> Detune<V> {
+    /// Wraps a voice, offsetting its pitch by the given number of cents.
+    pub fn new(voice: V, cents: f32) -> Self {
+        Self { voice, cents }
+    }
+}
+
+impl<A, B> Layer<A, B> {
+    /// Layers two voices, so that both play every note.
+    pub fn new(a: A, b: B) -> Self {
+        Self { a, b }
+    }
+}
+
+impl<A, B> Split<A, B> {
+    /// Splits the keyboard between two voices at the given note,
+    /// which is the lowest note played by the high voice.
+    pub fn new(low: A, high: B, split: Note) -> Self {
+        Self {
+            low,
+            high,
+            split,
+            active: [false; 2],
+        }
+    }
+}
+
+impl<A, B> Switch<A, B> {
+    /// Creates a switch between two voices, with voice A heard initially.
+    pub fn new(a: A, b: B) -> Self {
+        Self {
+            a,
+            b,
+            select: Arc::new(AtomicBool::new(false)),
+            mix: 0.0,
+            step: 1.0,
+        }
+    }
+
+    /// Gets a control for choosing which voice is heard, which can be used from any thread.
+    pub fn control(&self) -> SwitchControl {
+        SwitchControl(self.select.clone())
+    }
+}
+
+impl SwitchControl {
+    /// Selects voice B if `true`, or voice A if `false`.
+    pub fn set(&self, b: bool) {
+        self.0.store(b, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if voice B is selected.
+    pub fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl<V> Layers<V> {
+    /// Creates a voice without any layers, which produces silence.
+    pub fn new() -> Self {
+        Self { layers: vec![] }
+    }
+
+    /// Adds a layer.
+    pub fn with_layer(mut self, voice: V, settings: LayerSettings) -> Self {
+        self.layers.push((voice, settings));
+        self
+    }
+
+    /// Gets the settings of each layer, in the order the layers were added.
+    pub fn settings_mut(&mut self) -> impl Iterator<Item = &mut LayerSettings> {
+        self.layers.iter_mut().map(|(_, settings)| settings)
+    }
+}
+
+impl<V> Default for Layers<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for LayerSettings {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            pan: 0.0,
+            transpose: 0,
+        }
+    }
+}
+
+/// Implements the methods of [Voice] which are forwarded unchanged to a wrapped voice.
+macro_rules! forward_voice {
+    () => {
+        fn set_sample_rate(&mut self, sample_rate: u32) {
+            self.voice.set_sample_rate(sample_rate);
+        }
+
+        fn reset(&mut self) {
+            self.voice.reset();
+        }
+
+        fn set_resources(&mut self, resources: &Resources) {
+            self.voice.set_resources(resources);
+        }
+
+        fn prepare(&mut self, max_block_size: usize) {
+            self.voice.prepare(max_block_size);
+        }
+
+        fn set_position(&mut self, position: u64) {
+            self.voice.set_position(position);
+        }
+
+        fn trigger(&mut self, note: Note, velocity: Velocity) {
+            self.voice.trigger(note, velocity);
+        }
+
+        fn retrigger(&mut self, note: Note, velocity: Velocity) {
+            self.voice.retrigger(note, velocity);
+        }
+
+        fn glide(&mut self, note: Note, velocity: Velocity) {
+            self.voice.glide(note, velocity);
+        }
+
+        fn set_pressure(&mut self, pressure: f32) {
+            self.voice.set_pressure(pressure);
+        }
+
+        fn set_timbre(&mut self, timbre: f32) {
+            self.voice.set_timbre(timbre);
+        }
+
+        fn nominal_level(&self) -> f32 {
+            self.voice.nominal_level()
+        }
+
+        fn current_level(&self) -> Option<f32> {
+            self.voice.current_level()
+        }
+
+        fn release(&mut self, velocity: Velocity) {
+            self.voice.release(velocity);
+        }
+    };
+}
+
+impl<V: Voice> Voice for Gain<V> {
+    forward_voice!();
+
+    fn process(&mut self, pitch: f32, output: [&mut [f32]; 2]) -> bool {
+        let [left, right] = output;
+        let active = self.voice.process(pitch, [left, right]);
+        left.iter_mut().for_each(|s| *s *= self.gain);
+        right.iter_mut().for_each(|s| *s *= self.gain);
+        active
+    }
+}
+
+impl<V: Voice> Voice for Pan<V> {
+    forward_voice!();
+
+    fn process(&mut self, pitch: f32, output: [&mut [f32]; 2]) -> bool {
+        let [left, right] = output;
+        let active = self.voice.process(pitch, [left, right]);
+        let pan = self.pan.clamp(-1.0, 1.0);
+        if pan < 0.0 {
+            right.iter_mut().for_each(|s| *s *= 1.0 + pan);
+        } else if pan > 0.0 {
+            left.iter_mut().for_each(|s| *s *= 1.0 - pan);
+        }
+        active
+    }
+}
+
+impl<V: Voice> Voice for Detune<V> {
+    forward_voice!();
+
+    fn process(&mut self, pitch: f32, output: [&mut [f32]; 2]) -> bool {
+        let ratio = 2f32.powf(self.cents / 1200.0);
+        self.voice.process(pitch * ratio, output)
+    }
+}
+
+impl<A: Voice, B: Voice> Voice for Layer<A, B> {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.a.set_sample_rate(sample_rate);
+        self.b.set_sample_rate(sample_rate);
+    }
+
+    fn reset(&mut self) {
+        self.a.reset();
+        self.b.reset();
+    }
+
+    fn set_resources(&mut self, resources: &Resources) {
+        self.a.set_resources(resources);
+        self.b.set_resources(resources);
+    }
+
+    fn prepare(&mut self, max_block_size: usize) {
+        self.a.prepare(max_block_size);
+        self.b.prepare(max_block_size);
+    }
+
+    fn set_position(&mut self, position: u64) {
+        self.a.set_position(position);
+        self.b.set_position(position);
+    }
+
+    fn trigger(&mut self, note: Note, velocity: Velocity) {
+        self.a.trigger(note, velocity);
+        self.b.trigger(note, velocity);
+    }
+
+    fn retrigger(&mut self, note: Note, velocity: Velocity) {
+        self.a.retrigger(note, velocity);
+        self.b.retrigger(note, velocity);
+    }
+
+    fn glide(&mut self, note: Note, velocity: Velocity) {
+        self.a.glide(note, velocity);
+        self.b.glide(note, velocity);
+    }
+
+    fn set_pressure(&mut self, pressure: f32) {
+        self.a.set_pressure(pressure);
+        self.b.set_pressure(pressure);
+    }
+
+    fn set_timbre(&mut self, timbre: f32) {
+        self.a.set_timbre(timbre);
+        self.b.set_timbre(timbre);
+    }
+
+    fn nominal_level(&self) -> f32 {
+        self.a.nominal_level() + self.b.nominal_level()
+    }
+
+    fn current_level(&self) -> Option<f32> {
+        Some(self.a.current_level()? + self.b.current_level()?)
+    }
+
+    fn release(&mut self, velocity: Velocity) {
+        self.a.release(velocity);
+        self.b.release(velocity);
+    }
+
+    fn process(&mut self, pitch: f32, output: [&mut [f32]; 2]) -> bool {
+        let [left, right] = output;
+        let a = self.a.process(pitch, [left, right]);
+        let b = add_chunked([left, right], [1.0; 2], |output| self.b.process(pitch, output));
+        a || b
+    }
+}
+
+impl<A: Voice, B: Voice> Voice for Split<A, B> {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.low.set_sample_rate(sample_rate);
+        self.high.set_sample_rate(sample_rate);
+    }
+
+    fn reset(&mut self) {
+        self.low.reset();
+        self.high.reset();
+        self.active = [false; 2];
+    }
+
+    fn set_resources(&mut self, resources: &Resources) {
+        self.low.set_resources(resources);
+        self.high.set_resources(resources);
+    }
+
+    fn prepare(&mut self, max_block_size: usize) {
+        self.low.prepare(max_block_size);
+        self.high.prepare(max_block_size);
+    }
+
+    fn set_position(&mut self, position: u64) {
+        self.low.set_position(position);
+        self.high.set_position(position);
+    }
+
+    fn trigger(&mut self, note: Note, velocity: Velocity) {
+        if note < self.split {
+            self.low.trigger(note, velocity);
+            self.active[0] = true;
+        } else {
+            self.high.trigger(note, velocity);
+            self.active[1] = true;
+        }
+    }
+
+    fn retrigger(&mut self, note: Note, velocity: Velocity) {
+        // Only the side playing the new note continues its tail, while the other keeps releasing
+        if note < self.split {
+            self.low.retrigger(note, velocity);
+            self.active[0] = true;
+        } else {
+            self.high.retrigger(note, velocity);
+            self.active[1] = true;
+        }
+    }
+
+    fn glide(&mut self, note: Note, velocity: Velocity) {
+        // Gliding across the split point hands the note over to the other voice
+        if note < self.split {
+            if self.active[1] {
+                self.high.release(velocity);
+            }
+            if self.active[0] {
+                self.low.glide(note, velocity);
+            } else {
+                self.low.trigger(note, velocity);
+                self.active[0] = true;
+            }
+        } else {
+            if self.active[0] {
+                self.low.release(velocity);
+            }
+            if self.active[1] {
+                self.high.glide(note, velocity);
+            } else {
+                self.high.trigger(note, velocity);
+                self.active[1] = true;
+            }
+        }
+    }
+
+    fn set_pressure(&mut self, pressure: f32) {
+        self.low.set_pressure(pressure);
+        self.high.set_pressure(pressure);
+    }
+
+    fn set_timbre(&mut self, timbre: f32) {
+        self.low.set_timbre(timbre);
+        self.high.set_timbre(timbre);
+    }
+
+    fn nominal_level(&self) -> f32 {
+        self.low.nominal_level().max(self.high.nominal_level())
+    }
+
+    fn current_level(&self) -> Option<f32> {
+        Some(self.low.current_level()? + self.high.current_level()?)
+    }
+
+    fn release(&mut self, velocity: Velocity) {
+        if self.active[0] {
+            self.low.release(velocity);
+        }
+        if self.active[1] {
+            self.high.release(velocity);
+        }
+    }
+
+    fn process(&mut self, pitch: f32, output: [&mut [f32]; 2]) -> bool {
+        let [left, right] = output;
+        match self.active {
+            [false, false] => {
+                left.fill(0.0);
+                right.fill(0.0);
+            }
+            [true, false] => self.active[0] = self.low.process(pitch, [left, right]),
+            [false, true] => self.active[1] = self.high.process(pitch, [left, right]),
+            [true, true] => {
+                self.active[0] = self.low.process(pitch, [left, right]);
+                self.active[1] = add_chunked([left, right], [1.0; 2], |output| {
+                    self.high.process(pitch, output)
+                });
+            }
+        }
+        self.active != [false; 2]
+    }
+}
+
+impl Voice for NullVoice {
+    fn set_sample_rate(&mut self, _sample_rate: u32) {}
+
+    fn reset(&mut self) {}
+
+    fn trigger(&mut self, _note: Note, _velocity: Velocity) {}
+
+    fn current_level(&self) -> Option<f32> {
+        Some(0.0)
+    }
+
+    fn release(&mut self, _velocity: Velocity) {}
+
+    fn process(&mut self, _pitch: f32, output: [&mut [f32]; 2]) -> bool {
+        let [left, right] = output;
+        left.fill(0.0);
+        right.fill(0.0);
+        false
+    }
+}
+
+impl<A: Voice, B: Voice> Voice for Switch<A, B> {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.a.set_sample_rate(sample_rate);
+        self.b.set_sample_rate(sample_rate);
+        let samples = SWITCH_TIME * sample_rate as f32;
+        self.step = if samples > 1.0 { samples.recip() } else { 1.0 };
+    }
+
+    fn reset(&mut self) {
+        self.a.reset();
+        self.b.reset();
+    }
+
+    fn set_resources(&mut self, resources: &Resources) {
+        self.a.set_resources(resources);
+        self.b.set_resources(resources);
+    }
+
+    fn prepare(&mut self, max_block_size: usize) {
+        self.a.prepare(max_block_size);
+        self.b.prepare(max_block_size);
+    }
+
+    fn set_position(&mut self, position: u64) {
+        self.a.set_position(position);
+        self.b.set_position(position);
+    }
+
+    fn trigger(&mut self, note: Note, velocity: Velocity) {
+        self.a.trigger(note, velocity);
+        self.b.trigger(note, velocity);
+    }
+
+    fn retrigger(&mut self, note: Note, velocity: Velocity) {
+        self.a.retrigger(note, velocity);
+        self.b.retrigger(note, velocity);
+    }
+
+    fn glide(&mut self, note: Note, velocity: Velocity) {
+        self.a.glide(note, velocity);
+        self.b.glide(note, velocity);
+    }
+
+    fn set_pressure(&mut self, pressure: f32) {
+        self.a.set_pressure(pressure);
+        self.b.set_pressure(pressure);
+    }
+
+    fn set_timbre(&mut self, timbre: f32) {
+        self.a.set_timbre(timbre);
+        self.b.set_timbre(timbre);
+    }
+
+    fn nominal_level(&self) -> f32 {
+        if self.select.load(Ordering::Relaxed) {
+            self.b.nominal_level()
+        } else {
+            self.a.nominal_level()
+        }
+    }
+
+    fn current_level(&self) -> Option<f32> {
+        // Only the selected voice is heard, unless a crossfade is in progress
+        match self.mix {
+            0.0 => self.a.current_level(),
+            1.0 => self.b.current_level(),
+            mix => Some(self.a.current_level()? * (1.0 - mix) + self.b.current_level()? * mix),
+        }
+    }
+
+    fn release(&mut self, velocity: Velocity) {
+        self.a.release(velocity);
+        self.b.release(velocity);
+    }
+
+    fn process(&mut self, pitch: f32, output: [&mut [f32]; 2]) -> bool {
+        let [left, right] = output;
+        let target = if self.select.load(Ordering::Relaxed) { 1.0 } else { 0.0 };
+
+        // Only the selected voice is heard, unless a crossfade is in progress, but the hidden
+        // voice is still processed so that it stays in step with the selected one
+        if self.mix == target {
+            let len = left.len();
+            return if target == 0.0 {
+                let active = self.a.process(pitch, [left, right]);
+                discard_chunked(len, |buf| self.b.process(pitch, buf)) || active
+            } else {
+                let active = self.b.process(pitch, [left, right]);
+                discard_chunked(len, |buf| self.a.process(pitch, buf)) || active
+            };
+        }
+
+        let mut active = self.a.process(pitch, [left, right]);
+        let mut temp = [[0.0; CHUNK_SIZE]; 2];
+        for (left, right) in left.chunks_mut(CHUNK_SIZE).zip(right.chunks_mut(CHUNK_SIZE)) {
+            let len = left.len();
+            let [temp_left, temp_right] = &mut temp;
+            active |= self.b.process(pitch, [&mut temp_left[..len], &mut temp_right[..len]]);
+            for i in 0..len {
+                self.mix = if target > self.mix {
+                    (self.mix + self.step).min(target)
+                } else {
+                    (self.mix - self.step).max(target)
+                };
+                left[i] += self.mix * (temp_left[i] - left[i]);
+                right[i] += self.mix * (temp_right[i] - right[i]);
+            }
+        }
+        active
+    }
+}
+
+impl<V: Voice> Voice for Layers<V> {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.layers.iter_mut().for_each(|(voice, _)| voice.set_sample_rate(sample_rate));
+    }
+
+    fn reset(&mut self) {
+        self.layers.iter_mut().for_each(|(voice, _)| voice.reset());
+    }
+
+    fn set_resources(&mut self, resources: &Resources) {
+        self.layers.iter_mut().for_each(|(voice, _)| voice.set_resources(resources));
+    }
+
+    fn prepare(&mut self, max_block_size: usize) {
+        self.layers.iter_mut().for_each(|(voice, _)| voice.prepare(max_block_size));
+    }
+
+    fn set_position(&mut self, position: u64) {
+        self.layers.iter_mut().for_each(|(voice, _)| voice.set_position(position));
+    }
+
+    fn trigger(&mut self, note: Note, velocity: Velocity) {
+        for (voice, settings) in &mut self.layers {
+            voice.trigger(transpose(note, settings.transpose), velocity);
+        }
+    }
+
+    fn retrigger(&mut self, note: Note, velocity: Velocity) {
+        for (voice, settings) in &mut self.layers {
+            voice.retrigger(transpose(note, settings.transpose), velocity);
+        }
+    }
+
+    fn glide(&mut self, note: Note, velocity: Velocity) {
+        for (voice, settings) in &mut self.layers {
+            voice.glide(transpose(note, settings.transpose), velocity);
+        }
+    }
+
+    fn set_pressure(&mut self, pressure: f32) {
+        self.layers.iter_mut().for_each(|(voice, _)| voice.set_pressure(pressure));
+    }
+
+    fn set_timbre(&mut self, timbre: f32) {
+        self.layers.iter_mut().for_each(|(voice, _)| voice.set_timbre(timbre));
+    }
+
+    fn nominal_level(&self) -> f32 {
+        let levels = self.layers.iter().map(|(voice, settings)| {
+            voice.nominal_level() * settings.gain.abs()
+        });
+        levels.sum()
+    }
+
+    fn current_level(&self) -> Option<f32> {
+        let levels = self.layers.iter().map(|(voice, settings)| {
+            Some(voice.current_level()? * settings.gain.abs())
+        });
+        levels.sum()
+    }
+
+    fn release(&mut self, velocity: Velocity) {
+        self.layers.iter_mut().for_each(|(voice, _)| voice.release(velocity));
+    }
+
+    fn process(&mut self, pitch: f32, output: [&mut [f32]; 2]) -> bool {
+        let [left, right] = output;
+        left.fill(0.0);
+        right.fill(0.0);
+
+        let mut active = false;
+        for (voice, settings) in &mut self.layers {
+            let pitch = pitch * 2f32.powf(settings.transpose as f32 / 12.0);
+            let pan = settings.pan.clamp(-1.0, 1.0);
+            let gain = [
+                settings.gain * (1.0 - pan.max(0.0)),
+                settings.gain * (1.0 + pan.min(0.0)),
+            ];
+            active |= add_chunked([left, right], gain, |output| voice.process(pitch, output));
+        }
+        active
+    }
+}
+
+/// Processes a voice in chunks and adds its output to a buffer with the given gain on each
+/// channel, so that no buffer needs to be allocated.
+///
+/// # Return
+/// Returns `true` if the voice was active in any chunk.
+fn add_chunked(
+    output: [&mut [f32]; 2],
+    gain: [f32; 2],
+    mut process: impl FnMut([&mut [f32]; 2]) -> bool,
+) -> bool {
+    let [left, right] = output;
+    let mut active = false;
+    let mut temp = [[0.0; CHUNK_SIZE]; 2];
+    for (left, right) in left.chunks_mut(CHUNK_SIZE).zip(right.chunks_mut(CHUNK_SIZE)) {
+        let len = left.len();
+        let [temp_left, temp_right] = &mut temp;
+        active |= process([&mut temp_left[..len], &mut temp_right[..len]]);
+        left.iter_mut().zip(&temp_left[..len]).for_each(|(s, t)| *s += gain[0] * t);
+        right.iter_mut().zip(&temp_right[..len]).for_each(|(s, t)| *s += gain[1] * t);
+    }
+    active
+}
+
+/// Processes a voice in chunks for the given number of samples and discards its output.
+///
+/// # Return
+/// Returns `true` if the voice was active in any chunk.
+fn discard_chunked(len: usize, mut process: impl FnMut([&mut [f32]; 2]) -> bool) -> bool {
+    let mut active = false;
+    let mut temp = [[0.0; CHUNK_SIZE]; 2];
+    for start in (0..len).step_by(CHUNK_SIZE) {
+        let len = CHUNK_SIZE.min(len - start);
+        let [temp_left, temp_right] = &mut temp;
+        active |= process([&mut temp_left[..len], &mut temp_right[..len]]);
+    }
+    active
+}
+
+/// Transposes a note by the given number of semitones, keeping it within the MIDI range.
+fn transpose(note: Note, semitones: i8) -> Note {
+    (note as i32 + semitones as i32).clamp(0, 127) as Note
+}
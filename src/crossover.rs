@@ -0,0 +1,342 @@
+use crate::biquad::{Biquad, Response, BUTTERWORTH_Q};
+use crate::Effect;
+
+/// A fourth order Linkwitz-Riley crossover, which splits a signal into a low band and a high
+/// band that sum back to the original signal with a flat frequency response.
+///
+/// Each band is filtered by two cascaded Butterworth filters, so both bands are 6 dB down at
+/// the crossover frequency and in phase with each other. The sum of the bands is the input
+/// delayed by a second order allpass filter, which [Multiband] applies to the lower bands so
+/// that several crossovers can be combined.
+#[derive(Clone)]
+pub struct Crossover {
+    /// The crossover frequency in Hz.
+    freq: f32,
+    /// The sample rate in Hz.
+    sample_rate: u32,
+    /// The filters of the low band.
+    lowpass: [Biquad; 2],
+    /// The filters of the high band.
+    highpass: [Biquad; 2],
+}
+
+/// Splits a signal into several bands with [Crossover]s, processes each band with an effect of
+/// its own, and sums the bands, such as to compress or saturate the bass separately from the
+/// rest of the mix.
+///
+/// Each band is delayed by allpass filters matching the crossovers above it, so that the bands
+/// stay in phase and, with effects which leave them unchanged, sum back to a flat response.
+///
+/// # Parameters
+/// The parameters of the band effects, in order, so that the parameters of the first band
+/// are followed by those of the second band, and so on.
+pub struct Multiband<E> {
+    /// The crossovers between the bands, in ascending order of frequency.
+    crossovers: Vec<Crossover>,
+    /// The allpass filters which compensate each band for the crossovers above it.
+    compensation: Vec<Vec<Biquad>>,
+    /// The effect of each band, from lowest to highest.
+    bands: Vec<E>,
+    /// The left and right channels of each band except the highest, which is processed in
+    /// the output buffer.
+    buffers: Vec<f32>,
+    /// The number of samples processed at a time.
+    max_block_size: usize,
+    /// The sample rate in Hz.
+    sample_rate: u32,
+}
+
+impl Crossover {
+    /// Creates a crossover at the given frequency in Hz.
+    pub fn new(freq: f32) -> Self {
+        let mut out = Self {
+            freq,
+            sample_rate: 48000,
+            lowpass: Default::default(),
+            highpass: Default::default(),
+        };
+        out.update_filters();
+        out
+    }
+
+    /// Gets the crossover frequency in Hz.
+    pub fn frequency(&self) -> f32 {
+        self.freq
+    }
+
+    /// Sets the crossover frequency in Hz.
+    pub fn set_frequency(&mut self, freq: f32) {
+        self.freq = freq;
+        self.update_filters();
+    }
+
+    /// Sets the sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.update_filters();
+    }
+
+    /// Clears the state of the filters.
+    pub fn reset(&mut self) {
+        self.lowpass.iter_mut().for_each(Biquad::reset);
+        self.highpass.iter_mut().for_each(Biquad::reset);
+    }
+
+    /// Splits a block of stereo audio into its low and high bands.
+    ///
+    /// # Parameters
+    /// * `buffer` - The left and right channels of the signal, which are replaced with the
+    ///   high band.
+    /// * `low` - The left and right buffers which the low band is written to, which must have
+    ///   the same length as the signal.
+    pub fn split(&mut self, buffer: [&mut [f32]; 2], low: [&mut [f32]; 2]) {
+        let [left, right] = buffer;
+        let [low_left, low_right] = low;
+        low_left.copy_from_slice(left);
+        low_right.copy_from_slice(right);
+        for filter in &mut self.lowpass {
+            filter.process([&mut *low_left, &mut *low_right]);
+        }
+        for filter in &mut self.highpass {
+            filter.process([&mut *left, &mut *right]);
+        }
+    }
+
+    /// Updates the coefficients of the filters, keeping their state.
+    fn update_filters(&mut self) {
+        for filter in &mut self.lowpass {
+            filter.set(
+                Response::Lowpass,
+                self.freq,
+                BUTTERWORTH_Q,
+                self.sample_rate,
+            );
+        }
+        for filter in &mut self.highpass {
+            filter.set(
+                Response::Highpass,
+                self.freq,
+                BUTTERWORTH_Q,
+                self.sample_rate,
+            );
+        }
+    }
+}
+
+impl<E: Effect> Multiband<E> {
+    /// Creates a multiband effect.
+    ///
+    /// This allocates memory, so shouldn't be called from the audio thread.
+    ///
+    /// # Parameters
+    /// * `max_block_size` - The number of samples processed at a time. Larger blocks are split
+    ///   into blocks of this size.
+    /// * `freqs` - The crossover frequencies in Hz, in ascending order, which is one fewer than
+    ///   the number of bands.
+    /// * `band` - Creates the effect of each band, given its index from the lowest band.
+    pub fn new(max_block_size: usize, freqs: &[f32], band: impl FnMut(usize) -> E) -> Self {
+        assert!(
+            max_block_size > 0,
+            "Maximum block size must be at least one."
+        );
+        assert!(
+            freqs.windows(2).all(|w| w[0] < w[1]),
+            "Crossover frequencies must be in ascending order."
+        );
+        let crossovers: Vec<_> = freqs.iter().map(|&freq| Crossover::new(freq)).collect();
+        let mut out = Self {
+            compensation: vec![],
+            bands: (0..freqs.len() + 1).map(band).collect(),
+            buffers: vec![0.0; 2 * max_block_size * crossovers.len()],
+            crossovers,
+            max_block_size,
+            sample_rate: 0,
+        };
+        out.set_sample_rate(48000);
+        out
+    }
+
+    /// Gets the number of bands.
+    pub fn num_bands(&self) -> usize {
+        self.bands.len()
+    }
+
+    /// Gets the effect of a band, from the lowest band.
+    ///
+    /// # Panics
+    /// Panics if the band doesn't exist.
+    pub fn band_mut(&mut self, band: usize) -> &mut E {
+        &mut self.bands[band]
+    }
+
+    /// Sets the frequency in Hz of the crossover above a band, which must stay between the
+    /// frequencies of the crossovers either side of it.
+    ///
+    /// # Panics
+    /// Panics if the crossover doesn't exist.
+    pub fn set_frequency(&mut self, crossover: usize, freq: f32) {
+        self.crossovers[crossover].set_frequency(freq);
+        self.update_compensation();
+    }
+
+    /// Recreates the allpass filters which compensate the lower bands, keeping their state.
+    fn update_compensation(&mut self) {
+        let count = self.crossovers.len();
+        self.compensation.resize(count, vec![]);
+        for (band, filters) in self.compensation.iter_mut().enumerate() {
+            let above = &self.crossovers[band + 1..];
+            filters.resize(above.len(), Biquad::default());
+            for (filter, crossover) in filters.iter_mut().zip(above) {
+                filter.set(
+                    Response::Allpass,
+                    crossover.freq,
+                    BUTTERWORTH_Q,
+                    self.sample_rate,
+                );
+            }
+        }
+    }
+
+    /// Processes a block no larger than the maximum block size.
+    fn process_block(&mut self, buffer: [&mut [f32]; 2]) {
+        let [left, right] = buffer;
+        let len = left.len();
+
+        // Split off each band from the bottom, leaving the highest band in the output buffer
+        let mut buffers = self.buffers.chunks_exact_mut(2 * self.max_block_size);
+        for ((crossover, filters), band) in self
+            .crossovers
+            .iter_mut()
+            .zip(&mut self.compensation)
+            .zip(&mut self.bands)
+        {
+            let buffer = buffers.next().expect("a buffer for each crossover");
+            let (low_left, low_right) = buffer.split_at_mut(self.max_block_size);
+            let low = [&mut low_left[..len], &mut low_right[..len]];
+            let [low_left, low_right] = low;
+            crossover.split([&mut *left, &mut *right], [&mut *low_left, &mut *low_right]);
+            for filter in filters {
+                filter.process([&mut *low_left, &mut *low_right]);
+            }
+            band.process([low_left, low_right]);
+        }
+        let highest = self.bands.last_mut().expect("at least one band");
+        highest.process([&mut *left, &mut *right]);
+
+        // Sum the bands
+        for buffer in self.buffers.chunks_exact(2 * self.max_block_size) {
+            let (low_left, low_right) = buffer.split_at(self.max_block_size);
+            left.iter_mut().zip(low_left).for_each(|(out, s)| *out += s);
+            right
+                .iter_mut()
+                .zip(low_right)
+                .for_each(|(out, s)| *out += s);
+        }
+    }
+}
+
+impl<E: Effect> Effect for Multiband<E> {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        for crossover in &mut self.crossovers {
+            crossover.set_sample_rate(sample_rate);
+        }
+        self.update_compensation();
+        for band in &mut self.bands {
+            band.set_sample_rate(sample_rate);
+        }
+    }
+
+    fn prepare(&mut self, max_block_size: usize) {
+        for band in &mut self.bands {
+            band.prepare(max_block_size.min(self.max_block_size));
+        }
+    }
+
+    fn reset(&mut self) {
+        for crossover in &mut self.crossovers {
+            crossover.reset();
+        }
+        for filter in self.compensation.iter_mut().flatten() {
+            filter.reset();
+        }
+        for band in &mut self.bands {
+            band.reset();
+        }
+    }
+
+    fn process(&mut self, buffer: [&mut [f32]; 2]) {
+        let [left, right] = buffer;
+        assert_eq!(left.len(), right.len());
+        let max = self.max_block_size;
+        for (left, right) in left.chunks_mut(max).zip(right.chunks_mut(max)) {
+            self.process_block([left, right]);
+        }
+    }
+
+    fn num_params(&self) -> usize {
+        self.bands.iter().map(|band| band.num_params()).sum()
+    }
+
+    fn set_param(&mut self, mut index: usize, value: f32) {
+        for band in &mut self.bands {
+            let count = band.num_params();
+            if index < count {
+                band.set_param(index, value);
+                return;
+            }
+            index -= count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    /// An effect which scales its input.
+    struct Gain(f32);
+
+    impl Effect for Gain {
+        fn set_sample_rate(&mut self, _sample_rate: u32) {}
+
+        fn reset(&mut self) {}
+
+        fn process(&mut self, buffer: [&mut [f32]; 2]) {
+            for channel in buffer {
+                channel.iter_mut().for_each(|s| *s *= self.0);
+            }
+        }
+    }
+
+    /// Processes a sine wave through an effect, returning the ratio of the RMS level of the
+    /// output to that of the input, once the filters have settled.
+    fn response(effect: &mut impl Effect, freq: f32) -> f32 {
+        let tone: Vec<f32> = (0..24000)
+            .map(|n| (TAU * freq * n as f32 / 48000.0).sin())
+            .collect();
+        let (mut left, mut right) = (tone.clone(), tone.clone());
+        effect.process([&mut left, &mut right]);
+        let rms = |signal: &[f32]| {
+            (signal.iter().map(|s| s * s).sum::<f32>() / signal.len() as f32).sqrt()
+        };
+        rms(&left[12000..]) / rms(&tone[12000..])
+    }
+
+    #[test]
+    fn bands_sum_to_a_flat_response() {
+        let mut multiband = Multiband::new(256, &[200.0, 2000.0], |_| Gain(1.0));
+        for freq in [50.0, 200.0, 700.0, 2000.0, 8000.0] {
+            let gain = response(&mut multiband, freq);
+            assert!((gain - 1.0).abs() < 0.01, "{gain} at {freq} Hz");
+        }
+    }
+
+    #[test]
+    fn bands_are_processed_separately() {
+        let mut multiband = Multiband::new(256, &[1000.0], |band| Gain(band as f32));
+        assert!(response(&mut multiband, 100.0) < 0.05);
+        assert!((response(&mut multiband, 10000.0) - 1.0).abs() < 0.01);
+    }
+}
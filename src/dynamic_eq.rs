@@ -0,0 +1,257 @@
+use crate::biquad::{Biquad, Response};
+use crate::Effect;
+
+/// A band of dynamic equalisation, which cuts a range of frequencies only while they are loud,
+/// such as to tame harsh resonances or, as a de-esser, sibilance.
+///
+/// The level of the band is measured through a bandpass filter at its frequency, and once it
+/// rises above the threshold, the band is cut by a peaking or high shelf filter, as much as a
+/// compressor with the same threshold and ratio would reduce it, up to the range.
+///
+/// # Parameters
+/// 0. Frequency in Hz.
+/// 1. Threshold in decibels.
+/// 2. Ratio, of at least 1.
+/// 3. Range in decibels, which is the most the band is cut by.
+pub struct DynamicEq {
+    /// Measures the level of the band.
+    detector: Biquad,
+    /// Cuts the band.
+    filter: Biquad,
+    /// The shape of the cut.
+    shape: DynamicEqShape,
+    /// The centre or corner frequency of the band in Hz.
+    freq: f32,
+    /// The quality factor of the band.
+    q: f32,
+    /// The threshold in decibels.
+    threshold: f32,
+    /// The ratio.
+    ratio: f32,
+    /// The largest cut in decibels.
+    range: f32,
+    /// The attack time in seconds.
+    attack: f32,
+    /// The release time in seconds.
+    release: f32,
+    /// The one-pole filter coefficient of the envelope while it rises.
+    attack_coeff: f32,
+    /// The one-pole filter coefficient of the envelope while it falls.
+    release_coeff: f32,
+    /// The level of the band, as a linear amplitude.
+    envelope: f32,
+    /// The current cut in decibels.
+    cut: f32,
+    /// The sample rate in Hz.
+    sample_rate: u32,
+}
+
+/// The shape of the cut made by a [DynamicEq].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DynamicEqShape {
+    /// A bell centred on the frequency of the band.
+    Peak,
+    /// A shelf cutting everything above the frequency of the band.
+    HighShelf,
+}
+
+/// The number of samples between updates of the filter.
+const CONTROL_INTERVAL: usize = 16;
+
+impl DynamicEq {
+    /// Creates a band of dynamic equalisation with an attack of 1 ms, a release of 50 ms and
+    /// a range of 12 dB.
+    ///
+    /// # Parameters
+    /// * `shape` - The shape of the cut.
+    /// * `freq` - The centre or corner frequency in Hz.
+    /// * `q` - The quality factor, where higher values narrow the band.
+    /// * `threshold` - The level in decibels of the band above which it is cut.
+    /// * `ratio` - The ratio by which the level above the threshold is reduced.
+    pub fn new(shape: DynamicEqShape, freq: f32, q: f32, threshold: f32, ratio: f32) -> Self {
+        let mut out = Self {
+            detector: Biquad::default(),
+            filter: Biquad::default(),
+            shape,
+            freq,
+            q: q.max(0.1),
+            threshold,
+            ratio: ratio.max(1.0),
+            range: 12.0,
+            attack: 0.001,
+            release: 0.05,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            envelope: 0.0,
+            cut: 0.0,
+            sample_rate: 48000,
+        };
+        out.update_coeffs();
+        out
+    }
+
+    /// Creates a de-esser, which cuts everything above 6 kHz while sibilance is louder than
+    /// the threshold in decibels.
+    pub fn de_esser(threshold: f32) -> Self {
+        Self::new(DynamicEqShape::HighShelf, 6000.0, 1.0, threshold, 4.0)
+    }
+
+    /// Sets the centre or corner frequency of the band in Hz.
+    pub fn set_frequency(&mut self, freq: f32) {
+        self.freq = freq;
+        self.update_coeffs();
+    }
+
+    /// Sets the quality factor of the band.
+    pub fn set_q(&mut self, q: f32) {
+        self.q = q.max(0.1);
+        self.update_coeffs();
+    }
+
+    /// Sets the shape of the cut.
+    pub fn set_shape(&mut self, shape: DynamicEqShape) {
+        self.shape = shape;
+        self.update_filter();
+    }
+
+    /// Sets the level in decibels of the band above which it is cut.
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    /// Sets the ratio by which the level above the threshold is reduced, which is at least 1.
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio.max(1.0);
+    }
+
+    /// Sets the largest cut in decibels.
+    pub fn set_range(&mut self, range: f32) {
+        self.range = range.max(0.0);
+    }
+
+    /// Sets the attack and release times in seconds of the level detection.
+    pub fn set_times(&mut self, attack: f32, release: f32) {
+        self.attack = attack;
+        self.release = release;
+        self.update_coeffs();
+    }
+
+    /// Gets the current cut in decibels, for metering.
+    pub fn gain_reduction(&self) -> f32 {
+        self.cut
+    }
+
+    /// Updates the envelope and filter coefficients.
+    fn update_coeffs(&mut self) {
+        let coeff = |time: f32| {
+            let samples = time * self.sample_rate as f32;
+            if samples > 0.0 {
+                (-1.0 / samples).exp()
+            } else {
+                0.0
+            }
+        };
+        self.attack_coeff = coeff(self.attack);
+        self.release_coeff = coeff(self.release);
+        self.detector
+            .set(Response::Bandpass, self.freq, self.q, self.sample_rate);
+        self.update_filter();
+    }
+
+    /// Updates the filter which cuts the band from the current cut.
+    fn update_filter(&mut self) {
+        let response = match self.shape {
+            DynamicEqShape::Peak => Response::Peaking(-self.cut),
+            DynamicEqShape::HighShelf => Response::HighShelf(-self.cut),
+        };
+        self.filter
+            .set(response, self.freq, self.q, self.sample_rate);
+    }
+}
+
+impl Effect for DynamicEq {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.update_coeffs();
+    }
+
+    fn reset(&mut self) {
+        self.detector.reset();
+        self.filter.reset();
+        self.envelope = 0.0;
+        self.cut = 0.0;
+        self.update_filter();
+    }
+
+    fn process(&mut self, buffer: [&mut [f32]; 2]) {
+        let [left, right] = buffer;
+        for (left, right) in left
+            .chunks_mut(CONTROL_INTERVAL)
+            .zip(right.chunks_mut(CONTROL_INTERVAL))
+        {
+            // Follow the louder channel of the band, so that both channels are cut equally
+            for (&l, &r) in left.iter().zip(right.iter()) {
+                let l = self.detector.process_sample(0, l).abs();
+                let r = self.detector.process_sample(1, r).abs();
+                let x = l.max(r);
+                let coeff = if x > self.envelope {
+                    self.attack_coeff
+                } else {
+                    self.release_coeff
+                };
+                self.envelope = x + coeff * (self.envelope - x);
+            }
+
+            let level = 20.0 * self.envelope.max(1e-6).log10();
+            let over = (level - self.threshold).max(0.0);
+            let cut = (over * (1.0 - 1.0 / self.ratio)).min(self.range);
+            if cut != self.cut {
+                self.cut = cut;
+                self.update_filter();
+            }
+            self.filter.process([left, right]);
+        }
+    }
+
+    fn num_params(&self) -> usize {
+        4
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.set_frequency(value),
+            1 => self.set_threshold(value),
+            2 => self.set_ratio(value),
+            3 => self.set_range(value),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    /// Processes a sine wave, returning the change in its level in decibels once settled.
+    fn gain(effect: &mut DynamicEq, freq: f32, amplitude: f32) -> f32 {
+        let tone: Vec<f32> = (0..24000)
+            .map(|n| amplitude * (TAU * freq * n as f32 / 48000.0).sin())
+            .collect();
+        let (mut left, mut right) = (tone.clone(), tone.clone());
+        effect.process([&mut left, &mut right]);
+        let rms = |signal: &[f32]| signal.iter().map(|s| s * s).sum::<f32>().sqrt();
+        20.0 * (rms(&left[12000..]) / rms(&tone[12000..])).log10()
+    }
+
+    #[test]
+    fn de_esser_only_cuts_loud_sibilance() {
+        let mut de_esser = DynamicEq::de_esser(-30.0);
+        assert!(gain(&mut de_esser, 8000.0, 0.5) < -6.0);
+        de_esser.reset();
+        assert!(gain(&mut de_esser, 8000.0, 0.01).abs() < 0.5);
+        de_esser.reset();
+        assert!(gain(&mut de_esser, 200.0, 0.5).abs() < 0.5);
+        assert_eq!(de_esser.gain_reduction(), 0.0);
+    }
+}
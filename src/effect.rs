@@ -0,0 +1,797 @@
+use crate::smooth::Smoothed;
+
+/// An audio effect, such as a delay or reverb, which processes stereo audio in place and can be
+/// placed in an [EffectChain].
+pub trait Effect {
+    /// Sets the sample rate.
+    ///
+    /// This may be called multiple times.
+    fn set_sample_rate(&mut self, sample_rate: u32);
+
+    /// Prepares the effect for processing blocks of up to the given number of samples.
+    ///
+    /// This is called off the audio thread, after the sample rate is set, so effects can use it
+    /// to allocate their buffers. The default implementation does nothing.
+    fn prepare(&mut self, max_block_size: usize) {
+        let _ = max_block_size;
+    }
+
+    /// Clears the state of the effect, such as the contents of its delay lines.
+    fn reset(&mut self);
+
+    /// Processes a block of stereo audio in place.
+    ///
+    /// # Parameters
+    /// * `buffer` - The left and right audio buffers, which have the same length.
+    fn process(&mut self, buffer: [&mut [f32]; 2]);
+
+    /// Gets the number of parameters which can be set with [set_param](Self::set_param).
+    /// The default implementation returns 0.
+    fn num_params(&self) -> usize {
+        0
+    }
+
+    /// Sets a parameter of the effect, such as its delay time or mix, by its index.
+    ///
+    /// This allows parameters to be automated and modulated generically, such as by an
+    /// [EffectChain]. Each effect documents the meaning and range of its parameters.
+    /// This is called on the audio thread, so it should be cheap and must not allocate.
+    /// The default implementation ignores the parameter.
+    fn set_param(&mut self, index: usize, value: f32) {
+        let _ = (index, value);
+    }
+}
+
+/// An opaque identifier for an effect in an [EffectChain], which stays the same when the effect
+/// is moved within the chain.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct EffectId(usize);
+
+/// A parameter of an effect in an [EffectChain], which can be used as the destination of a
+/// [ModMatrix](crate::ModMatrix) when the `modulation` feature is enabled.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct EffectParam {
+    /// The identifier of the effect.
+    pub effect: EffectId,
+    /// The index of the parameter, as passed to [Effect::set_param].
+    pub param: usize,
+}
+
+/// A series of effects, each with a smoothed wet/dry mix and a bypass switch which crossfades
+/// rather than cutting in and out, which can be reordered while playing.
+///
+/// Effects are added and removed off the audio thread, as doing so allocates memory, but the
+/// mix, bypass and order of the effects can be changed on the audio thread at any time.
+/// The output of one effect can also be fed back into the input of an earlier one, such as to
+/// build a shimmer from a reverb and a pitch shifter.
+/// The chain is itself an [Effect], so chains can be nested.
+pub struct EffectChain {
+    /// The effects, in processing order.
+    slots: Vec<EffectSlot>,
+    /// A copy of the input of the effect being processed, for mixing with its output.
+    dry: Vec<f32>,
+    /// The number of samples processed at a time.
+    max_block_size: usize,
+    /// The sample rate in Hz.
+    sample_rate: u32,
+    /// The identifier given to the next effect which is added.
+    next_id: usize,
+    /// A move of an effect to a new index which is waiting for the output to fade out.
+    pending_move: Option<(EffectId, usize)>,
+    /// The gain of the output, which dips while the chain is reordered.
+    fade: f32,
+    /// The route which feeds the output of an effect back into the chain, if any.
+    feedback: Option<FeedbackRoute>,
+    /// The output of the effect which is fed back, from the previous block.
+    feedback_buffer: Vec<f32>,
+    /// The length of the previous block in the feedback buffer.
+    feedback_len: usize,
+}
+
+/// A route in an [EffectChain] which feeds the output of an effect back into the input of
+/// an earlier effect, one block later.
+struct FeedbackRoute {
+    /// The effect whose output is fed back.
+    from: EffectId,
+    /// The effect whose input the feedback is added to.
+    to: EffectId,
+    /// The gain of the feedback.
+    gain: Smoothed,
+}
+
+/// Mixes the parts of a multi-timbral synth, each through an effect chain of its own, and then
+/// through a master effect chain.
+///
+/// The parts are rendered to separate buses with
+/// [Synth::process_buses](crate::Synth::process_buses), with the `bus_routing` of the synth set
+/// to [BusRouting::Channel](crate::BusRouting::Channel) so that each MIDI channel is a part,
+/// and then mixed with [process](Self::process).
+pub struct PartMixer {
+    /// The effect chain of each part.
+    parts: Vec<EffectChain>,
+    /// The effect chain applied to the mix of the parts.
+    master: EffectChain,
+}
+
+/// An effect in an [EffectChain], along with its mix and bypass state.
+struct EffectSlot {
+    /// The identifier of the effect.
+    id: EffectId,
+    /// The effect.
+    effect: Box<dyn Effect + Send>,
+    /// The proportion of the output which is processed by the effect, from 0 (dry) to 1 (wet).
+    mix: Smoothed,
+    /// The proportion of the output passing through the effect rather than bypassing it,
+    /// which moves to 0 when the effect is bypassed.
+    enabled: Smoothed,
+    /// The state of each parameter of the effect.
+    params: Vec<ParamState>,
+}
+
+/// The state of a parameter of an effect in an [EffectChain].
+#[derive(Copy, Clone)]
+struct ParamState {
+    /// The value set with [EffectChain::set_param], which modulation is added to,
+    /// or `None` if it hasn't been set.
+    base: Option<f32>,
+    /// The value which was last passed to the effect.
+    current: f32,
+}
+
+/// The time constant in seconds of the smoothing of the wet/dry mix.
+const MIX_TIME: f32 = 0.01;
+
+/// The time constant in seconds of the crossfade when an effect is bypassed or enabled.
+const BYPASS_TIME: f32 = 0.005;
+
+/// The time in seconds over which the output fades out before the chain is reordered,
+/// and back in afterwards.
+const REORDER_TIME: f32 = 0.005;
+
+impl EffectChain {
+    /// Creates an empty chain which processes blocks of up to `max_block_size` samples at a time.
+    /// Larger blocks are split into blocks of this size.
+    pub fn new(max_block_size: usize) -> Self {
+        assert!(
+            max_block_size > 0,
+            "Maximum block size must be at least one."
+        );
+        Self {
+            slots: vec![],
+            dry: vec![0.0; 2 * max_block_size],
+            max_block_size,
+            sample_rate: 48000,
+            next_id: 0,
+            pending_move: None,
+            fade: 1.0,
+            feedback: None,
+            feedback_buffer: vec![0.0; 2 * max_block_size],
+            feedback_len: 0,
+        }
+    }
+
+    /// Adds an effect to the end of the chain, fully wet and enabled.
+    ///
+    /// This allocates memory, so shouldn't be called from the audio thread.
+    ///
+    /// # Return
+    /// Returns the identifier of the effect.
+    pub fn push(&mut self, mut effect: impl Effect + Send + 'static) -> EffectId {
+        effect.set_sample_rate(self.sample_rate);
+        effect.prepare(self.max_block_size);
+        let id = EffectId(self.next_id);
+        self.next_id += 1;
+        let param = ParamState {
+            base: None,
+            current: f32::NAN,
+        };
+        let mut slot = EffectSlot {
+            id,
+            params: vec![param; effect.num_params()],
+            effect: Box::new(effect),
+            mix: Smoothed::new(1.0),
+            enabled: Smoothed::new(1.0),
+        };
+        slot.mix.set_time(MIX_TIME, self.sample_rate);
+        slot.enabled.set_time(BYPASS_TIME, self.sample_rate);
+        self.slots.push(slot);
+        id
+    }
+
+    /// Removes an effect from the chain.
+    ///
+    /// # Return
+    /// Returns the effect, so that it can be dropped off the audio thread,
+    /// or `None` if the effect isn't in the chain.
+    pub fn remove(&mut self, id: EffectId) -> Option<Box<dyn Effect + Send>> {
+        let index = self.index(id)?;
+        if let Some(route) = &self.feedback {
+            if route.from == id || route.to == id {
+                self.clear_feedback();
+            }
+        }
+        Some(self.slots.remove(index).effect)
+    }
+
+    /// Gets the number of effects in the chain.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns `true` if there are no effects in the chain.
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Gets the identifiers of the effects, in processing order.
+    pub fn ids(&self) -> impl Iterator<Item = EffectId> + '_ {
+        self.slots.iter().map(|slot| slot.id)
+    }
+
+    /// Gets the position of an effect in the chain, if it is in the chain.
+    pub fn index(&self, id: EffectId) -> Option<usize> {
+        self.slots.iter().position(|slot| slot.id == id)
+    }
+
+    /// Gets an effect, for changing its parameters.
+    pub fn effect_mut(&mut self, id: EffectId) -> Option<&mut (dyn Effect + Send + 'static)> {
+        let index = self.index(id)?;
+        Some(self.slots[index].effect.as_mut())
+    }
+
+    /// Sets a parameter of an effect, which is also the base value that modulation applied with
+    /// [modulate](Self::modulate) is added to.
+    ///
+    /// # Parameters
+    /// * `param` - The effect and the index of its parameter.
+    /// * `value` - The value of the parameter.
+    pub fn set_param(&mut self, param: EffectParam, value: f32) {
+        let Some(index) = self.index(param.effect) else {
+            return;
+        };
+        let slot = &mut self.slots[index];
+        if let Some(state) = slot.params.get_mut(param.param) {
+            state.base = Some(value);
+            state.current = value;
+        }
+        slot.effect.set_param(param.param, value);
+    }
+
+    /// Applies the values of a modulation matrix at the end of its last block to the parameters
+    /// of the effects, which can be called before processing each block, after the matrix has
+    /// been processed.
+    ///
+    /// Only the parameters which have been given a base value with [set_param](Self::set_param)
+    /// are modulated, as the chain doesn't otherwise know the value to add the modulation to.
+    #[cfg(feature = "modulation")]
+    pub fn modulate(&mut self, matrix: &crate::ModMatrix<EffectParam>) {
+        self.apply_modulation(|param, base| matrix.value(param, base));
+    }
+
+    /// Processes a block of stereo audio through every effect in turn, while applying the
+    /// values of a modulation matrix to the parameters of the effects.
+    ///
+    /// The block is processed in intervals of [CONTROL_INTERVAL](crate::CONTROL_INTERVAL)
+    /// samples, and the parameters are set to the values of the matrix at the start of each
+    /// interval, so that fast modulation doesn't alias against the block rate. As with
+    /// [modulate](Self::modulate), only parameters with a base value are modulated.
+    ///
+    /// # Parameters
+    /// * `matrix` - The modulation matrix, which has been processed for this block.
+    /// * `buffer` - The left and right audio buffers, which must be no longer than the last
+    ///   block of the matrix.
+    #[cfg(feature = "modulation")]
+    pub fn process_modulated(
+        &mut self,
+        matrix: &crate::ModMatrix<EffectParam>,
+        buffer: [&mut [f32]; 2],
+    ) {
+        let [left, right] = buffer;
+        assert_eq!(left.len(), right.len());
+        let interval = crate::CONTROL_INTERVAL;
+        for (index, (left, right)) in left
+            .chunks_mut(interval)
+            .zip(right.chunks_mut(interval))
+            .enumerate()
+        {
+            let offset = index * interval;
+            self.apply_modulation(|param, base| matrix.value_at(param, base, offset));
+            self.process([left, right]);
+        }
+    }
+
+    /// Sets each parameter which has a base value to the value given by `value`, which is
+    /// passed the parameter and its base value.
+    #[cfg(feature = "modulation")]
+    fn apply_modulation(&mut self, mut value: impl FnMut(&EffectParam, f32) -> f32) {
+        for slot in &mut self.slots {
+            for (index, state) in slot.params.iter_mut().enumerate() {
+                let Some(base) = state.base else {
+                    continue;
+                };
+                let param = EffectParam {
+                    effect: slot.id,
+                    param: index,
+                };
+                let value = value(&param, base);
+                if value != state.current {
+                    state.current = value;
+                    slot.effect.set_param(index, value);
+                }
+            }
+        }
+    }
+
+    /// Sets the wet/dry mix of an effect, which is smoothed to avoid clicks.
+    ///
+    /// # Parameters
+    /// * `id` - The identifier of the effect.
+    /// * `mix` - The proportion of the output which is processed by the effect,
+    ///   from 0 (dry) to 1 (wet).
+    pub fn set_mix(&mut self, id: EffectId, mix: f32) {
+        if let Some(index) = self.index(id) {
+            self.slots[index].mix.set_target(mix.clamp(0.0, 1.0));
+        }
+    }
+
+    /// Gets the wet/dry mix of an effect, if it is in the chain.
+    pub fn mix(&self, id: EffectId) -> Option<f32> {
+        self.index(id).map(|index| self.slots[index].mix.target())
+    }
+
+    /// Bypasses or enables an effect, crossfading between its input and output.
+    ///
+    /// A bypassed effect isn't processed once the crossfade has finished, and is reset before
+    /// it is enabled again, so that it doesn't resume with a stale tail.
+    pub fn set_bypass(&mut self, id: EffectId, bypass: bool) {
+        if let Some(index) = self.index(id) {
+            let slot = &mut self.slots[index];
+            if !bypass && slot.enabled.is_settled() && slot.enabled.target() == 0.0 {
+                slot.effect.reset();
+            }
+            slot.enabled.set_target(if bypass { 0.0 } else { 1.0 });
+        }
+    }
+
+    /// Returns `true` if the effect is bypassed.
+    pub fn is_bypassed(&self, id: EffectId) -> bool {
+        self.index(id)
+            .is_some_and(|index| self.slots[index].enabled.target() == 0.0)
+    }
+
+    /// Moves an effect to a new position in the chain, without allocating memory.
+    ///
+    /// So that the change in the signal path doesn't click, the output of the chain fades out
+    /// over a few milliseconds, the effect is moved, and then the output fades back in.
+    /// The effects keep their state, so delay and reverb tails continue after the move.
+    ///
+    /// # Parameters
+    /// * `id` - The identifier of the effect.
+    /// * `index` - The new position of the effect, which is clamped to the end of the chain.
+    pub fn move_effect(&mut self, id: EffectId, index: usize) {
+        if self.index(id).is_some() {
+            self.pending_move = Some((id, index));
+        }
+    }
+
+    /// Feeds the output of an effect back into the input of an earlier effect in the chain,
+    /// replacing any existing feedback route.
+    ///
+    /// The feedback is delayed by one block, which is at most the maximum block size of the
+    /// chain, and changes of gain are smoothed. The feedback only decays if the gain of the loop,
+    /// including the gain of the effects within it, is less than 1.
+    ///
+    /// # Parameters
+    /// * `from` - The effect whose output, after its wet/dry mix, is fed back.
+    /// * `to` - The effect whose input the feedback is added to, which may be the same effect.
+    /// * `gain` - The gain of the feedback, which may be negative.
+    pub fn set_feedback(&mut self, from: EffectId, to: EffectId, gain: f32) {
+        match &mut self.feedback {
+            Some(route) if route.from == from && route.to == to => route.gain.set_target(gain),
+            _ => {
+                // Fade in the new route, rather than injecting a stale block
+                let mut route = FeedbackRoute {
+                    from,
+                    to,
+                    gain: Smoothed::new(0.0),
+                };
+                route.gain.set_time(MIX_TIME, self.sample_rate);
+                route.gain.set_target(gain);
+                self.feedback = Some(route);
+                self.feedback_len = 0;
+            }
+        }
+    }
+
+    /// Removes the feedback route, if any.
+    pub fn clear_feedback(&mut self) {
+        self.feedback = None;
+        self.feedback_len = 0;
+    }
+
+    /// Applies a pending move of an effect.
+    fn apply_move(&mut self) {
+        if let Some((id, to)) = self.pending_move.take() {
+            if let Some(from) = self.index(id) {
+                let slot = self.slots.remove(from);
+                self.slots.insert(to.min(self.slots.len()), slot);
+            }
+        }
+    }
+
+    /// Processes a block no larger than the maximum block size.
+    fn process_block(&mut self, buffer: [&mut [f32]; 2]) {
+        let [left, right] = buffer;
+        let len = left.len();
+        if self.pending_move.is_some() && self.fade == 0.0 {
+            self.apply_move();
+        }
+
+        let max = self.max_block_size;
+        for slot in &mut self.slots {
+            if let Some(route) = self.feedback.as_mut().filter(|route| route.to == slot.id) {
+                let (fb_left, fb_right) = self.feedback_buffer.split_at(max);
+                let fb_len = self.feedback_len.min(len);
+                for ((l, r), (fl, fr)) in left
+                    .iter_mut()
+                    .zip(right.iter_mut())
+                    .zip(fb_left[..fb_len].iter().zip(&fb_right[..fb_len]))
+                {
+                    let gain = route.gain.next();
+                    *l += gain * fl;
+                    *r += gain * fr;
+                }
+            }
+
+            // Skip effects which are fully bypassed
+            if !slot.enabled.is_settled() || slot.enabled.target() != 0.0 {
+                Self::process_slot(slot, &mut self.dry, [&mut *left, &mut *right]);
+            }
+
+            if self
+                .feedback
+                .as_ref()
+                .is_some_and(|route| route.from == slot.id)
+            {
+                let (fb_left, fb_right) = self.feedback_buffer.split_at_mut(max);
+                fb_left[..len].copy_from_slice(left);
+                fb_right[..len].copy_from_slice(right);
+                self.feedback_len = len;
+            }
+        }
+
+        // Fade out while a move is pending, and back in afterwards
+        let target = if self.pending_move.is_some() {
+            0.0
+        } else {
+            1.0
+        };
+        if self.fade != 1.0 || target != 1.0 {
+            let step = 1.0 / (REORDER_TIME * self.sample_rate as f32).max(1.0);
+            for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                self.fade = if target > self.fade {
+                    (self.fade + step).min(target)
+                } else {
+                    (self.fade - step).max(target)
+                };
+                *l *= self.fade;
+                *r *= self.fade;
+            }
+        }
+    }
+
+    /// Processes a block through an effect, mixing its output with its input.
+    fn process_slot(slot: &mut EffectSlot, dry: &mut [f32], buffer: [&mut [f32]; 2]) {
+        let [left, right] = buffer;
+        let len = left.len();
+        let (dry_left, dry_right) = dry[..2 * len].split_at_mut(len);
+        dry_left.copy_from_slice(left);
+        dry_right.copy_from_slice(right);
+        slot.effect.process([&mut *left, &mut *right]);
+
+        let wet = slot.mix.is_settled() && slot.mix.target() == 1.0;
+        if wet && slot.enabled.is_settled() {
+            return;
+        }
+        for ((l, r), (dl, dr)) in left
+            .iter_mut()
+            .zip(right.iter_mut())
+            .zip(dry_left.iter().zip(dry_right.iter()))
+        {
+            let mix = slot.mix.next() * slot.enabled.next();
+            *l = dl + mix * (*l - dl);
+            *r = dr + mix * (*r - dr);
+        }
+    }
+}
+
+impl PartMixer {
+    /// Creates a mixer with an empty effect chain for each part and for the master bus.
+    ///
+    /// # Parameters
+    /// * `parts` - The number of parts, such as 16 for one part per MIDI channel.
+    /// * `max_block_size` - The maximum block size of the effect chains.
+    pub fn new(parts: usize, max_block_size: usize) -> Self {
+        Self {
+            parts: (0..parts)
+                .map(|_| EffectChain::new(max_block_size))
+                .collect(),
+            master: EffectChain::new(max_block_size),
+        }
+    }
+
+    /// Gets the number of parts.
+    pub fn num_parts(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Gets the effect chain of a part.
+    ///
+    /// # Panics
+    /// Panics if the part doesn't exist.
+    pub fn part_mut(&mut self, part: usize) -> &mut EffectChain {
+        &mut self.parts[part]
+    }
+
+    /// Gets the effect chain which is applied to the mix of the parts.
+    pub fn master_mut(&mut self) -> &mut EffectChain {
+        &mut self.master
+    }
+
+    /// Sets the sample rate of every effect chain.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        for chain in &mut self.parts {
+            chain.set_sample_rate(sample_rate);
+        }
+        self.master.set_sample_rate(sample_rate);
+    }
+
+    /// Resets every effect chain.
+    pub fn reset(&mut self) {
+        for chain in &mut self.parts {
+            chain.reset();
+        }
+        self.master.reset();
+    }
+
+    /// Processes each part through its effect chain in place, and mixes them into the output
+    /// through the master effect chain.
+    ///
+    /// # Parameters
+    /// * `buses` - The left and right audio buffers of each part, no more than the number of
+    ///   parts, which must all have the same length as the output.
+    /// * `output` - The left and right audio buffers which the mix is written to.
+    pub fn process(&mut self, buses: &mut [[&mut [f32]; 2]], output: [&mut [f32]; 2]) {
+        let [left, right] = output;
+        assert!(
+            buses.len() <= self.parts.len(),
+            "Too many buses for the number of parts."
+        );
+        assert_eq!(left.len(), right.len());
+        left.fill(0.0);
+        right.fill(0.0);
+        for (chain, [bus_left, bus_right]) in self.parts.iter_mut().zip(buses.iter_mut()) {
+            assert!(bus_left.len() == left.len() && bus_right.len() == left.len());
+            chain.process([&mut **bus_left, &mut **bus_right]);
+            left.iter_mut()
+                .zip(bus_left.iter())
+                .for_each(|(out, s)| *out += s);
+            right
+                .iter_mut()
+                .zip(bus_right.iter())
+                .for_each(|(out, s)| *out += s);
+        }
+        self.master.process([left, right]);
+    }
+}
+
+impl Effect for EffectChain {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        for slot in &mut self.slots {
+            slot.effect.set_sample_rate(sample_rate);
+            slot.effect.prepare(self.max_block_size);
+            slot.mix.set_time(MIX_TIME, sample_rate);
+            slot.enabled.set_time(BYPASS_TIME, sample_rate);
+        }
+        if let Some(route) = &mut self.feedback {
+            route.gain.set_time(MIX_TIME, sample_rate);
+        }
+    }
+
+    fn reset(&mut self) {
+        for slot in &mut self.slots {
+            slot.effect.reset();
+        }
+        self.apply_move();
+        self.fade = 1.0;
+        self.feedback_len = 0;
+    }
+
+    /// Processes a block of stereo audio through every effect in turn.
+    ///
+    /// Blocks larger than the maximum block size are processed in several smaller blocks.
+    fn process(&mut self, buffer: [&mut [f32]; 2]) {
+        let [left, right] = buffer;
+        assert_eq!(left.len(), right.len());
+        let max = self.max_block_size;
+        for (left, right) in left.chunks_mut(max).zip(right.chunks_mut(max)) {
+            self.process_block([left, right]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An effect which scales its input and adds an offset.
+    struct Affine(f32, f32);
+
+    impl Effect for Affine {
+        fn set_sample_rate(&mut self, _sample_rate: u32) {}
+
+        fn reset(&mut self) {}
+
+        fn process(&mut self, buffer: [&mut [f32]; 2]) {
+            for channel in buffer {
+                channel.iter_mut().for_each(|s| *s = *s * self.0 + self.1);
+            }
+        }
+    }
+
+    /// Processes blocks of a constant signal through the chain, returning the left channel.
+    fn run(chain: &mut EffectChain, blocks: usize) -> Vec<f32> {
+        let mut out = vec![];
+        for _ in 0..blocks {
+            let (mut left, mut right) = ([1.0; 64], [1.0; 64]);
+            chain.process([&mut left, &mut right]);
+            out.extend_from_slice(&left);
+        }
+        out
+    }
+
+    #[test]
+    fn mix_and_bypass_crossfade() {
+        let mut chain = EffectChain::new(64);
+        let id = chain.push(Affine(3.0, 0.0));
+        assert_eq!(*run(&mut chain, 1).last().unwrap(), 3.0);
+
+        // Half wet, approached smoothly
+        chain.set_mix(id, 0.5);
+        let out = run(&mut chain, 100);
+        assert!(out.windows(2).all(|w| (w[1] - w[0]).abs() < 0.05));
+        assert_eq!(*out.last().unwrap(), 2.0);
+
+        chain.set_bypass(id, true);
+        assert!(chain.is_bypassed(id));
+        let out = run(&mut chain, 100);
+        assert!(out.windows(2).all(|w| (w[1] - w[0]).abs() < 0.1));
+        assert_eq!(*out.last().unwrap(), 1.0);
+    }
+
+    #[test]
+    fn reordering_fades_out_and_back_in() {
+        let mut chain = EffectChain::new(64);
+        let scale = chain.push(Affine(2.0, 0.0));
+        let offset = chain.push(Affine(1.0, 1.0));
+        assert_eq!(*run(&mut chain, 1).last().unwrap(), 3.0);
+
+        chain.move_effect(offset, 0);
+        let out = run(&mut chain, 20);
+        assert!(out.windows(2).all(|w| (w[1] - w[0]).abs() < 0.2));
+        assert_eq!(*out.last().unwrap(), 4.0);
+        assert_eq!(chain.ids().collect::<Vec<_>>(), [offset, scale]);
+    }
+
+    #[test]
+    fn feedback_is_delayed_by_one_block() {
+        let mut chain = EffectChain::new(64);
+        let first = chain.push(Affine(1.0, 0.0));
+        let last = chain.push(Affine(1.0, 0.0));
+        chain.set_feedback(last, first, 0.5);
+
+        // Let the gain of the new route settle
+        for _ in 0..100 {
+            chain.process([&mut [0.0; 64], &mut [0.0; 64]]);
+        }
+        let mut out = vec![];
+        for block in 0..3 {
+            let (mut left, mut right) = ([0.0; 64], [0.0; 64]);
+            if block == 0 {
+                left[10] = 1.0;
+            }
+            chain.process([&mut left, &mut right]);
+            out.push(left[10]);
+        }
+        assert_eq!(out, [1.0, 0.5, 0.25]);
+    }
+
+    #[test]
+    fn parts_are_processed_separately_then_mixed() {
+        let mut mixer = PartMixer::new(16, 64);
+        mixer.part_mut(0).push(Affine(2.0, 0.0));
+        mixer.part_mut(1).push(Affine(0.0, 1.0));
+        mixer.master_mut().push(Affine(0.5, 0.0));
+
+        let mut bus_a = ([1.0; 64], [1.0; 64]);
+        let mut bus_b = ([3.0; 64], [3.0; 64]);
+        let mut buses = [
+            [&mut bus_a.0[..], &mut bus_a.1[..]],
+            [&mut bus_b.0, &mut bus_b.1],
+        ];
+        let (mut left, mut right) = ([0.0; 64], [0.0; 64]);
+        mixer.process(&mut buses, [&mut left, &mut right]);
+        assert_eq!(left, [1.5; 64]);
+        assert_eq!(right, [1.5; 64]);
+    }
+
+    #[cfg(feature = "modulation")]
+    #[test]
+    fn parameters_are_modulated_around_their_base_value() {
+        use crate::{ModMatrix, ModRoute, ModSource};
+        use std::sync::{Arc, Mutex};
+
+        /// An effect which records the values of its parameter.
+        struct Recorder(Arc<Mutex<Vec<f32>>>);
+
+        impl Effect for Recorder {
+            fn set_sample_rate(&mut self, _sample_rate: u32) {}
+
+            fn reset(&mut self) {}
+
+            fn process(&mut self, _buffer: [&mut [f32]; 2]) {}
+
+            fn num_params(&self) -> usize {
+                1
+            }
+
+            fn set_param(&mut self, _index: usize, value: f32) {
+                self.0.lock().unwrap().push(value);
+            }
+        }
+
+        /// A source which counts the samples it has processed.
+        struct Counter(f32);
+
+        impl ModSource for Counter {
+            fn set_sample_rate(&mut self, _sample_rate: u32) {}
+
+            fn reset(&mut self) {}
+
+            fn process(&mut self, _input: [&[f32]; 2], output: &mut [f32]) {
+                for value in output {
+                    self.0 += 1.0;
+                    *value = self.0;
+                }
+            }
+        }
+
+        let values = Arc::new(Mutex::new(vec![]));
+        let mut chain = EffectChain::new(64);
+        let id = chain.push(Recorder(values.clone()));
+        let param = EffectParam {
+            effect: id,
+            param: 0,
+        };
+        let mut matrix = ModMatrix::new(64);
+        let source = matrix.add_source(Counter(0.0));
+        matrix.route(ModRoute {
+            source,
+            target: param,
+            amount: 0.5,
+        });
+
+        // Nothing is modulated until the base value is set
+        matrix.process(64, None);
+        chain.modulate(&matrix);
+        chain.set_param(param, 10.0);
+        matrix.process(64, None);
+        chain.modulate(&matrix);
+
+        // The parameter follows the source within the block
+        matrix.process(64, None);
+        chain.process_modulated(&matrix, [&mut [0.0; 64], &mut [0.0; 64]]);
+        assert_eq!(*values.lock().unwrap(), [10.0, 74.0, 74.5, 90.5]);
+    }
+}
@@ -1,46 +1,92 @@
+use crate::FadeCurve;
+use std::f32::consts::FRAC_PI_2;
+
 /// A small buffer used to gracefully fade out voices which have been voice-stolen.
-pub struct FadeBuffer<const N: usize> {
+pub struct FadeBuffer {
     /// Contains the faded out audio in stereo.
-    buffer: [[f32; N]; 2],
-    /// The next sample to read from the buffer, which is `N` at completion.
+    buffer: [Vec<f32>; 2],
+    /// Holds the remainder of the previous fade while a new voice is added.
+    scratch: [Vec<f32>; 2],
+    /// The number of samples over which a voice is faded out.
+    fade_len: usize,
+    /// The shape of the fade.
+    curve: FadeCurve,
+    /// The number of samples in the buffer which contain audio.
+    len: usize,
+    /// The next sample to read from the buffer, which is `len` at completion.
     index: usize,
 }
 
-impl<const N: usize> Default for FadeBuffer<N> {
+impl Default for FadeBuffer {
     fn default() -> Self {
         Self {
-            buffer: [[0.0; N]; 2],
-            index: N
+            buffer: [vec![], vec![]],
+            scratch: [vec![], vec![]],
+            fade_len: 0,
+            curve: FadeCurve::Linear,
+            len: 0,
+            index: 0,
         }
     }
 }
 
-impl<const N: usize> FadeBuffer<N> {
+impl FadeBuffer {
     /// Creates an empty [FadeBuffer].
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Allocates room for fades of up to the given number of samples,
+    /// discarding any fade in progress.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        for ch in 0..2 {
+            self.buffer[ch] = vec![0.0; capacity];
+            self.scratch[ch] = vec![0.0; capacity];
+        }
+        self.fade_len = self.fade_len.min(capacity);
+        self.len = 0;
+        self.index = 0;
+    }
+
+    /// Sets the length and shape of subsequent fades.
+    /// The length is limited to the capacity of the buffer.
+    pub fn set_fade(&mut self, len: usize, curve: FadeCurve) {
+        self.fade_len = len.min(self.buffer[0].len());
+        self.curve = curve;
+    }
+
+    /// Discards any fade in progress.
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.index = 0;
+    }
+
     /// Adds a voice to fade out to the internal buffer.
     pub fn add_voice(&mut self, f: impl FnOnce([&mut [f32]; 2])) {
-        // Retain the old buffer contents
-        let temp = self.buffer;
+        // Retain the remainder of the previous fade
+        let remaining = self.len - self.index;
+        for ch in 0..2 {
+            self.scratch[ch][..remaining].copy_from_slice(&self.buffer[ch][self.index..self.len]);
+        }
 
         // Process the voice into the internal buffer
+        let fade_len = self.fade_len;
         let [left, right] = &mut self.buffer;
-        f([left, right]);
+        f([&mut left[..fade_len], &mut right[..fade_len]]);
 
-        // Apply the fade and add the contents from the retained buffer
+        // Apply the fade and add the remainder of the previous fade
+        let len = fade_len.max(remaining);
         for ch in 0..2 {
-            for i in 0..N {
-                self.buffer[ch][i] *= 1.0 - (i as f32 / N as f32);
-            }
-            for i in 0..(N - self.index) {
-                self.buffer[ch][i] += temp[ch][self.index + i];
+            let buffer = &mut self.buffer[ch];
+            for (i, sample) in buffer[..fade_len].iter_mut().enumerate() {
+                *sample *= self.curve.gain(i as f32 / fade_len as f32);
             }
+            buffer[fade_len..len].fill(0.0);
+            add_buffers(&mut buffer[..remaining], &self.scratch[ch][..remaining]);
         }
 
         // Reset the read index
+        self.len = len;
         self.index = 0;
     }
 
@@ -49,11 +95,27 @@ impl<const N: usize> FadeBuffer<N> {
         let [left, right] = output;
         debug_assert!(left.len() == right.len());
 
-        let len = usize::min(left.len(), N - self.index);
-        for idx in 0..len {
-            left[idx] += self.buffer[0][self.index + idx];
-            right[idx] += self.buffer[1][self.index + idx];
-        }
+        let len = usize::min(left.len(), self.len - self.index);
+        let range = self.index..(self.index + len);
+        add_buffers(&mut left[..len], &self.buffer[0][range.clone()]);
+        add_buffers(&mut right[..len], &self.buffer[1][range]);
         self.index += len;
     }
-}
\ No newline at end of file
+}
+
+impl FadeCurve {
+    /// Gets the gain of the fade at the given position, from 0 (start) to 1 (end).
+    fn gain(&self, t: f32) -> f32 {
+        match self {
+            FadeCurve::Linear => 1.0 - t,
+            FadeCurve::EqualPower => (t * FRAC_PI_2).cos(),
+        }
+    }
+}
+
+/// Adds the samples of one buffer to another.
+fn add_buffers(dst: &mut [f32], src: &[f32]) {
+    for (dst, src) in dst.iter_mut().zip(src) {
+        *dst += src;
+    }
+}
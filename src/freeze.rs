@@ -0,0 +1,262 @@
+use crate::smooth::Smoothed;
+use crate::synth::{random, RNG_SEED};
+use crate::Effect;
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use std::f32::consts::TAU;
+use std::sync::Arc;
+
+/// A spectral freeze effect, which captures the spectrum of its input and sustains it
+/// indefinitely, such as to hold a chord as a pad while playing over it.
+///
+/// While frozen, the magnitudes of the captured spectrum are resynthesised with random phases
+/// in overlapping frames, which smears transients into a smooth drone. The frozen sound fades
+/// in and out when the freeze is engaged and released, and is mixed with the live input.
+///
+/// # Parameters
+/// 0. Frozen, where values of 0.5 and above engage the freeze.
+/// 1. Mix, from 0 (live input only) to 1 (frozen sound only).
+pub struct Freeze {
+    /// The forward transform of each frame.
+    fft: Arc<dyn RealToComplex<f32>>,
+    /// The inverse transform of each frame.
+    ifft: Arc<dyn ComplexToReal<f32>>,
+    /// The Hann window applied before analysis and after resynthesis.
+    window: Vec<f32>,
+    /// The most recent frame of the input of each channel, as a circular buffer.
+    input: [Vec<f32>; 2],
+    /// The resynthesised output of each channel, as a circular buffer which frames are
+    /// overlapped and added into.
+    output: [Vec<f32>; 2],
+    /// The magnitudes of the captured spectrum of each channel.
+    magnitudes: [Vec<f32>; 2],
+    /// The position in the circular buffers.
+    pos: usize,
+    /// The number of samples until the next frame.
+    countdown: usize,
+    /// Whether the freeze is engaged.
+    frozen: bool,
+    /// Whether a spectrum has been captured since the freeze was engaged.
+    captured: bool,
+    /// The level of the frozen sound, which fades in and out.
+    level: Smoothed,
+    /// The balance between the live input and the frozen sound.
+    mix: f32,
+    /// The time domain buffer of the transforms.
+    frame: Vec<f32>,
+    /// The frequency domain buffer of the transforms.
+    spectrum: Vec<Complex<f32>>,
+    /// Scratch space for the transforms.
+    scratch: Vec<Complex<f32>>,
+    /// The state of the random number generator for the phases.
+    rng: u32,
+    /// The sample rate in Hz.
+    sample_rate: u32,
+}
+
+/// The number of samples in each frame.
+const FRAME_SIZE: usize = 2048;
+
+/// The number of samples between the starts of consecutive frames.
+const HOP_SIZE: usize = FRAME_SIZE / 4;
+
+/// The time constant in seconds with which the frozen sound fades in and out.
+const FADE_TIME: f32 = 0.05;
+
+impl Freeze {
+    /// Creates a freeze effect which isn't frozen, with the given mix.
+    ///
+    /// This plans the Fourier transforms and allocates memory,
+    /// so shouldn't be called from the audio thread.
+    pub fn new(mix: f32) -> Self {
+        let mut planner = RealFftPlanner::new();
+        let fft = planner.plan_fft_forward(FRAME_SIZE);
+        let ifft = planner.plan_fft_inverse(FRAME_SIZE);
+        let scratch_len = fft.get_scratch_len().max(ifft.get_scratch_len());
+        let window = (0..FRAME_SIZE)
+            .map(|n| 0.5 - 0.5 * (TAU * n as f32 / FRAME_SIZE as f32).cos())
+            .collect();
+        let bins = FRAME_SIZE / 2 + 1;
+        let mut out = Self {
+            window,
+            input: [vec![0.0; FRAME_SIZE], vec![0.0; FRAME_SIZE]],
+            output: [vec![0.0; FRAME_SIZE], vec![0.0; FRAME_SIZE]],
+            magnitudes: [vec![0.0; bins], vec![0.0; bins]],
+            pos: 0,
+            countdown: HOP_SIZE,
+            frozen: false,
+            captured: false,
+            level: Smoothed::new(0.0),
+            mix: mix.clamp(0.0, 1.0),
+            frame: fft.make_input_vec(),
+            spectrum: fft.make_output_vec(),
+            scratch: vec![Complex::default(); scratch_len],
+            rng: RNG_SEED,
+            sample_rate: 48000,
+            fft,
+            ifft,
+        };
+        out.set_sample_rate(48000);
+        out
+    }
+
+    /// Engages or releases the freeze.
+    ///
+    /// When engaged, the spectrum of the input is captured at the start of the next frame,
+    /// within about 10 ms at 48 kHz, and the frozen sound fades in.
+    pub fn set_frozen(&mut self, frozen: bool) {
+        if frozen && !self.frozen {
+            self.captured = false;
+        }
+        self.frozen = frozen;
+    }
+
+    /// Returns `true` if the freeze is engaged.
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Sets the balance between the live input and the frozen sound, from 0 (live input only)
+    /// to 1 (frozen sound only).
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    /// Analyses or resynthesises a frame, at the end of each hop.
+    fn process_frame(&mut self) {
+        if self.frozen && !self.captured {
+            self.capture();
+            self.captured = true;
+            self.level.set_target(1.0);
+        }
+        if !self.frozen {
+            self.level.set_target(0.0);
+        }
+        if !self.captured || (self.level.is_settled() && self.level.target() == 0.0) {
+            return;
+        }
+
+        // Resynthesise the captured magnitudes with random phases
+        let scale = 1.0 / (FRAME_SIZE as f32 * 1.5);
+        for channel in 0..2 {
+            for (bin, &magnitude) in self.spectrum.iter_mut().zip(&self.magnitudes[channel]) {
+                *bin = Complex::from_polar(magnitude, TAU * random(&mut self.rng));
+            }
+            // The DC and Nyquist bins of a real signal have no imaginary part
+            let last = self.spectrum.len() - 1;
+            self.spectrum[0] = Complex::new(self.magnitudes[channel][0], 0.0);
+            self.spectrum[last] = Complex::new(self.magnitudes[channel][last], 0.0);
+            self.ifft
+                .process_with_scratch(&mut self.spectrum, &mut self.frame, &mut self.scratch)
+                .expect("buffers have the planned lengths");
+
+            let output = &mut self.output[channel];
+            for (n, (&sample, &window)) in self.frame.iter().zip(&self.window).enumerate() {
+                output[(self.pos + n) % FRAME_SIZE] += sample * window * scale;
+            }
+        }
+    }
+
+    /// Captures the magnitudes of the spectrum of the most recent frame of the input.
+    fn capture(&mut self) {
+        for channel in 0..2 {
+            let input = &self.input[channel];
+            for (n, (sample, &window)) in self.frame.iter_mut().zip(&self.window).enumerate() {
+                *sample = input[(self.pos + n) % FRAME_SIZE] * window;
+            }
+            self.fft
+                .process_with_scratch(&mut self.frame, &mut self.spectrum, &mut self.scratch)
+                .expect("buffers have the planned lengths");
+            for (magnitude, bin) in self.magnitudes[channel].iter_mut().zip(&self.spectrum) {
+                *magnitude = bin.norm();
+            }
+        }
+    }
+}
+
+impl Effect for Freeze {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.level.set_time(FADE_TIME, sample_rate);
+    }
+
+    fn reset(&mut self) {
+        for buffer in self.input.iter_mut().chain(&mut self.output) {
+            buffer.fill(0.0);
+        }
+        self.countdown = HOP_SIZE;
+        self.captured = false;
+        self.level = Smoothed::new(0.0);
+        self.level.set_time(FADE_TIME, self.sample_rate);
+    }
+
+    fn process(&mut self, buffer: [&mut [f32]; 2]) {
+        let [left, right] = buffer;
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            if self.countdown == 0 {
+                self.process_frame();
+                self.countdown = HOP_SIZE;
+            }
+            self.countdown -= 1;
+
+            let pos = self.pos;
+            self.input[0][pos] = *l;
+            self.input[1][pos] = *r;
+            let frozen = [self.output[0][pos], self.output[1][pos]];
+            self.output[0][pos] = 0.0;
+            self.output[1][pos] = 0.0;
+            self.pos = (pos + 1) % FRAME_SIZE;
+
+            let mix = self.mix * self.level.next();
+            *l += mix * (frozen[0] - *l);
+            *r += mix * (frozen[1] - *r);
+        }
+    }
+
+    fn num_params(&self) -> usize {
+        2
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.set_frozen(value >= 0.5),
+            1 => self.set_mix(value),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frozen_tone_is_sustained_after_the_input_stops() {
+        let mut freeze = Freeze::new(1.0);
+        let tone: Vec<f32> = (0..4800)
+            .map(|n| 0.5 * (TAU * 1000.0 * n as f32 / 48000.0).sin())
+            .collect();
+        let (mut left, mut right) = (tone.clone(), tone.clone());
+        freeze.process([&mut left, &mut right]);
+
+        freeze.set_frozen(true);
+        for _ in 0..10 {
+            let (mut left, mut right) = (vec![0.0; 4800], vec![0.0; 4800]);
+            freeze.process([&mut left, &mut right]);
+        }
+        let (mut left, mut right) = (vec![0.0; 4800], vec![0.0; 4800]);
+        freeze.process([&mut left, &mut right]);
+        let rms = (left.iter().map(|s| s * s).sum::<f32>() / left.len() as f32).sqrt();
+        assert!(rms > 0.1 && rms < 0.7, "rms {rms}");
+
+        // Releasing the freeze fades the sound out
+        freeze.set_frozen(false);
+        for _ in 0..10 {
+            let (mut left, mut right) = (vec![0.0; 4800], vec![0.0; 4800]);
+            freeze.process([&mut left, &mut right]);
+        }
+        let (mut left, mut right) = (vec![0.0; 4800], vec![0.0; 4800]);
+        freeze.process([&mut left, &mut right]);
+        assert!(left.iter().all(|s| s.abs() < 1e-3));
+    }
+}
@@ -0,0 +1,72 @@
+/// A noise gate applied to the output of the synth, which silences the output when its level
+/// falls below a threshold, such as during extremely quiet voice tails.
+#[derive(Copy, Clone, Debug)]
+pub struct Gate {
+    /// The level above which the gate opens, as a linear amplitude.
+    pub threshold: f32,
+    /// The distance below the threshold in decibels that the level must fall before
+    /// the gate closes, which prevents chattering around the threshold.
+    pub hysteresis: f32,
+    /// The time in seconds taken for the gate to fully open.
+    pub attack: f32,
+    /// The time in seconds the gate remains open after the level falls below the closing threshold.
+    pub hold: f32,
+    /// The time in seconds taken for the gate to fully close.
+    pub release: f32,
+}
+
+/// The state of a [Gate] between blocks.
+#[derive(Copy, Clone, Default)]
+pub(crate) struct GateState {
+    /// Whether the gate is open.
+    open: bool,
+    /// The number of samples remaining before the gate begins to close.
+    hold: usize,
+    /// The current gain applied by the gate.
+    gain: f32,
+}
+
+impl Gate {
+    /// Applies the gate to a block of stereo audio in place.
+    pub(crate) fn process(&self, state: &mut GateState, sample_rate: u32, output: [&mut [f32]; 2]) {
+        let [left, right] = output;
+        let sample_rate = sample_rate as f32;
+
+        let close_threshold = self.threshold * 10f32.powf(-self.hysteresis.max(0.0) / 20.0);
+        let hold = (self.hold * sample_rate) as usize;
+        let attack_step = step(self.attack, sample_rate);
+        let release_step = step(self.release, sample_rate);
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let level = l.abs().max(r.abs());
+
+            // Update whether the gate is open
+            if level >= self.threshold {
+                state.open = true;
+                state.hold = hold;
+            } else if level < close_threshold {
+                if state.hold > 0 {
+                    state.hold -= 1;
+                } else {
+                    state.open = false;
+                }
+            }
+
+            // Ramp the gain towards fully open or closed
+            state.gain = if state.open {
+                (state.gain + attack_step).min(1.0)
+            } else {
+                (state.gain - release_step).max(0.0)
+            };
+
+            *l *= state.gain;
+            *r *= state.gain;
+        }
+    }
+}
+
+/// Gets the change in gain per sample needed to ramp fully in the given time.
+fn step(time: f32, sample_rate: f32) -> f32 {
+    let samples = time * sample_rate;
+    if samples > 1.0 { samples.recip() } else { 1.0 }
+}
@@ -0,0 +1,511 @@
+use crate::synth::{random, RNG_SEED};
+use crate::{MidiEvent, Note};
+use std::ops::RangeInclusive;
+
+/// A clock which divides time into steps of equal length at a given tempo, for clocking
+/// generators such as [EuclideanGenerator] and [MelodyGenerator].
+///
+/// The clock counts in fractional samples, so steps which aren't a whole number of samples long
+/// don't drift over time.
+#[derive(Clone, Debug)]
+pub struct StepClock {
+    /// The tempo in beats per minute.
+    tempo: f32,
+    /// The number of steps in each beat.
+    steps_per_beat: u32,
+    /// The sample rate in Hz.
+    sample_rate: u32,
+    /// The length of each step in samples.
+    samples_per_step: f64,
+    /// The number of samples until the next step.
+    countdown: f64,
+    /// The index of the next step.
+    step: u64,
+}
+
+/// Plays a Euclidean rhythm, which spreads a number of pulses as evenly as possible over a
+/// number of steps, such as the tresillo of 3 pulses over 8 steps.
+///
+/// Each block produces the MIDI events of the pulses, which can be passed to
+/// [Synth::process_with_events](crate::Synth::process_with_events) to play them at the exact
+/// sample of each step.
+pub struct EuclideanGenerator {
+    /// The clock which sets the length of each step.
+    pub clock: StepClock,
+    /// The note which is played at each pulse.
+    pub note: Note,
+    /// The MIDI channel of the notes.
+    pub channel: u8,
+    /// The velocity of each pulse.
+    pub velocity: u8,
+    /// The velocity of the first pulse of the pattern, to accent the downbeat.
+    pub accent: u8,
+    /// The length of each note as a proportion of a step, from 0 to 1.
+    pub gate: f32,
+    /// The number of steps in the pattern.
+    steps: u32,
+    /// The number of pulses in the pattern.
+    pulses: u32,
+    /// The number of steps by which the pattern is rotated to the left.
+    rotation: u32,
+    /// The notes played by the generator.
+    notes: NoteEmitter,
+}
+
+/// Generates a melody by a random walk through the notes of a scale, constrained to a range
+/// of notes and a largest leap between consecutive notes, for music out of the box in ambient
+/// and generative applications.
+///
+/// Each step of the clock plays a note with the probability set by the density, so the rhythm
+/// falls on the grid of the clock. The same seed always produces the same melody.
+pub struct MelodyGenerator {
+    /// The clock which sets the rhythm grid.
+    pub clock: StepClock,
+    /// The MIDI channel of the notes.
+    pub channel: u8,
+    /// The range of velocities of the notes, which are chosen at random.
+    pub velocity: (u8, u8),
+    /// The length of each note as a proportion of a step, from 0 to 1.
+    pub gate: f32,
+    /// The probability that each step plays a note, from 0 to 1.
+    pub density: f32,
+    /// The largest leap between consecutive notes, in steps of the scale.
+    pub max_leap: u32,
+    /// The notes of the scale within the range, in ascending order.
+    allowed: Vec<Note>,
+    /// The index into the allowed notes of the last note.
+    position: usize,
+    /// The seed of the random number generator.
+    seed: u32,
+    /// The state of the random number generator.
+    rng: u32,
+    /// The notes played by the generator.
+    notes: NoteEmitter,
+}
+
+/// Produces the MIDI events of a generator which plays one note at a time.
+struct NoteEmitter {
+    /// The events produced by the last block, paired with their offsets.
+    events: Vec<(usize, MidiEvent)>,
+    /// The held note and the number of samples until it is released, if a note is held.
+    held: Option<(Note, u8, usize)>,
+}
+
+impl StepClock {
+    /// Creates a clock whose first step starts at the first sample.
+    ///
+    /// # Parameters
+    /// * `tempo` - The tempo in beats per minute.
+    /// * `steps_per_beat` - The number of steps in each beat, such as 4 for sixteenth notes.
+    pub fn new(tempo: f32, steps_per_beat: u32) -> Self {
+        let mut out = Self {
+            tempo,
+            steps_per_beat: steps_per_beat.max(1),
+            sample_rate: 48000,
+            samples_per_step: 0.0,
+            countdown: 0.0,
+            step: 0,
+        };
+        out.update();
+        out
+    }
+
+    /// Sets the tempo in beats per minute, which takes effect from the next step.
+    pub fn set_tempo(&mut self, tempo: f32) {
+        self.tempo = tempo;
+        self.update();
+    }
+
+    /// Sets the number of steps in each beat, which takes effect from the next step.
+    pub fn set_steps_per_beat(&mut self, steps_per_beat: u32) {
+        self.steps_per_beat = steps_per_beat.max(1);
+        self.update();
+    }
+
+    /// Sets the sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.update();
+    }
+
+    /// Gets the length of each step in samples.
+    pub fn samples_per_step(&self) -> f64 {
+        self.samples_per_step
+    }
+
+    /// Restarts the clock, so that the next sample starts the first step.
+    pub fn reset(&mut self) {
+        self.countdown = 0.0;
+        self.step = 0;
+    }
+
+    /// Advances the clock by a sample.
+    ///
+    /// # Return
+    /// Returns the index of the step, counting from zero, if a step starts at this sample.
+    pub fn tick(&mut self) -> Option<u64> {
+        let step = if self.countdown < 0.5 {
+            self.countdown += self.samples_per_step;
+            self.step += 1;
+            Some(self.step - 1)
+        } else {
+            None
+        };
+        self.countdown -= 1.0;
+        step
+    }
+
+    /// Updates the length of each step.
+    fn update(&mut self) {
+        let beats_per_second = self.tempo.max(1.0) as f64 / 60.0;
+        let samples_per_beat = self.sample_rate as f64 / beats_per_second;
+        self.samples_per_step = (samples_per_beat / self.steps_per_beat as f64).max(1.0);
+    }
+}
+
+impl EuclideanGenerator {
+    /// Creates a generator, for blocks of up to `max_block_size` samples.
+    ///
+    /// # Parameters
+    /// * `clock` - The clock which sets the length of each step.
+    /// * `steps` - The number of steps in the pattern.
+    /// * `pulses` - The number of pulses, which is at most the number of steps.
+    /// * `note` - The note which is played at each pulse.
+    pub fn new(
+        max_block_size: usize,
+        clock: StepClock,
+        steps: u32,
+        pulses: u32,
+        note: Note,
+    ) -> Self {
+        let mut out = Self {
+            clock,
+            note,
+            channel: 0,
+            velocity: 100,
+            accent: 100,
+            gate: 0.5,
+            steps: 1,
+            pulses: 0,
+            rotation: 0,
+            notes: NoteEmitter::new(max_block_size),
+        };
+        out.set_pattern(steps, pulses, 0);
+        out
+    }
+
+    /// Sets the pattern.
+    ///
+    /// # Parameters
+    /// * `steps` - The number of steps in the pattern, which is at least one.
+    /// * `pulses` - The number of pulses, which is clamped to the number of steps.
+    /// * `rotation` - The number of steps by which the pattern is rotated to the left.
+    pub fn set_pattern(&mut self, steps: u32, pulses: u32, rotation: u32) {
+        self.steps = steps.max(1);
+        self.pulses = pulses.min(self.steps);
+        self.rotation = rotation % self.steps;
+    }
+
+    /// Gets the number of steps, pulses and rotation of the pattern.
+    pub fn pattern(&self) -> (u32, u32, u32) {
+        (self.steps, self.pulses, self.rotation)
+    }
+
+    /// Returns `true` if the given step of the pattern is a pulse.
+    pub fn is_pulse(&self, step: u32) -> bool {
+        let step = (step % self.steps + self.rotation) % self.steps;
+        // The pulses fall where the running total of pulses per step reaches a whole number
+        (step * self.pulses) % self.steps < self.pulses
+    }
+
+    /// Sets the sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.clock.set_sample_rate(sample_rate);
+    }
+
+    /// Releases the held note, if any, and restarts the pattern from its first step.
+    ///
+    /// # Return
+    /// Returns the event which releases the held note, if a note was held.
+    pub fn reset(&mut self) -> Option<MidiEvent> {
+        self.clock.reset();
+        self.notes.release()
+    }
+
+    /// Generates a block of events, which must be no larger than the maximum block size.
+    ///
+    /// # Return
+    /// Returns the note on and note off events for the block, paired with their offsets
+    /// from the start of the block and sorted by offset.
+    pub fn process(&mut self, len: usize) -> &[(usize, MidiEvent)] {
+        self.notes.begin(len);
+        let gate = self.gate.clamp(0.0, 1.0) as f64 * self.clock.samples_per_step();
+        for offset in 0..len {
+            self.notes.tick(offset);
+            let Some(step) = self.clock.tick() else {
+                continue;
+            };
+            let step = (step % self.steps as u64) as u32;
+            if self.is_pulse(step) {
+                let first = (0..step).all(|s| !self.is_pulse(s));
+                let velocity = if first { self.accent } else { self.velocity };
+                let (channel, note) = (self.channel, self.note);
+                self.notes.play(offset, channel, note, velocity, gate);
+            }
+        }
+        &self.notes.events
+    }
+}
+
+impl MelodyGenerator {
+    /// Creates a generator which walks through the C major scale between C3 and C5, for blocks
+    /// of up to `max_block_size` samples.
+    ///
+    /// # Parameters
+    /// * `clock` - The clock which sets the rhythm grid.
+    /// * `seed` - The seed of the random number generator.
+    pub fn new(max_block_size: usize, clock: StepClock, seed: u32) -> Self {
+        let mut out = Self {
+            clock,
+            channel: 0,
+            velocity: (80, 110),
+            gate: 0.8,
+            density: 0.5,
+            max_leap: 2,
+            allowed: vec![],
+            position: 0,
+            seed,
+            rng: 0,
+            notes: NoteEmitter::new(max_block_size),
+        };
+        out.set_scale(48, &[0, 2, 4, 5, 7, 9, 11], 48..=72);
+        out.reset();
+        out
+    }
+
+    /// Sets the scale and range of the melody, which starts again from the middle of the range.
+    ///
+    /// This allocates memory, so shouldn't be called from the audio thread.
+    ///
+    /// # Parameters
+    /// * `root` - The root note of the scale.
+    /// * `degrees` - The degrees of the scale in semitones above the root, such as
+    ///   `[0, 2, 4, 7, 9]` for a major pentatonic scale.
+    /// * `range` - The lowest and highest notes of the melody.
+    ///
+    /// # Panics
+    /// Panics if no notes of the scale are within the range.
+    pub fn set_scale(&mut self, root: Note, degrees: &[u8], range: RangeInclusive<Note>) {
+        self.allowed = range
+            .filter(|&note| {
+                let interval = (note as i32 - root as i32).rem_euclid(12) as u8;
+                degrees.iter().any(|&degree| degree % 12 == interval)
+            })
+            .collect();
+        assert!(
+            !self.allowed.is_empty(),
+            "No notes of the scale are within the range."
+        );
+        self.position = self.allowed.len() / 2;
+    }
+
+    /// Gets the notes of the scale within the range, in ascending order.
+    pub fn notes(&self) -> &[Note] {
+        &self.allowed
+    }
+
+    /// Sets the seed of the random number generator, and restarts the melody.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+        self.reset();
+    }
+
+    /// Sets the sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.clock.set_sample_rate(sample_rate);
+    }
+
+    /// Releases the held note, if any, and restarts the melody from the middle of the range,
+    /// so that it repeats exactly.
+    ///
+    /// # Return
+    /// Returns the event which releases the held note, if a note was held.
+    pub fn reset(&mut self) -> Option<MidiEvent> {
+        self.rng = if self.seed == 0 { RNG_SEED } else { self.seed };
+        self.position = self.allowed.len() / 2;
+        self.clock.reset();
+        self.notes.release()
+    }
+
+    /// Generates a block of events, which must be no larger than the maximum block size.
+    ///
+    /// # Return
+    /// Returns the note on and note off events for the block, paired with their offsets
+    /// from the start of the block and sorted by offset.
+    pub fn process(&mut self, len: usize) -> &[(usize, MidiEvent)] {
+        self.notes.begin(len);
+        let gate = self.gate.clamp(0.0, 1.0) as f64 * self.clock.samples_per_step();
+        for offset in 0..len {
+            self.notes.tick(offset);
+            if self.clock.tick().is_none() || self.random() >= self.density {
+                continue;
+            }
+
+            // Leap by up to the largest leap in either direction, reflecting off the ends
+            let leap = self.max_leap as i64;
+            let step = (self.random() * (2 * leap + 1) as f32) as i64 - leap;
+            let last = self.allowed.len() as i64 - 1;
+            let mut position = self.position as i64 + step;
+            if position < 0 {
+                position = -position;
+            } else if position > last {
+                position = 2 * last - position;
+            }
+            self.position = position.clamp(0, last) as usize;
+
+            let (min, max) = self.velocity;
+            let velocity = min as f32 + self.random() * (max as f32 - min as f32);
+            let note = self.allowed[self.position];
+            self.notes
+                .play(offset, self.channel, note, velocity.round() as u8, gate);
+        }
+        &self.notes.events
+    }
+
+    /// Generates a pseudo-random number between 0 and 1.
+    fn random(&mut self) -> f32 {
+        random(&mut self.rng)
+    }
+}
+
+impl NoteEmitter {
+    /// Creates an emitter for blocks of up to `max_block_size` samples.
+    fn new(max_block_size: usize) -> Self {
+        Self {
+            // Each sample releases and plays a note at most
+            events: Vec::with_capacity(2 * max_block_size),
+            held: None,
+        }
+    }
+
+    /// Starts a block.
+    fn begin(&mut self, len: usize) {
+        debug_assert!(len <= self.events.capacity() / 2);
+        self.events.clear();
+    }
+
+    /// Advances the held note by a sample, releasing it once its gate has passed.
+    fn tick(&mut self, offset: usize) {
+        if let Some((_, _, remaining)) = &mut self.held {
+            if *remaining == 0 {
+                let event = self.release().expect("a note is held");
+                self.events.push((offset, event));
+            } else {
+                *remaining -= 1;
+            }
+        }
+    }
+
+    /// Plays a note, releasing the held note first.
+    fn play(&mut self, offset: usize, channel: u8, note: Note, velocity: u8, gate: f64) {
+        if let Some(event) = self.release() {
+            self.events.push((offset, event));
+        }
+        let velocity = velocity.clamp(1, 127);
+        self.events.push((
+            offset,
+            MidiEvent::NoteOn {
+                channel,
+                note,
+                velocity,
+            },
+        ));
+        // The note is released after the given number of samples, and at least one
+        let gate = (gate.round() as usize).max(1);
+        self.held = Some((note, channel, gate - 1));
+    }
+
+    /// Releases the held note, if any, returning its note off event.
+    fn release(&mut self) -> Option<MidiEvent> {
+        let (note, channel, _) = self.held.take()?;
+        Some(MidiEvent::NoteOff {
+            channel,
+            note,
+            velocity: 64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn euclidean_pattern_plays_on_its_pulses() {
+        // At 120 BPM and 48 kHz, each sixteenth note is 6000 samples long
+        let clock = StepClock::new(120.0, 4);
+        let mut generator = EuclideanGenerator::new(48000, clock, 8, 3, 36);
+        generator.accent = 120;
+        let pattern: String = (0..8)
+            .map(|s| if generator.is_pulse(s) { 'x' } else { '.' })
+            .collect();
+        assert_eq!(pattern, "x..x..x.");
+
+        let events = generator.process(48000).to_vec();
+        let note_ons: Vec<_> = events
+            .iter()
+            .filter_map(|&(offset, event)| match event {
+                MidiEvent::NoteOn { velocity, .. } => Some((offset, velocity)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(note_ons, [(0, 120), (18000, 100), (36000, 100)]);
+        let off = MidiEvent::NoteOff {
+            channel: 0,
+            note: 36,
+            velocity: 64,
+        };
+        assert!(events.contains(&(3000, off)));
+
+        generator.set_pattern(8, 3, 1);
+        let pattern: String = (0..8)
+            .map(|s| if generator.is_pulse(s) { 'x' } else { '.' })
+            .collect();
+        assert_eq!(pattern, "..x..x.x");
+    }
+
+    #[test]
+    fn melody_stays_in_scale_and_range_and_repeats_with_its_seed() {
+        let clock = StepClock::new(120.0, 4);
+        let mut generator = MelodyGenerator::new(4800, clock, 7);
+        generator.set_scale(60, &[0, 2, 4, 7, 9], 60..=84);
+        generator.density = 1.0;
+        generator.max_leap = 2;
+
+        let mut melody = vec![];
+        for _ in 0..100 {
+            for &(_, event) in generator.process(4800) {
+                if let MidiEvent::NoteOn { note, .. } = event {
+                    melody.push(note);
+                }
+            }
+        }
+        assert_eq!(melody.len(), 80);
+        let scale = generator.notes().to_vec();
+        assert!(melody.iter().all(|note| scale.contains(note)));
+        let index = |note| scale.iter().position(|n| n == note).unwrap() as i32;
+        assert!(melody
+            .windows(2)
+            .all(|w| (index(&w[0]) - index(&w[1])).abs() <= 2));
+
+        generator.reset();
+        let first = generator
+            .process(4800)
+            .iter()
+            .find_map(|&(_, event)| match event {
+                MidiEvent::NoteOn { note, .. } => Some(note),
+                _ => None,
+            });
+        assert_eq!(first, Some(melody[0]));
+    }
+}
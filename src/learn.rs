@@ -0,0 +1,152 @@
+use crate::{Channel, MidiEvent, Normalized};
+
+/// Routes MIDI controllers to parameters, learning each mapping from the next incoming controller.
+///
+/// The parameter type `P` is chosen by the application, and is typically an enum or an index
+/// identifying a parameter of a voice or effect.
+pub struct MidiLearn<P> {
+    /// The parameter waiting to be mapped to the next incoming controller, if any.
+    armed: Option<P>,
+    /// The learned mappings.
+    mappings: Vec<Mapping<P>>,
+}
+
+/// A mapping from a MIDI controller to a parameter.
+#[derive(Clone, Debug)]
+pub struct Mapping<P> {
+    /// The MIDI channel.
+    pub channel: Channel,
+    /// The controller number, between 0 and 127.
+    pub controller: u8,
+    /// The parameter controlled by the mapping.
+    pub target: P,
+    /// The parameter value when the controller is at 0.
+    pub min: f32,
+    /// The parameter value when the controller is at 127.
+    pub max: f32,
+    /// The curve applied to the controller value before scaling it to the parameter range.
+    pub curve: Curve,
+}
+
+/// The response curve of a controller mapping.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Curve {
+    /// The parameter changes linearly with the controller.
+    Linear,
+    /// The normalised controller value is raised to the given power,
+    /// so exponents above 1 give finer control at the bottom of the range.
+    Power(f32),
+}
+
+impl<P> Default for MidiLearn<P> {
+    fn default() -> Self {
+        Self {
+            armed: None,
+            mappings: vec![],
+        }
+    }
+}
+
+impl<P: Clone + PartialEq> MidiLearn<P> {
+    /// Creates a [MidiLearn] with no mappings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Arms the given parameter, so that the next incoming controller is mapped to it.
+    pub fn arm(&mut self, target: P) {
+        self.armed = Some(target);
+    }
+
+    /// Cancels learning, if a parameter is armed.
+    pub fn disarm(&mut self) {
+        self.armed = None;
+    }
+
+    /// Gets the parameter waiting to be mapped, if any.
+    pub fn armed(&self) -> Option<&P> {
+        self.armed.as_ref()
+    }
+
+    /// Gets the learned mappings.
+    pub fn mappings(&self) -> &[Mapping<P>] {
+        &self.mappings
+    }
+
+    /// Gets the mapping for the given parameter, if it has been learned.
+    pub fn mapping_mut(&mut self, target: &P) -> Option<&mut Mapping<P>> {
+        self.mappings.iter_mut().find(|m| &m.target == target)
+    }
+
+    /// Adds a mapping, replacing any existing mapping for the same parameter or controller.
+    pub fn map(&mut self, mapping: Mapping<P>) {
+        self.mappings.retain(|m| {
+            m.target != mapping.target
+                && (m.channel, m.controller) != (mapping.channel, mapping.controller)
+        });
+        self.mappings.push(mapping);
+    }
+
+    /// Removes the mapping for the given parameter, if any.
+    pub fn unmap(&mut self, target: &P) {
+        self.mappings.retain(|m| &m.target != target);
+    }
+
+    /// Processes a MIDI event, completing a pending learn and invoking `f` with the target
+    /// and scaled value of every mapping matching the event.
+    ///
+    /// Learning a new mapping allocates memory, so parameters should be armed from a
+    /// thread which is not real-time sensitive if this is called from the audio thread.
+    ///
+    /// # Return
+    /// Returns `true` if the event was consumed by at least one mapping.
+    pub fn midi_event(&mut self, event: MidiEvent, mut f: impl FnMut(&P, f32)) -> bool {
+        let (channel, controller, value) = match event {
+            MidiEvent::ControlChange { channel, controller, value } => {
+                (Channel::new(channel), controller, Normalized::from_midi(value))
+            }
+            MidiEvent::ControlChange14 { channel, controller, value } => {
+                let value = Normalized::new(value.min(16383) as f32 / 16383.0);
+                (Channel::new(channel), controller, value)
+            }
+            _ => return false,
+        };
+
+        if let Some(target) = self.armed.take() {
+            self.map(Mapping {
+                channel,
+                controller,
+                target,
+                min: 0.0,
+                max: 1.0,
+                curve: Curve::Linear,
+            });
+        }
+
+        let mut consumed = false;
+        for mapping in &self.mappings {
+            if mapping.channel == channel && mapping.controller == controller {
+                f(&mapping.target, mapping.scale_normalized(value));
+                consumed = true;
+            }
+        }
+        consumed
+    }
+}
+
+impl<P> Mapping<P> {
+    /// Scales a 7-bit controller value into the parameter range.
+    pub fn scale(&self, value: u8) -> f32 {
+        self.scale_normalized(Normalized::from_midi(value))
+    }
+
+    /// Scales a normalized controller value into the parameter range.
+    pub fn scale_normalized(&self, x: Normalized) -> f32 {
+        let x = x.get();
+        let x = match self.curve {
+            Curve::Linear => x,
+            Curve::Power(exponent) => x.powf(exponent),
+        };
+        self.min + x * (self.max - self.min)
+    }
+}
@@ -1,13 +1,115 @@
+#[cfg(feature = "analysis")]
+pub use chord::*;
+pub use combinators::*;
+#[cfg(feature = "effects")]
+pub use crossover::*;
+#[cfg(feature = "effects")]
+pub use dynamic_eq::*;
+#[cfg(feature = "effects")]
+pub use effect::*;
+#[cfg(feature = "effects")]
+pub use freeze::*;
+pub use gate::*;
+#[cfg(feature = "generators")]
+pub use generator::*;
+#[cfg(feature = "routing")]
+pub use learn::*;
+pub use limiter::*;
+#[cfg(feature = "loader")]
+pub use loader::*;
+pub use logging::*;
+#[cfg(feature = "routing")]
+pub use matrix::*;
 pub use midi::*;
+#[cfg(feature = "modulation")]
+pub use modulation::*;
+#[cfg(feature = "modulation")]
+pub use onset::*;
+#[cfg(feature = "effects")]
+pub use pitch_shift::*;
+#[cfg(feature = "presets")]
+pub use preset::*;
+pub use queue::*;
+#[cfg(feature = "record")]
+pub use record::*;
+pub use resources::*;
+#[cfg(feature = "effects")]
+pub use rotary::*;
+pub use saturation::*;
+#[cfg(feature = "score")]
+pub use score::*;
+#[cfg(feature = "effects")]
+pub use shimmer::*;
+#[cfg(feature = "streaming")]
+pub use stream::*;
 pub use synth::*;
+pub use trace::*;
+#[cfg(feature = "analysis")]
+pub use tuner::*;
 pub use tuning::*;
+pub use units::*;
 pub use voice::*;
+#[cfg(feature = "routing")]
+pub use zone::*;
 
+#[cfg(feature = "effects")]
+mod biquad;
+#[cfg(feature = "analysis")]
+mod chord;
+mod combinators;
+#[cfg(feature = "effects")]
+mod crossover;
+#[cfg(feature = "effects")]
+mod dynamic_eq;
+#[cfg(feature = "effects")]
+mod effect;
 mod fade;
+#[cfg(feature = "effects")]
+mod freeze;
+mod gate;
+#[cfg(feature = "generators")]
+mod generator;
+#[cfg(feature = "routing")]
+mod learn;
+mod limiter;
+#[cfg(feature = "loader")]
+mod loader;
+mod logging;
+#[cfg(feature = "routing")]
+mod matrix;
 mod midi;
+#[cfg(feature = "modulation")]
+mod modulation;
+#[cfg(feature = "modulation")]
+mod onset;
+#[cfg(feature = "effects")]
+mod pitch_shift;
+#[cfg(feature = "presets")]
+mod preset;
+mod queue;
+#[cfg(feature = "record")]
+mod record;
+mod resources;
+#[cfg(feature = "effects")]
+mod rotary;
+mod saturation;
+#[cfg(feature = "score")]
+mod score;
+#[cfg(feature = "effects")]
+mod shimmer;
+mod smooth;
+#[cfg(feature = "streaming")]
+mod stream;
 mod synth;
+mod trace;
+#[cfg(feature = "analysis")]
+mod tuner;
 mod tuning;
+mod units;
 mod voice;
+#[cfg(feature = "routing")]
+mod zone;
+#[cfg(feature = "blep")]
 pub mod blep;
 
 /// A MIDI note between 0 and 127.
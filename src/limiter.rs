@@ -0,0 +1,341 @@
+use std::collections::VecDeque;
+
+/// The final stage applied to the output of the synth, which keeps it within ±1
+/// when many voices are mixed together.
+#[derive(Copy, Clone, Debug)]
+pub enum Limiter {
+    /// The output is not limited.
+    Off,
+    /// The output is soft-clipped, smoothly approaching ±1.
+    SoftClip,
+    /// The gain is instantly reduced to keep peaks within ±1, and then recovers.
+    Peak {
+        /// The time in seconds for the gain to recover by a factor of e.
+        release: f32,
+    },
+    /// The peaks between samples are found by oversampling the output four times, and the gain
+    /// is reduced smoothly ahead of each peak, so that the output stays within ±1 even once
+    /// converted to analog, without the distortion of an instant change in gain.
+    ///
+    /// The output is delayed by the lookahead and the oversampling filter, which should be
+    /// reported to the host as latency, as given by [Synth::latency](crate::Synth::latency).
+    TruePeak {
+        /// The time in seconds for the gain to recover by a factor of e.
+        release: f32,
+        /// The time in seconds over which the gain is reduced ahead of a peak,
+        /// which is limited to 10 milliseconds.
+        lookahead: f32,
+    },
+}
+
+/// The state of a [Limiter] between blocks.
+#[derive(Clone)]
+pub(crate) struct LimiterState {
+    /// The current gain applied by a peak limiter.
+    gain: f32,
+    /// The interpolation filter for each point between two samples, excluding the samples
+    /// themselves, used by a true-peak limiter to find the peaks between samples.
+    filters: [[f32; TAPS]; OVERSAMPLE - 1],
+    /// The recent input frames, used both as the history of the interpolation filter
+    /// and as the delay line for the lookahead.
+    delay: Vec<[f32; 2]>,
+    /// The index in `delay` which the next input frame is written to.
+    pos: usize,
+    /// The lookahead in samples which the state was last prepared for,
+    /// or zero if it needs preparing before a true-peak limiter is applied.
+    lookahead: usize,
+    /// The longest lookahead in samples which the buffers have room for.
+    max_lookahead: usize,
+    /// The number of gains which have been added to `minimum`.
+    count: usize,
+    /// The gains required in the lookahead window which could still become its minimum,
+    /// along with the value of `count` when they were added, in increasing order of both.
+    minimum: VecDeque<(usize, f32)>,
+    /// The recent minimum gains, which are averaged to smooth the gain reduction.
+    average: Vec<f32>,
+    /// The index in `average` of the oldest minimum gain.
+    average_pos: usize,
+    /// The sum of the minimum gains in `average`.
+    average_sum: f64,
+}
+
+/// The longest lookahead of a true-peak limiter, in seconds.
+const MAX_LOOKAHEAD: f32 = 0.01;
+
+/// The number of samples which the interpolation filter of a true-peak limiter spans.
+const TAPS: usize = 12;
+
+/// The factor by which a true-peak limiter oversamples its input to find peaks.
+const OVERSAMPLE: usize = 4;
+
+impl Default for LimiterState {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            filters: interpolation_filters(),
+            delay: Vec::new(),
+            pos: 0,
+            lookahead: 0,
+            max_lookahead: 0,
+            count: 0,
+            minimum: VecDeque::new(),
+            average: Vec::new(),
+            average_pos: 0,
+            average_sum: 0.0,
+        }
+    }
+}
+
+impl LimiterState {
+    /// Allocates room for the longest lookahead at the given sample rate.
+    pub(crate) fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.max_lookahead = (MAX_LOOKAHEAD * sample_rate as f32).ceil().max(1.0) as usize;
+        self.delay = vec![[0.0; 2]; self.max_lookahead + TAPS];
+        self.minimum = VecDeque::with_capacity(self.max_lookahead + 2);
+        self.average = vec![1.0; self.max_lookahead];
+        self.reset();
+    }
+
+    /// Returns the limiter to its initial state, forgetting any delayed audio.
+    pub(crate) fn reset(&mut self) {
+        self.gain = 1.0;
+        self.lookahead = 0;
+    }
+
+    /// Clears the buffers of a true-peak limiter, ready for the given lookahead in samples.
+    fn prepare(&mut self, lookahead: usize) {
+        self.delay.fill([0.0; 2]);
+        self.pos = 0;
+        self.lookahead = lookahead;
+        self.count = 0;
+        self.minimum.clear();
+        self.average.fill(1.0);
+        self.average_pos = 0;
+        self.average_sum = lookahead as f64;
+    }
+
+    /// Gets the input frame which was written the given number of frames before the last one.
+    fn delayed(&self, frames: usize) -> [f32; 2] {
+        let len = self.delay.len();
+        self.delay[(self.pos + 2 * len - 1 - frames) % len]
+    }
+
+    /// Gets the gain needed to keep the interpolated output within ±1 between the frames which
+    /// were written `TAPS / 2` and `TAPS / 2 - 1` frames before the last one.
+    fn required_gain(&self) -> f32 {
+        let mut peak = 0f32;
+        for channel in 0..2 {
+            let history: [f32; TAPS] = std::array::from_fn(|i| self.delayed(TAPS - 1 - i)[channel]);
+            peak = peak.max(history[TAPS / 2 - 1].abs());
+            for filter in &self.filters {
+                let point: f32 = filter.iter().zip(&history).map(|(h, x)| h * x).sum();
+                peak = peak.max(point.abs());
+            }
+        }
+        if peak > 1.0 {
+            peak.recip()
+        } else {
+            1.0
+        }
+    }
+
+    /// Adds a required gain to the lookahead window, returning the minimum of the last
+    /// `lookahead + 1` required gains.
+    fn window_minimum(&mut self, gain: f32) -> f32 {
+        while self.minimum.back().is_some_and(|&(_, min)| min >= gain) {
+            self.minimum.pop_back();
+        }
+        self.minimum.push_back((self.count, gain));
+        while self.minimum.front().is_some_and(|&(added, _)| {
+            self.count.wrapping_sub(added) > self.lookahead
+        }) {
+            self.minimum.pop_front();
+        }
+        self.count = self.count.wrapping_add(1);
+        self.minimum.front().map_or(1.0, |&(_, min)| min)
+    }
+
+    /// Adds a minimum gain to the moving average, returning the average of the last
+    /// `lookahead` minimum gains.
+    fn moving_average(&mut self, gain: f32) -> f32 {
+        let oldest = std::mem::replace(&mut self.average[self.average_pos], gain);
+        self.average_sum += gain as f64 - oldest as f64;
+        self.average_pos += 1;
+        if self.average_pos == self.lookahead {
+            // Resum the window now and then, so that rounding errors don't accumulate
+            self.average_pos = 0;
+            self.average_sum = self.average[..self.lookahead].iter().map(|&g| g as f64).sum();
+        }
+        (self.average_sum / self.lookahead as f64) as f32
+    }
+}
+
+impl Limiter {
+    /// Gets the number of samples by which the limiter delays its input at the given sample rate.
+    pub(crate) fn latency(&self, sample_rate: u32) -> usize {
+        match *self {
+            Limiter::TruePeak { lookahead, .. } => {
+                lookahead_samples(lookahead, sample_rate) + TAPS / 2 - 1
+            }
+            _ => 0,
+        }
+    }
+
+    /// Applies the limiter to a block of stereo audio in place.
+    pub(crate) fn process(
+        &self,
+        state: &mut LimiterState,
+        sample_rate: u32,
+        output: [&mut [f32]; 2],
+    ) {
+        let [left, right] = output;
+        match *self {
+            Limiter::Off => state.reset(),
+            Limiter::SoftClip => {
+                state.reset();
+                left.iter_mut().for_each(|s| *s = s.tanh());
+                right.iter_mut().for_each(|s| *s = s.tanh());
+            }
+            Limiter::Peak { release } => {
+                let coeff = release_coeff(release, sample_rate);
+                state.lookahead = 0;
+
+                for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                    let peak = l.abs().max(r.abs());
+                    let target = if peak > 1.0 { peak.recip() } else { 1.0 };
+                    state.gain = release_gain(state.gain, target, coeff);
+
+                    *l *= state.gain;
+                    *r *= state.gain;
+                }
+            }
+            Limiter::TruePeak { release, lookahead } => {
+                let coeff = release_coeff(release, sample_rate);
+                let lookahead = lookahead_samples(lookahead, sample_rate).min(state.max_lookahead);
+                if lookahead != state.lookahead {
+                    state.prepare(lookahead);
+                }
+
+                for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                    let len = state.delay.len();
+                    state.delay[state.pos] = [*l, *r];
+                    state.pos = (state.pos + 1) % len;
+
+                    // Hold the lowest gain required within the lookahead, and average it over
+                    // the lookahead, so that the gain has fallen far enough by each peak.
+                    let required = state.required_gain();
+                    let minimum = state.window_minimum(required);
+                    let target = state.moving_average(minimum);
+                    state.gain = release_gain(state.gain, target, coeff);
+
+                    let [dl, dr] = state.delayed(lookahead + TAPS / 2 - 1);
+                    *l = dl * state.gain;
+                    *r = dr * state.gain;
+                }
+            }
+        }
+    }
+}
+
+/// Gets the lookahead of a true-peak limiter in samples, which is at least one.
+fn lookahead_samples(lookahead: f32, sample_rate: u32) -> usize {
+    let lookahead = lookahead.clamp(0.0, MAX_LOOKAHEAD) * sample_rate as f32;
+    (lookahead.round() as usize).max(1)
+}
+
+/// Gets the coefficient of the exponential release of a peak limiter.
+fn release_coeff(release: f32, sample_rate: u32) -> f32 {
+    let samples = release * sample_rate as f32;
+    if samples > 0.0 {
+        (-1.0 / samples).exp()
+    } else {
+        0.0
+    }
+}
+
+/// Moves the gain of a peak limiter towards the target, instantly if it is lower,
+/// and exponentially otherwise.
+fn release_gain(gain: f32, target: f32, coeff: f32) -> f32 {
+    if target < gain {
+        target
+    } else {
+        target + coeff * (gain - target)
+    }
+}
+
+/// Computes the windowed sinc filters which interpolate the points a quarter, half and three
+/// quarters of the way between the middle two of `TAPS` samples.
+fn interpolation_filters() -> [[f32; TAPS]; OVERSAMPLE - 1] {
+    std::array::from_fn(|phase| {
+        let fraction = (phase + 1) as f32 / OVERSAMPLE as f32;
+        let mut filter: [f32; TAPS] = std::array::from_fn(|i| {
+            let t = i as f32 - (TAPS / 2 - 1) as f32 - fraction;
+            let x = std::f32::consts::PI * t;
+            let window = 0.5 + 0.5 * (x / (TAPS / 2) as f32).cos();
+            window * x.sin() / x
+        });
+        // Normalise the filter, so that constant signals pass through unchanged
+        let sum: f32 = filter.iter().sum();
+        filter.iter_mut().for_each(|h| *h /= sum);
+        filter
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a limiter over the given mono signal, returning the left channel of the output.
+    fn limit(limiter: Limiter, input: &[f32]) -> Vec<f32> {
+        let mut state = LimiterState::default();
+        state.set_sample_rate(48000);
+        let (mut left, mut right) = (input.to_vec(), input.to_vec());
+        for (left, right) in left.chunks_mut(64).zip(right.chunks_mut(64)) {
+            limiter.process(&mut state, 48000, [left, right]);
+        }
+        left
+    }
+
+    #[test]
+    fn true_peak_limiter_catches_peaks_between_samples() {
+        // A sine at a quarter of the sample rate, sampled 45 degrees away from its peaks
+        let amplitude = 1.8;
+        let input: Vec<f32> = (0..4800)
+            .map(|n| amplitude * (std::f32::consts::FRAC_PI_4 * (2 * n + 1) as f32).sin())
+            .collect();
+        let sample_peak = amplitude * std::f32::consts::FRAC_1_SQRT_2;
+        assert!(sample_peak > 1.0);
+
+        // The sample peak limiter lets the true peak through
+        let peak = limit(Limiter::Peak { release: 0.05 }, &input);
+        assert!(peak[2400..].iter().all(|s| (s.abs() - 1.0).abs() < 1e-3));
+
+        // The true-peak limiter keeps the underlying sine within ±1
+        let limiter = Limiter::TruePeak { release: 0.05, lookahead: 0.002 };
+        let output = limit(limiter, &input);
+        let limit = std::f32::consts::FRAC_1_SQRT_2;
+        assert!(output.iter().all(|s| s.abs() <= limit * 1.01));
+        assert!(output[2400..].iter().all(|s| s.abs() >= limit * 0.97));
+    }
+
+    #[test]
+    fn true_peak_limiter_delays_by_its_latency() {
+        let limiter = Limiter::TruePeak { release: 0.05, lookahead: 0.002 };
+        let latency = limiter.latency(48000);
+        assert_eq!(latency, 96 + TAPS / 2 - 1);
+
+        let mut input = vec![0.0; 256];
+        input[10] = 0.5;
+        let output = limit(limiter, &input);
+        assert_eq!(output[10 + latency], 0.5);
+        assert_eq!(output.iter().filter(|&&s| s != 0.0).count(), 1);
+
+        // A loud impulse is attenuated without the gain reduction leaking into the output
+        // before it, as the gain has fallen by the time the impulse arrives
+        input[10] = 4.0;
+        input[9] = 0.5;
+        let output = limit(limiter, &input);
+        assert!(output[10 + latency] <= 1.0 + 1e-6);
+        assert!(output.iter().all(|s| s.abs() <= 1.0 + 1e-6));
+    }
+}
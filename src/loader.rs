@@ -0,0 +1,318 @@
+use crate::queue::RingBuffer;
+use crate::{Resources, Synth, Voice};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Prepares resources, such as samples and wavetables, on a background thread, so that changing
+/// to a preset with heavy assets doesn't cause a glitch.
+///
+/// Each [LoadJob] starts from a set of resources and runs its tasks in turn to add to them,
+/// reporting its [progress](Self::progress) as it goes. Once every task has finished, the
+/// resources are handed to the [ResourceSwap], which swaps them into the synth, and so into
+/// every voice at once, at the next block boundary. The resources which are replaced are handed
+/// back to the loader thread to be freed, so the audio thread never frees memory.
+///
+/// Jobs are run in the order they are sent. The thread stops when the loader is dropped, after
+/// finishing the job it is running.
+pub struct ResourceLoader {
+    /// Sends jobs to the thread.
+    jobs: Option<mpsc::Sender<LoadJob>>,
+    /// The state shared with the thread and the swap.
+    state: Arc<LoadState>,
+    /// The loader thread.
+    thread: Option<JoinHandle<()>>,
+}
+
+/// The end of a [ResourceLoader] used by the audio thread, which swaps loaded resources into
+/// a synth.
+pub struct ResourceSwap {
+    /// The state shared with the loader.
+    state: Arc<LoadState>,
+}
+
+/// A set of resources to be prepared by a [ResourceLoader].
+pub struct LoadJob {
+    /// The resources which the tasks add to.
+    resources: Resources,
+    /// The tasks which prepare the resources.
+    tasks: Vec<LoadTask>,
+}
+
+/// The progress of the job which a [ResourceLoader] ran most recently.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct LoadProgress {
+    /// The number of tasks of the job which have finished.
+    pub completed: usize,
+    /// The number of tasks in the job.
+    pub total: usize,
+    /// Whether the resources of every job which hasn't failed have been swapped into the synth.
+    pub swapped: bool,
+}
+
+/// A task of a [LoadJob].
+type LoadTask = Box<dyn FnOnce(&mut Resources) -> io::Result<()> + Send>;
+
+/// The state shared by a [ResourceLoader], its thread and its [ResourceSwap].
+struct LoadState {
+    /// The loaded resources, waiting to be swapped in.
+    loaded: RingBuffer<Box<Resources>>,
+    /// The resources which were swapped out, waiting to be freed by the loader thread.
+    replaced: RingBuffer<Box<Resources>>,
+    /// The number of tasks of the current job which have finished.
+    completed: AtomicUsize,
+    /// The number of tasks in the current job.
+    total: AtomicUsize,
+    /// The number of jobs which have been started, and haven't failed.
+    started_jobs: AtomicUsize,
+    /// The number of jobs which have been swapped into the synth.
+    swapped_jobs: AtomicUsize,
+    /// The error which stopped the most recent failed job, if it hasn't been taken.
+    error: Mutex<Option<io::Error>>,
+}
+
+impl ResourceLoader {
+    /// Creates a loader, and starts its thread.
+    ///
+    /// # Parameters
+    /// * `capacity` - The number of loaded jobs which can wait to be swapped in. A job which is
+    ///   loaded while the queue is full waits for room.
+    /// * `interval` - How often the thread checks for resources to free, and for room in a full
+    ///   queue.
+    ///
+    /// # Return
+    /// Returns the loader, and the end which swaps its resources into a synth.
+    pub fn new(capacity: usize, interval: Duration) -> (Self, ResourceSwap) {
+        assert!(capacity > 0, "Loader must have a capacity of at least one.");
+        let state = Arc::new(LoadState {
+            loaded: RingBuffer::new(capacity),
+            replaced: RingBuffer::new(capacity + 1),
+            completed: AtomicUsize::new(0),
+            total: AtomicUsize::new(0),
+            started_jobs: AtomicUsize::new(0),
+            swapped_jobs: AtomicUsize::new(0),
+            error: Mutex::new(None),
+        });
+        let (jobs, receiver) = mpsc::channel();
+        let thread_state = state.clone();
+        let thread = std::thread::spawn(move || run(&thread_state, receiver, interval));
+        let loader = Self {
+            jobs: Some(jobs),
+            state: state.clone(),
+            thread: Some(thread),
+        };
+        (loader, ResourceSwap { state })
+    }
+
+    /// Sends a job to be loaded once the jobs before it have finished.
+    pub fn load(&self, job: LoadJob) {
+        if let Some(jobs) = &self.jobs {
+            // The thread only stops once the loader is dropped
+            let _ = jobs.send(job);
+        }
+    }
+
+    /// Gets the progress of the job which was run most recently.
+    pub fn progress(&self) -> LoadProgress {
+        let state = &self.state;
+        LoadProgress {
+            completed: state.completed.load(Ordering::Acquire),
+            total: state.total.load(Ordering::Acquire),
+            swapped: state.swapped_jobs.load(Ordering::Acquire)
+                == state.started_jobs.load(Ordering::Acquire),
+        }
+    }
+
+    /// Takes the error which stopped the most recent failed job, if any. The resources of a
+    /// failed job are dropped rather than being swapped in.
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.state
+            .error
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+    }
+}
+
+impl Drop for ResourceLoader {
+    fn drop(&mut self) {
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl ResourceSwap {
+    /// Swaps the most recently loaded resources into a synth, if any have been loaded since
+    /// the last call, skipping any older ones.
+    ///
+    /// This should be called by the audio thread between blocks, so that every voice changes to
+    /// the new resources at the same time. It doesn't allocate or free memory, as long as the
+    /// voices don't in [set_resources](Voice::set_resources).
+    ///
+    /// # Return
+    /// Returns `true` if new resources were swapped in.
+    pub fn apply<V: Voice + Clone>(&mut self, synth: &mut Synth<V>) -> bool {
+        let state = &self.state;
+        let mut swapped = false;
+        // SAFETY: The swap is the only end which pops loaded resources, and it is borrowed
+        // mutably.
+        while let Some(mut resources) = unsafe { state.loaded.pop() } {
+            synth.swap_resources(&mut resources);
+            // SAFETY: The swap is the only end which pushes replaced resources, and it is
+            // borrowed mutably. The loader thread empties the queue before each push of loaded
+            // resources, so between two pushes no more can be returned than the loaded queue
+            // held, plus the one pushed after emptying it, and there is always room.
+            let returned = unsafe { state.replaced.push(resources) };
+            debug_assert!(returned.is_ok());
+            state.swapped_jobs.fetch_add(1, Ordering::Release);
+            swapped = true;
+        }
+        swapped
+    }
+}
+
+impl LoadJob {
+    /// Creates a job which adds to the given resources, such as those of the current preset
+    /// which are kept.
+    pub fn new(resources: Resources) -> Self {
+        Self {
+            resources,
+            tasks: vec![],
+        }
+    }
+
+    /// Adds a task which prepares resources, such as by reading a sample from disk or building
+    /// a wavetable, and inserts them. If a task fails, the job is abandoned.
+    pub fn with_task(
+        mut self,
+        task: impl FnOnce(&mut Resources) -> io::Result<()> + Send + 'static,
+    ) -> Self {
+        self.tasks.push(Box::new(task));
+        self
+    }
+}
+
+/// Runs the jobs sent to a [ResourceLoader] until it is dropped.
+fn run(state: &LoadState, jobs: mpsc::Receiver<LoadJob>, interval: Duration) {
+    // SAFETY: The loader thread is the only end which pops replaced resources.
+    let collect = || while unsafe { state.replaced.pop() }.is_some() {};
+    loop {
+        let job = match jobs.recv_timeout(interval) {
+            Ok(job) => job,
+            Err(RecvTimeoutError::Timeout) => {
+                collect();
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+
+        state.completed.store(0, Ordering::Release);
+        state.total.store(job.tasks.len(), Ordering::Release);
+        state.started_jobs.fetch_add(1, Ordering::Release);
+        let mut resources = job.resources;
+        let result = job.tasks.into_iter().try_for_each(|task| {
+            task(&mut resources)?;
+            state.completed.fetch_add(1, Ordering::Release);
+            Ok(())
+        });
+        if let Err(error) = result {
+            *state.error.lock().unwrap_or_else(|e| e.into_inner()) = Some(error);
+            state.started_jobs.fetch_sub(1, Ordering::Release);
+            continue;
+        }
+
+        let mut resources = Box::new(resources);
+        loop {
+            collect();
+            // SAFETY: The loader thread is the only end which pushes loaded resources.
+            match unsafe { state.loaded.push(resources) } {
+                Ok(()) => break,
+                Err(returned) => {
+                    resources = returned;
+                    std::thread::sleep(interval);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Note, SynthCapacity, SynthOpts, Velocity};
+
+    /// A voice which holds the wavetable of its resources.
+    #[derive(Clone, Default)]
+    struct TableVoice {
+        table: Option<Arc<Vec<f32>>>,
+    }
+
+    impl Voice for TableVoice {
+        fn set_sample_rate(&mut self, _sample_rate: u32) {}
+
+        fn reset(&mut self) {}
+
+        fn set_resources(&mut self, resources: &Resources) {
+            self.table = resources.get();
+        }
+
+        fn trigger(&mut self, _note: Note, _velocity: Velocity) {}
+
+        fn release(&mut self, _velocity: Velocity) {}
+
+        fn process(&mut self, _pitch: f32, _output: [&mut [f32]; 2]) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn loaded_resources_are_swapped_in_at_a_block_boundary() {
+        let capacity = SynthCapacity {
+            max_block_size: 64,
+            max_voices: 2,
+            max_buses: 1,
+        };
+        let opts = SynthOpts {
+            max_voices: 2,
+            ..Default::default()
+        };
+        let mut synth = Synth::new(capacity, opts, TableVoice::default());
+        let (loader, mut swap) = ResourceLoader::new(2, Duration::from_millis(1));
+
+        let job = LoadJob::new(Resources::new())
+            .with_task(|resources| {
+                resources.insert(Arc::new(vec![0.5f32; 2048]));
+                Ok(())
+            })
+            .with_task(|_| Ok(()));
+        loader.load(job);
+        while !swap.apply(&mut synth) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let table = synth.resources().get::<Vec<f32>>().unwrap();
+        assert_eq!(table.len(), 2048);
+        let progress = loader.progress();
+        assert_eq!(
+            (progress.completed, progress.total, progress.swapped),
+            (2, 2, true)
+        );
+
+        let failing = LoadJob::new(Resources::new())
+            .with_task(|_| Err(io::Error::new(io::ErrorKind::NotFound, "missing sample")));
+        loader.load(failing);
+        let error = loop {
+            if let Some(error) = loader.take_error() {
+                break error;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(!swap.apply(&mut synth));
+        assert!(synth.resources().get::<Vec<f32>>().is_some());
+    }
+}
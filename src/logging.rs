@@ -0,0 +1,130 @@
+use crate::queue::RingBuffer;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A bounded, lock-free queue for passing diagnostics out of the audio thread.
+///
+/// The queue is split into a [Logger], which is given to a [Synth](crate::Synth) with
+/// [set_logger](crate::Synth::set_logger), and a [LogConsumer], which is drained by another
+/// thread that prints or forwards the records. Records are fixed-size, so logging never
+/// allocates, blocks or formats text on the audio thread.
+///
+/// When the queue is full, new records are dropped and counted by [LogConsumer::dropped].
+pub struct LogQueue {
+    inner: Arc<LogInner>,
+}
+
+/// The sending end of a [LogQueue], used by the audio thread.
+pub struct Logger {
+    inner: Arc<LogInner>,
+}
+
+/// The receiving end of a [LogQueue].
+pub struct LogConsumer {
+    inner: Arc<LogInner>,
+}
+
+struct LogInner {
+    /// The queued records.
+    records: RingBuffer<LogRecord>,
+    /// The number of records which were dropped because the queue was full.
+    dropped: AtomicUsize,
+}
+
+/// The severity of a [LogRecord].
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum LogLevel {
+    /// Something went wrong, such as a voice panicking.
+    Error,
+    /// Something unexpected happened which may be audible.
+    Warn,
+    /// A notable change of state.
+    Info,
+    /// Detailed information about the inner workings of the synth, such as voice stealing.
+    Debug,
+}
+
+/// A diagnostic message logged from the audio thread.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct LogRecord {
+    /// The severity of the record.
+    pub level: LogLevel,
+    /// The message, which is static so that it needn't be formatted on the audio thread.
+    pub message: &'static str,
+    /// A value accompanying the message, such as the note of the affected voice, if any.
+    pub value: Option<f32>,
+    /// The time the record was logged, in samples since the synth was created.
+    pub position: u64,
+}
+
+impl LogQueue {
+    /// Creates a queue which can hold up to `capacity` records.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Log queue must have a capacity of at least one.");
+        Self {
+            inner: Arc::new(LogInner {
+                records: RingBuffer::new(capacity),
+                dropped: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Splits the queue into its logger and consumer.
+    pub fn split(self) -> (Logger, LogConsumer) {
+        let logger = Logger { inner: self.inner.clone() };
+        let consumer = LogConsumer { inner: self.inner };
+        (logger, consumer)
+    }
+}
+
+impl Logger {
+    /// Logs a record, dropping it if the queue is full.
+    pub fn log(&mut self, record: LogRecord) {
+        // SAFETY: The logger is the only end which pushes, and it is borrowed mutably.
+        if unsafe { self.inner.records.push(record) }.is_err() {
+            self.inner.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl LogConsumer {
+    /// Pops the oldest record from the queue, if there is one.
+    pub fn pop(&mut self) -> Option<LogRecord> {
+        // SAFETY: The consumer is the only end which pops, and it is borrowed mutably.
+        unsafe { self.inner.records.pop() }
+    }
+
+    /// Gets the number of records which have been dropped because the queue was full.
+    pub fn dropped(&self) -> usize {
+        self.inner.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Drains the queue into the [log] crate, with a target of `reson`.
+    ///
+    /// This is only available with the `log` feature, and should be called periodically
+    /// from a thread which is not real-time sensitive.
+    #[cfg(feature = "log")]
+    pub fn forward(&mut self) {
+        for record in self {
+            let level = match record.level {
+                LogLevel::Error => log::Level::Error,
+                LogLevel::Warn => log::Level::Warn,
+                LogLevel::Info => log::Level::Info,
+                LogLevel::Debug => log::Level::Debug,
+            };
+            let LogRecord { position, message, .. } = record;
+            match record.value {
+                Some(value) => log::log!(target: "reson", level, "[{position}] {message}: {value}"),
+                None => log::log!(target: "reson", level, "[{position}] {message}"),
+            }
+        }
+    }
+}
+
+impl Iterator for LogConsumer {
+    type Item = LogRecord;
+
+    fn next(&mut self) -> Option<LogRecord> {
+        self.pop()
+    }
+}
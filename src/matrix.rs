@@ -0,0 +1,96 @@
+/// Routes the stereo buses rendered by [Synth::process_buses](crate::Synth::process_buses)
+/// to any number of output channels, such as the channels of a multi-output audio interface.
+///
+/// The left and right channels of bus `i` are the matrix inputs `2 * i` and `2 * i + 1`.
+/// Each output is the sum of every input scaled by the gain from that input to the output.
+/// Every gain is initially zero, so nothing is routed until it is set.
+#[derive(Clone, Debug)]
+pub struct OutputMatrix {
+    /// The number of input channels, which is twice the number of buses.
+    inputs: usize,
+    /// The number of output channels.
+    outputs: usize,
+    /// The gain from each input to each output, indexed by `output * inputs + input`.
+    gains: Vec<f32>,
+}
+
+impl OutputMatrix {
+    /// Creates a matrix from the given number of stereo buses to the given number of outputs,
+    /// with nothing routed.
+    pub fn new(num_buses: usize, num_outputs: usize) -> Self {
+        Self {
+            inputs: 2 * num_buses,
+            outputs: num_outputs,
+            gains: vec![0.0; 2 * num_buses * num_outputs],
+        }
+    }
+
+    /// Gets the number of stereo buses routed by the matrix.
+    pub fn num_buses(&self) -> usize {
+        self.inputs / 2
+    }
+
+    /// Gets the number of output channels.
+    pub fn num_outputs(&self) -> usize {
+        self.outputs
+    }
+
+    /// Sets the gain from an input channel to an output channel, as a linear amplitude.
+    ///
+    /// # Parameters
+    /// * `input` - The input channel, which is `2 * bus` for the left channel of a bus,
+    ///   or `2 * bus + 1` for its right channel.
+    /// * `output` - The output channel.
+    /// * `gain` - The gain, where zero disconnects the input from the output.
+    pub fn set_gain(&mut self, input: usize, output: usize, gain: f32) {
+        assert!(input < self.inputs && output < self.outputs, "Channel out of range.");
+        self.gains[output * self.inputs + input] = gain;
+    }
+
+    /// Gets the gain from an input channel to an output channel.
+    pub fn gain(&self, input: usize, output: usize) -> f32 {
+        assert!(input < self.inputs && output < self.outputs, "Channel out of range.");
+        self.gains[output * self.inputs + input]
+    }
+
+    /// Routes a bus in stereo to a pair of output channels at unity gain,
+    /// in addition to any existing routing.
+    pub fn route_bus(&mut self, bus: usize, left: usize, right: usize) {
+        self.set_gain(2 * bus, left, 1.0);
+        self.set_gain(2 * bus + 1, right, 1.0);
+    }
+
+    /// Disconnects every input from every output.
+    pub fn clear(&mut self) {
+        self.gains.fill(0.0);
+    }
+
+    /// Mixes a block of buses into the output channels, overwriting their contents.
+    ///
+    /// This never allocates memory, so it is safe to call from the audio thread.
+    ///
+    /// # Parameters
+    /// * `buses` - The left and right audio buffers of each bus, as rendered by
+    ///   [Synth::process_buses](crate::Synth::process_buses).
+    /// * `outputs` - The audio buffer of each output channel, which must all have the same
+    ///   length as the buses.
+    pub fn process(&self, buses: &[[&mut [f32]; 2]], outputs: &mut [&mut [f32]]) {
+        assert_eq!(buses.len(), self.num_buses(), "Wrong number of buses.");
+        assert_eq!(outputs.len(), self.outputs, "Wrong number of outputs.");
+
+        for (index, output) in outputs.iter_mut().enumerate() {
+            output.fill(0.0);
+            let gains = &self.gains[index * self.inputs..(index + 1) * self.inputs];
+            let inputs = buses.iter().flat_map(|[left, right]| [&**left, &**right]);
+            for (input, &gain) in inputs.zip(gains) {
+                if gain == 0.0 {
+                    continue;
+                }
+                debug_assert!(input.len() == output.len());
+                for (out, sample) in output.iter_mut().zip(input) {
+                    *out += gain * sample;
+                }
+            }
+        }
+    }
+}
@@ -19,11 +19,34 @@ pub enum MidiEvent {
         channel: u8,
         value: u16,
     },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    /// A high-resolution controller value, combined from a pair of MSB (CC0–31)
+    /// and LSB (CC32–63) control change messages by [ControllerPairing].
+    ControlChange14 {
+        channel: u8,
+        /// The controller number of the MSB, between 0 and 31.
+        controller: u8,
+        /// The 14-bit controller value, between 0 and 16383.
+        value: u16,
+    },
 }
 
 impl MidiEvent {
     /// Creates a MIDI event from raw bytes.
     pub fn from_raw(data: &[u8]) -> Option<Self> {
+        // Data bytes must not have their high bit set, so every value is in range
+        if data.iter().skip(1).any(|&byte| byte > 0x7f) {
+            return None;
+        }
+
         Some(match *data {
             [a @ 0x80..=0x8f, note, velocity] => MidiEvent::NoteOff {
                 channel: a & 0x0f,
@@ -35,6 +58,15 @@ impl MidiEvent {
                 note,
                 velocity,
             },
+            [a @ 0xb0..=0xbf, controller, value] => MidiEvent::ControlChange {
+                channel: a & 0x0f,
+                controller,
+                value,
+            },
+            [a @ 0xc0..=0xcf, program] => MidiEvent::ProgramChange {
+                channel: a & 0x0f,
+                program,
+            },
             [a @ 0xe0..=0xef, lsb, msb] => MidiEvent::PitchBend {
                 channel: a & 0x0f,
                 value: lsb as u16 | ((msb as u16) << 7),
@@ -42,4 +74,409 @@ impl MidiEvent {
             _ => return None,
         })
     }
+
+    /// Converts the event into a raw MIDI message.
+    ///
+    /// Out of range values are masked to fit, and high-resolution controller changes
+    /// are reduced to their MSB.
+    ///
+    /// # Return
+    /// Returns a buffer holding the message, and the number of bytes of the buffer it occupies.
+    pub fn to_raw(self) -> ([u8; 3], usize) {
+        let status = |kind: u8, channel: u8| kind | (channel & 0x0f);
+        match self {
+            MidiEvent::NoteOff { channel, note, velocity } => {
+                ([status(0x80, channel), note & 0x7f, velocity & 0x7f], 3)
+            }
+            MidiEvent::NoteOn { channel, note, velocity } => {
+                ([status(0x90, channel), note & 0x7f, velocity & 0x7f], 3)
+            }
+            MidiEvent::ControlChange { channel, controller, value } => {
+                ([status(0xb0, channel), controller & 0x7f, value & 0x7f], 3)
+            }
+            MidiEvent::ProgramChange { channel, program } => {
+                ([status(0xc0, channel), program & 0x7f, 0], 2)
+            }
+            MidiEvent::PitchBend { channel, value } => {
+                let (lsb, msb) = (value as u8 & 0x7f, (value >> 7) as u8 & 0x7f);
+                ([status(0xe0, channel), lsb, msb], 3)
+            }
+            MidiEvent::ControlChange14 { channel, controller, value } => {
+                let msb = (value >> 7) as u8 & 0x7f;
+                ([status(0xb0, channel), controller & 0x7f, msb], 3)
+            }
+        }
+    }
+
+    /// Parses a complete stream of raw MIDI bytes, such as the contents of a file or a fuzzer input,
+    /// yielding each event it contains.
+    ///
+    /// Any sequence of bytes is accepted: malformed messages, system messages and unsupported
+    /// channel messages are skipped, and every yielded event has its values in range.
+    pub fn parse_stream(bytes: &[u8]) -> impl Iterator<Item = MidiEvent> + '_ {
+        let mut parser = MidiStreamParser::new();
+        bytes.iter().filter_map(move |&byte| parser.push(byte))
+    }
+}
+
+/// A program selected via MIDI Bank Select (CC0/CC32) and Program Change messages.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug)]
+pub struct Program {
+    /// The 14-bit bank number, combining the bank select MSB (CC0) and LSB (CC32).
+    pub bank: u16,
+    /// The program number within the bank, between 0 and 127.
+    pub program: u8,
+}
+
+impl Program {
+    /// Gets a flat index addressing the program across all banks.
+    pub fn index(&self) -> u32 {
+        ((self.bank as u32) << 7) | self.program as u32
+    }
+}
+
+/// Parses a stream of raw MIDI bytes into [MidiEvent]s.
+///
+/// Unlike [MidiEvent::from_raw], the bytes may arrive in arbitrary chunks containing several
+/// messages, partial messages, running status, or interleaved system realtime bytes.
+/// Messages which have no [MidiEvent] equivalent, including system exclusive messages,
+/// are skipped.
+#[derive(Clone, Default)]
+pub struct MidiStreamParser {
+    /// The current running status byte, if any.
+    status: Option<u8>,
+    /// The data bytes received so far for the current message.
+    data: [u8; 2],
+    /// The number of data bytes received so far for the current message.
+    len: usize,
+}
+
+impl MidiStreamParser {
+    /// Creates a new parser with no running status.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a chunk of bytes, yielding each complete event it contains.
+    ///
+    /// Any trailing partial message is retained, and completed by subsequent chunks.
+    pub fn parse<'a>(&'a mut self, bytes: &'a [u8]) -> impl Iterator<Item = MidiEvent> + 'a {
+        bytes.iter().filter_map(move |&byte| self.push(byte))
+    }
+
+    /// Parses a single byte, returning an event if it completes one.
+    pub fn push(&mut self, byte: u8) -> Option<MidiEvent> {
+        match byte {
+            // System realtime messages may appear anywhere, and don't affect running status
+            0xf8..=0xff => None,
+            // Channel messages set the running status
+            0x80..=0xef => {
+                self.status = Some(byte);
+                self.len = 0;
+                None
+            }
+            // System exclusive and system common messages cancel running status,
+            // so their data bytes are ignored
+            0xf0..=0xf7 => {
+                self.status = None;
+                self.len = 0;
+                None
+            }
+            // Data bytes
+            _ => {
+                let status = self.status?;
+                self.data[self.len] = byte;
+                self.len += 1;
+
+                let expected = match status & 0xf0 {
+                    0xc0 | 0xd0 => 1,
+                    _ => 2,
+                };
+                if self.len < expected {
+                    return None;
+                }
+
+                self.len = 0;
+                match expected {
+                    1 => MidiEvent::from_raw(&[status, self.data[0]]),
+                    _ => MidiEvent::from_raw(&[status, self.data[0], self.data[1]]),
+                }
+            }
+        }
+    }
+}
+
+/// Combines pairs of MSB (CC0–31) and LSB (CC32–63) control change messages into
+/// single 14-bit [MidiEvent::ControlChange14] events, so that high-resolution controllers
+/// are not quantised to 7 bits.
+///
+/// An MSB message resets the LSB to zero, as per the MIDI specification,
+/// so controllers which only send the MSB still produce the expected values.
+#[derive(Clone, Default)]
+pub struct ControllerPairing {
+    /// The most recent 14-bit value of each pairable controller on each channel.
+    values: [[u16; 32]; 16],
+}
+
+impl ControllerPairing {
+    /// Creates a [ControllerPairing] with all controllers at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Processes a MIDI event, converting control changes for controllers 0 to 63 into
+    /// 14-bit control changes. All other events are returned unchanged.
+    pub fn process(&mut self, event: MidiEvent) -> MidiEvent {
+        let MidiEvent::ControlChange { channel, controller, value } = event else {
+            return event;
+        };
+
+        let values = &mut self.values[channel as usize & 0x0f];
+        let value = value as u16 & 0x7f;
+        let controller = match controller {
+            // MSB
+            0..=31 => {
+                values[controller as usize] = value << 7;
+                controller
+            }
+            // LSB
+            32..=63 => {
+                let msb = controller - 32;
+                let pair = &mut values[msb as usize];
+                *pair = (*pair & !0x7f) | value;
+                msb
+            }
+            _ => return event,
+        };
+
+        MidiEvent::ControlChange14 {
+            channel,
+            controller,
+            value: values[controller as usize],
+        }
+    }
+}
+
+/// Error returned when converting a `midly` event which has no [MidiEvent] equivalent.
+#[cfg(feature = "midly")]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct UnsupportedEvent;
+
+#[cfg(feature = "midly")]
+impl std::fmt::Display for UnsupportedEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MIDI event is not supported")
+    }
+}
+
+#[cfg(feature = "midly")]
+impl std::error::Error for UnsupportedEvent {}
+
+#[cfg(feature = "midly")]
+impl MidiEvent {
+    /// Converts a `midly` channel message into a MIDI event.
+    ///
+    /// Returns `None` for messages which have no [MidiEvent] equivalent, such as aftertouch.
+    pub fn from_midly(channel: midly::num::u4, message: midly::MidiMessage) -> Option<Self> {
+        use midly::MidiMessage as M;
+
+        let channel = channel.as_int();
+        Some(match message {
+            M::NoteOff { key, vel } => MidiEvent::NoteOff {
+                channel,
+                note: key.as_int(),
+                velocity: vel.as_int(),
+            },
+            M::NoteOn { key, vel } => MidiEvent::NoteOn {
+                channel,
+                note: key.as_int(),
+                velocity: vel.as_int(),
+            },
+            M::PitchBend { bend } => MidiEvent::PitchBend {
+                channel,
+                value: bend.0.as_int(),
+            },
+            M::Controller { controller, value } => MidiEvent::ControlChange {
+                channel,
+                controller: controller.as_int(),
+                value: value.as_int(),
+            },
+            M::ProgramChange { program } => MidiEvent::ProgramChange {
+                channel,
+                program: program.as_int(),
+            },
+            _ => return None,
+        })
+    }
+
+    /// Converts the MIDI event into a `midly` channel and message.
+    ///
+    /// High-resolution controller changes are reduced to their MSB.
+    pub fn to_midly(self) -> (midly::num::u4, midly::MidiMessage) {
+        use midly::num::{u14, u4, u7};
+        use midly::MidiMessage as M;
+
+        match self {
+            MidiEvent::NoteOn { channel, note, velocity } => (
+                u4::new(channel),
+                M::NoteOn { key: u7::new(note), vel: u7::new(velocity) },
+            ),
+            MidiEvent::NoteOff { channel, note, velocity } => (
+                u4::new(channel),
+                M::NoteOff { key: u7::new(note), vel: u7::new(velocity) },
+            ),
+            MidiEvent::PitchBend { channel, value } => (
+                u4::new(channel),
+                M::PitchBend { bend: midly::PitchBend(u14::new(value)) },
+            ),
+            MidiEvent::ControlChange { channel, controller, value } => (
+                u4::new(channel),
+                M::Controller { controller: u7::new(controller), value: u7::new(value) },
+            ),
+            MidiEvent::ProgramChange { channel, program } => (
+                u4::new(channel),
+                M::ProgramChange { program: u7::new(program) },
+            ),
+            MidiEvent::ControlChange14 { channel, controller, value } => (
+                u4::new(channel),
+                M::Controller { controller: u7::new(controller), value: u7::new((value >> 7) as u8) },
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "midly")]
+impl TryFrom<midly::live::LiveEvent<'_>> for MidiEvent {
+    type Error = UnsupportedEvent;
+
+    fn try_from(event: midly::live::LiveEvent<'_>) -> Result<Self, Self::Error> {
+        match event {
+            midly::live::LiveEvent::Midi { channel, message } => {
+                Self::from_midly(channel, message).ok_or(UnsupportedEvent)
+            }
+            _ => Err(UnsupportedEvent),
+        }
+    }
+}
+
+#[cfg(feature = "midly")]
+impl TryFrom<midly::TrackEventKind<'_>> for MidiEvent {
+    type Error = UnsupportedEvent;
+
+    fn try_from(event: midly::TrackEventKind<'_>) -> Result<Self, Self::Error> {
+        match event {
+            midly::TrackEventKind::Midi { channel, message } => {
+                Self::from_midly(channel, message).ok_or(UnsupportedEvent)
+            }
+            _ => Err(UnsupportedEvent),
+        }
+    }
+}
+
+#[cfg(feature = "midly")]
+impl From<MidiEvent> for midly::live::LiveEvent<'static> {
+    fn from(event: MidiEvent) -> Self {
+        let (channel, message) = event.to_midly();
+        midly::live::LiveEvent::Midi { channel, message }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gets the number of data bytes following a supported channel status byte,
+    /// or `None` if it has no [MidiEvent] equivalent.
+    fn data_len(status: u8) -> Option<usize> {
+        match status & 0xf0 {
+            0x80 | 0x90 | 0xb0 | 0xe0 => Some(2),
+            0xc0 => Some(1),
+            _ => None,
+        }
+    }
+
+    /// A small xorshift generator, so that random inputs are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    #[test]
+    fn raw_round_trip_is_exact() {
+        for status in 0x80..=0xef {
+            for a in 0..=0x7f {
+                for b in 0..=0x7f {
+                    let raw = [status, a, b];
+                    let Some(len) = data_len(status) else {
+                        assert_eq!(MidiEvent::from_raw(&raw), None);
+                        continue;
+                    };
+                    let event = MidiEvent::from_raw(&raw[..len + 1]).unwrap();
+                    let (bytes, bytes_len) = event.to_raw();
+                    assert_eq!(&bytes[..bytes_len], &raw[..len + 1]);
+                    assert_eq!(MidiEvent::from_raw(&bytes[..bytes_len]), Some(event));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn raw_rejects_invalid_data_bytes() {
+        for status in 0x80..=0xef {
+            for byte in 0x80..=0xff {
+                assert_eq!(MidiEvent::from_raw(&[status, byte, 0]), None);
+                assert_eq!(MidiEvent::from_raw(&[status, 0, byte]), None);
+            }
+        }
+    }
+
+    #[test]
+    fn stream_parser_accepts_arbitrary_bytes() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..1000 {
+            let len = rng.below(256);
+            let bytes: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+
+            // Every event is in range, so survives a round trip through raw bytes
+            let events: Vec<MidiEvent> = MidiEvent::parse_stream(&bytes).collect();
+            for &event in &events {
+                let (raw, len) = event.to_raw();
+                assert_eq!(MidiEvent::from_raw(&raw[..len]), Some(event));
+            }
+
+            // Splitting the stream into chunks doesn't change the events
+            let mut parser = MidiStreamParser::new();
+            let mut chunked = vec![];
+            let mut rest = &bytes[..];
+            while !rest.is_empty() {
+                let (chunk, tail) = rest.split_at(1 + rng.below(rest.len()));
+                chunked.extend(parser.parse(chunk));
+                rest = tail;
+            }
+            assert_eq!(chunked, events);
+        }
+    }
+
+    #[test]
+    fn stream_parser_handles_running_status_and_realtime() {
+        let bytes = [0x90, 60, 100, 0xf8, 62, 0xfe, 100, 0xf0, 1, 2, 0xf7, 64, 100];
+        let events: Vec<MidiEvent> = MidiEvent::parse_stream(&bytes).collect();
+        assert_eq!(
+            events,
+            [
+                MidiEvent::NoteOn { channel: 0, note: 60, velocity: 100 },
+                MidiEvent::NoteOn { channel: 0, note: 62, velocity: 100 },
+            ]
+        );
+    }
 }
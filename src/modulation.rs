@@ -0,0 +1,479 @@
+/// A source of modulation, such as an envelope follower, which is rendered a block at a time by a
+/// [ModMatrix].
+pub trait ModSource {
+    /// Sets the sample rate.
+    ///
+    /// This may be called multiple times.
+    fn set_sample_rate(&mut self, sample_rate: u32);
+
+    /// Resets the state of the source.
+    fn reset(&mut self);
+
+    /// Gets the rate at which the source is rendered. The default implementation returns
+    /// [ModRate::Audio].
+    fn rate(&self) -> ModRate {
+        ModRate::Audio
+    }
+
+    /// Renders a block of the source.
+    ///
+    /// # Parameters
+    /// * `input` - The left and right channels of the audio input for the block, for sources
+    ///   which follow it, which are silent if the matrix has no input.
+    /// * `output` - The buffer which the values of the source are written to. At audio rate,
+    ///   this has a value for each sample of the input. At control rate, it has a value for each
+    ///   [CONTROL_INTERVAL] samples of the input, the last of which may be shorter, and each value
+    ///   is the value of the source at the end of its interval.
+    fn process(&mut self, input: [&[f32]; 2], output: &mut [f32]);
+}
+
+/// The rate at which a [ModSource] is rendered.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ModRate {
+    /// The source is rendered once every [CONTROL_INTERVAL] samples, and the matrix ramps
+    /// linearly between the values, which suits slow sources which are costly to evaluate.
+    Control,
+    /// The source is rendered at every sample, so that fast sources, such as audio rate LFOs
+    /// and envelope followers, don't alias against the block rate.
+    Audio,
+}
+
+/// The number of samples between the values of a source rendered at [ModRate::Control].
+pub const CONTROL_INTERVAL: usize = 32;
+
+/// Routes modulation sources to parameters, which are known as destinations.
+///
+/// The destination type `P` is chosen by the application, and is typically an enum or an index
+/// identifying a parameter of a voice or effect. Each block, the matrix is
+/// [processed](Self::process) with the audio input, if any, which renders every source into a
+/// buffer with a value for each sample. The value of each destination, which adds the routed
+/// sources to a base value, can then be read at the end of the block with [value](Self::value),
+/// at any sample with [value_at](Self::value_at), or for every sample with
+/// [values](Self::values).
+///
+/// For example, to duck the synth whenever a kick drum on the audio input hits, an
+/// [EnvelopeFollower] can be routed to the master gain with a negative amount, and the value of
+/// the gain passed to [Synth::set_gain](crate::Synth::set_gain) before each block.
+pub struct ModMatrix<P> {
+    /// The modulation sources.
+    sources: Vec<Box<dyn ModSource + Send>>,
+    /// The value of each source at the end of the last block.
+    values: Vec<f32>,
+    /// The value of each source at each sample of the last block, one block after another.
+    buffers: Vec<f32>,
+    /// The values rendered by a source at control rate.
+    control: Vec<f32>,
+    /// The length of the last block.
+    len: usize,
+    /// The routes from sources to destinations.
+    routes: Vec<ModRoute<P>>,
+    /// Silence, used as the input of blocks without audio input.
+    silence: Vec<f32>,
+    /// The sample rate in Hz.
+    sample_rate: u32,
+}
+
+/// A route from a modulation source to a destination.
+#[derive(Clone, Debug)]
+pub struct ModRoute<P> {
+    /// The index of the source, as returned by [ModMatrix::add_source].
+    pub source: usize,
+    /// The destination which is modulated.
+    pub target: P,
+    /// The amount by which the value of the source is scaled before being added to the
+    /// destination, which may be negative.
+    pub amount: f32,
+}
+
+/// Follows the amplitude of an audio signal, such as the audio input of the synth,
+/// for use as a modulation source.
+///
+/// The level rises towards the amplitude of the signal with the attack time, and falls with
+/// the release time, which are the times for the level to move by a factor of e.
+#[derive(Clone)]
+pub struct EnvelopeFollower {
+    /// The time in seconds for the level to rise.
+    attack: f32,
+    /// The time in seconds for the level to fall.
+    release: f32,
+    /// How the amplitude of the signal is measured.
+    mode: FollowerMode,
+    /// The one-pole filter coefficient applied while the level rises.
+    attack_coeff: f32,
+    /// The one-pole filter coefficient applied while the level falls.
+    release_coeff: f32,
+    /// The sample rate in Hz.
+    sample_rate: u32,
+    /// The current level, which is the mean square of the signal in RMS mode.
+    level: f32,
+}
+
+/// How an [EnvelopeFollower] measures the amplitude of a signal.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FollowerMode {
+    /// The level follows the peaks of the signal, responding quickly to transients.
+    Peak,
+    /// The level follows the root mean square of the signal, which is closer to its loudness.
+    Rms,
+}
+
+/// The initial sample rate of an [EnvelopeFollower], until one is set.
+const DEFAULT_SAMPLE_RATE: u32 = 48000;
+
+impl<P: PartialEq> ModMatrix<P> {
+    /// Creates a matrix with no sources or routes, for blocks of up to `max_block_size` samples.
+    pub fn new(max_block_size: usize) -> Self {
+        Self {
+            sources: vec![],
+            values: vec![],
+            buffers: vec![],
+            control: vec![0.0; max_block_size.div_ceil(CONTROL_INTERVAL)],
+            len: 0,
+            routes: vec![],
+            silence: vec![0.0; max_block_size],
+            sample_rate: DEFAULT_SAMPLE_RATE,
+        }
+    }
+
+    /// Adds a modulation source, returning the index used to route it.
+    ///
+    /// This allocates memory, so shouldn't be called from the audio thread.
+    pub fn add_source(&mut self, mut source: impl ModSource + Send + 'static) -> usize {
+        source.set_sample_rate(self.sample_rate);
+        self.sources.push(Box::new(source));
+        self.values.push(0.0);
+        self.buffers
+            .resize(self.buffers.len() + self.silence.len(), 0.0);
+        self.sources.len() - 1
+    }
+
+    /// Gets the number of sources.
+    pub fn num_sources(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// Routes a source to a destination, replacing any existing route between them.
+    ///
+    /// Adding a route may allocate memory, so routes should be set up before processing,
+    /// or from a thread which is not real-time sensitive.
+    pub fn route(&mut self, route: ModRoute<P>) {
+        assert!(
+            route.source < self.sources.len(),
+            "Modulation source doesn't exist."
+        );
+        match self
+            .routes
+            .iter_mut()
+            .find(|r| r.source == route.source && r.target == route.target)
+        {
+            Some(existing) => existing.amount = route.amount,
+            None => self.routes.push(route),
+        }
+    }
+
+    /// Removes the route between a source and a destination, if there is one.
+    pub fn unroute(&mut self, source: usize, target: &P) {
+        self.routes
+            .retain(|r| r.source != source || &r.target != target);
+    }
+
+    /// Gets the routes from sources to destinations.
+    pub fn routes(&self) -> &[ModRoute<P>] {
+        &self.routes
+    }
+
+    /// Sets the sample rate of every source.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        for source in &mut self.sources {
+            source.set_sample_rate(sample_rate);
+        }
+    }
+
+    /// Resets every source.
+    pub fn reset(&mut self) {
+        for source in &mut self.sources {
+            source.reset();
+        }
+        self.values.fill(0.0);
+        self.buffers.fill(0.0);
+    }
+
+    /// Renders every source for a block.
+    ///
+    /// # Parameters
+    /// * `len` - The length of the block, which must be no larger than the maximum block size.
+    /// * `input` - The left and right channels of the audio input for the block, if any,
+    ///   each of length `len`.
+    pub fn process(&mut self, len: usize, input: Option<[&[f32]; 2]>) {
+        let max = self.silence.len();
+        assert!(len <= max);
+        let silence = &self.silence[..len];
+        let input = input.unwrap_or([silence, silence]);
+        assert!(input.iter().all(|channel| channel.len() == len));
+        self.len = len;
+        if len == 0 {
+            return;
+        }
+
+        let buffers = self.buffers.chunks_exact_mut(max);
+        for ((source, value), buffer) in self.sources.iter_mut().zip(&mut self.values).zip(buffers)
+        {
+            let buffer = &mut buffer[..len];
+            match source.rate() {
+                ModRate::Audio => source.process(input, buffer),
+                ModRate::Control => {
+                    let control = &mut self.control[..len.div_ceil(CONTROL_INTERVAL)];
+                    source.process(input, control);
+                    // Ramp from the value at the end of each interval to the next
+                    let mut prev = *value;
+                    for (chunk, &next) in buffer.chunks_mut(CONTROL_INTERVAL).zip(&*control) {
+                        let step = (next - prev) / chunk.len() as f32;
+                        for (n, sample) in chunk.iter_mut().enumerate() {
+                            *sample = prev + step * (n + 1) as f32;
+                        }
+                        chunk[chunk.len() - 1] = next;
+                        prev = next;
+                    }
+                }
+            }
+            *value = buffer[len - 1];
+        }
+    }
+
+    /// Gets the value of a source at the end of the last block.
+    pub fn source_value(&self, source: usize) -> f32 {
+        self.values[source]
+    }
+
+    /// Gets the value of a source at each sample of the last block.
+    pub fn source_buffer(&self, source: usize) -> &[f32] {
+        let max = self.silence.len();
+        &self.buffers[source * max..][..self.len]
+    }
+
+    /// Gets the value of a destination, which is the base value plus the value of every source
+    /// routed to it, scaled by the amount of its route.
+    pub fn value(&self, target: &P, base: f32) -> f32 {
+        self.routes
+            .iter()
+            .filter(|route| &route.target == target)
+            .fold(base, |value, route| {
+                value + route.amount * self.values[route.source]
+            })
+    }
+
+    /// Gets the value of a destination at a sample of the last block, which is the base value
+    /// plus the value of every source routed to it at that sample, scaled by the amount of its
+    /// route.
+    ///
+    /// # Panics
+    /// Panics if the offset isn't within the last block.
+    pub fn value_at(&self, target: &P, base: f32, offset: usize) -> f32 {
+        assert!(offset < self.len, "Offset is outside of the last block.");
+        let max = self.silence.len();
+        self.routes
+            .iter()
+            .filter(|route| &route.target == target)
+            .fold(base, |value, route| {
+                value + route.amount * self.buffers[route.source * max + offset]
+            })
+    }
+
+    /// Gets the value of a destination at every sample of the last block, for destinations
+    /// which are modulated at audio rate.
+    ///
+    /// # Parameters
+    /// * `target` - The destination.
+    /// * `base` - The value of the destination before modulation.
+    /// * `output` - The buffer which the values are written to, which must be no longer than
+    ///   the last block.
+    pub fn values(&self, target: &P, base: f32, output: &mut [f32]) {
+        assert!(
+            output.len() <= self.len,
+            "Output is longer than the last block."
+        );
+        output.fill(base);
+        for route in self.routes.iter().filter(|route| &route.target == target) {
+            let buffer = self.source_buffer(route.source);
+            for (value, source) in output.iter_mut().zip(buffer) {
+                *value += route.amount * source;
+            }
+        }
+    }
+}
+
+impl EnvelopeFollower {
+    /// Creates an envelope follower with the given attack and release times in seconds.
+    pub fn new(attack: f32, release: f32, mode: FollowerMode) -> Self {
+        let mut out = Self {
+            attack,
+            release,
+            mode,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            level: 0.0,
+        };
+        out.update_coeffs();
+        out
+    }
+
+    /// Sets the attack and release times in seconds.
+    pub fn set_times(&mut self, attack: f32, release: f32) {
+        self.attack = attack;
+        self.release = release;
+        self.update_coeffs();
+    }
+
+    /// Gets the current level of the signal, as a linear amplitude.
+    pub fn level(&self) -> f32 {
+        match self.mode {
+            FollowerMode::Peak => self.level,
+            FollowerMode::Rms => self.level.sqrt(),
+        }
+    }
+
+    /// Follows a single sample of the signal, returning the new level.
+    pub fn process_sample(&mut self, sample: f32) -> f32 {
+        let x = match self.mode {
+            FollowerMode::Peak => sample.abs(),
+            FollowerMode::Rms => sample * sample,
+        };
+        let coeff = if x > self.level {
+            self.attack_coeff
+        } else {
+            self.release_coeff
+        };
+        self.level = x + coeff * (self.level - x);
+        self.level()
+    }
+
+    /// Updates the filter coefficients from the attack and release times.
+    fn update_coeffs(&mut self) {
+        let coeff = |time: f32| {
+            let samples = time * self.sample_rate as f32;
+            if samples > 0.0 {
+                (-1.0 / samples).exp()
+            } else {
+                0.0
+            }
+        };
+        self.attack_coeff = coeff(self.attack);
+        self.release_coeff = coeff(self.release);
+    }
+}
+
+impl ModSource for EnvelopeFollower {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.update_coeffs();
+    }
+
+    fn reset(&mut self) {
+        self.level = 0.0;
+    }
+
+    fn process(&mut self, input: [&[f32]; 2], output: &mut [f32]) {
+        let [left, right] = input;
+        for ((&l, &r), out) in left.iter().zip(right).zip(output) {
+            // Follow the louder channel, so that hard-panned signals aren't halved
+            let sample = if l.abs() > r.abs() { l } else { r };
+            *out = self.process_sample(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(PartialEq, Debug)]
+    enum Target {
+        Gain,
+        Cutoff,
+    }
+
+    #[test]
+    fn follower_modulates_routed_destinations() {
+        let mut matrix = ModMatrix::new(64);
+        let follower = matrix.add_source(EnvelopeFollower::new(0.001, 0.05, FollowerMode::Peak));
+        matrix.route(ModRoute {
+            source: follower,
+            target: Target::Gain,
+            amount: -0.5,
+        });
+
+        // A loud input ducks the gain
+        let loud = [0.8; 64];
+        for _ in 0..20 {
+            matrix.process(64, Some([&loud, &[0.0; 64]]));
+        }
+        assert!((matrix.source_value(follower) - 0.8).abs() < 1e-3);
+        assert!((matrix.value(&Target::Gain, 1.0) - 0.6).abs() < 1e-3);
+        assert_eq!(matrix.value(&Target::Cutoff, 0.3), 0.3);
+
+        // The level is released once the input stops
+        for _ in 0..20 {
+            matrix.process(64, None);
+        }
+        let level = matrix.source_value(follower);
+        assert!(level < 0.8 && level > 0.0);
+        matrix.unroute(follower, &Target::Gain);
+        assert_eq!(matrix.value(&Target::Gain, 1.0), 1.0);
+    }
+
+    /// A source which rises by one at the end of each interval, rendered at control rate.
+    struct Steps(f32);
+
+    impl ModSource for Steps {
+        fn set_sample_rate(&mut self, _sample_rate: u32) {}
+
+        fn reset(&mut self) {}
+
+        fn rate(&self) -> ModRate {
+            ModRate::Control
+        }
+
+        fn process(&mut self, _input: [&[f32]; 2], output: &mut [f32]) {
+            for value in output {
+                self.0 += 1.0;
+                *value = self.0;
+            }
+        }
+    }
+
+    #[test]
+    fn control_rate_sources_are_ramped_between_intervals() {
+        let mut matrix = ModMatrix::new(64);
+        let steps = matrix.add_source(Steps(0.0));
+        matrix.route(ModRoute {
+            source: steps,
+            target: Target::Cutoff,
+            amount: 2.0,
+        });
+        matrix.process(48, None);
+
+        let buffer = matrix.source_buffer(steps);
+        assert_eq!(buffer.len(), 48);
+        assert_eq!(
+            (buffer[15], buffer[31], buffer[39], buffer[47]),
+            (0.5, 1.0, 1.5, 2.0)
+        );
+        assert_eq!(matrix.value_at(&Target::Cutoff, 1.0, 31), 3.0);
+        assert_eq!(matrix.value(&Target::Cutoff, 1.0), 5.0);
+        let mut values = [0.0; 48];
+        matrix.values(&Target::Cutoff, 1.0, &mut values);
+        assert_eq!(values[15], 2.0);
+    }
+
+    #[test]
+    fn rms_follower_measures_the_root_mean_square() {
+        let mut follower = EnvelopeFollower::new(0.01, 0.01, FollowerMode::Rms);
+        let mut level = 0.0;
+        for n in 0..48000 {
+            level = follower.process_sample(if n % 2 == 0 { 1.0 } else { -1.0 } * 0.5);
+        }
+        assert!((level - 0.5).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,308 @@
+use crate::{EnvelopeFollower, FollowerMode, MidiEvent, ModSource, Note};
+
+/// Detects onsets, which are the sudden rises in level at the start of notes and drum hits,
+/// in an audio signal such as the audio input of the synth.
+///
+/// An onset is detected when a fast envelope of the signal rises above a slow envelope by more
+/// than the sensitivity, after which further onsets are ignored for the hold time, so that a
+/// single hit isn't detected several times.
+///
+/// As a [ModSource], the detector gives the strength of each onset at the sample it starts,
+/// and zero elsewhere, which can be used to retrigger envelopes.
+#[derive(Clone)]
+pub struct OnsetDetector {
+    /// Follows the level of the signal closely.
+    fast: EnvelopeFollower,
+    /// Follows the average level of the signal.
+    slow: EnvelopeFollower,
+    /// The ratio by which the fast level must exceed the slow level for an onset.
+    ratio: f32,
+    /// The level below which onsets are ignored, as a linear amplitude.
+    floor: f32,
+    /// The time in seconds after an onset during which further onsets are ignored.
+    hold: f32,
+    /// The hold time in samples.
+    hold_samples: usize,
+    /// The number of samples until onsets are detected again.
+    countdown: usize,
+}
+
+/// Turns onsets detected in an audio signal into notes, for example to play the synth in time
+/// with a drum loop.
+///
+/// Each block of the signal produces the MIDI events for the notes, which can be passed to
+/// [Synth::process_with_events](crate::Synth::process_with_events) to trigger the notes at the
+/// exact sample of each onset.
+pub struct OnsetTrigger {
+    /// The detector which finds the onsets.
+    pub detector: OnsetDetector,
+    /// The note which is played at each onset.
+    pub note: Note,
+    /// The MIDI channel of the notes.
+    pub channel: u8,
+    /// How the velocity of each note is chosen.
+    pub velocity: OnsetVelocity,
+    /// The time in seconds each note is held for.
+    pub gate: f32,
+    /// The sample rate in Hz.
+    sample_rate: u32,
+    /// The number of samples until the held note is released, if a note is held.
+    held: Option<usize>,
+    /// The events produced by the last block, paired with their offsets.
+    events: Vec<(usize, MidiEvent)>,
+}
+
+/// How the velocity of a note triggered by an [OnsetTrigger] is chosen.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum OnsetVelocity {
+    /// Every note has the same velocity.
+    Fixed(u8),
+    /// The velocity rises with the strength of the onset, from `min` for onsets at the floor
+    /// of the detector to `max` for onsets at full scale.
+    Strength { min: u8, max: u8 },
+}
+
+/// The attack and release time in seconds of the fast envelope of an [OnsetDetector], which
+/// follows the peaks of the signal instantly.
+const FAST_TIME: (f32, f32) = (0.0, 0.01);
+
+/// The attack and release time in seconds of the slow envelope of an [OnsetDetector].
+const SLOW_TIME: (f32, f32) = (0.03, 0.1);
+
+impl OnsetDetector {
+    /// Creates an onset detector.
+    ///
+    /// # Parameters
+    /// * `sensitivity` - The rise in level in decibels above the recent average which counts as an
+    ///   onset, where lower values detect quieter transients. 6 dB is a reasonable default.
+    /// * `floor` - The level in decibels below which onsets are ignored, so that noise isn't
+    ///   detected, such as -50 dB.
+    /// * `hold` - The time in seconds after an onset during which further onsets are ignored.
+    pub fn new(sensitivity: f32, floor: f32, hold: f32) -> Self {
+        let mut out = Self {
+            fast: EnvelopeFollower::new(FAST_TIME.0, FAST_TIME.1, FollowerMode::Peak),
+            slow: EnvelopeFollower::new(SLOW_TIME.0, SLOW_TIME.1, FollowerMode::Peak),
+            ratio: 10f32.powf(sensitivity / 20.0),
+            floor: 10f32.powf(floor / 20.0),
+            hold,
+            hold_samples: 0,
+            countdown: 0,
+        };
+        out.set_sample_rate(48000);
+        out
+    }
+
+    /// Analyses a single stereo frame of the signal.
+    ///
+    /// # Return
+    /// Returns the strength of the onset, as the linear amplitude of the signal,
+    /// if an onset starts at this frame.
+    pub fn process_frame(&mut self, left: f32, right: f32) -> Option<f32> {
+        let sample = if left.abs() > right.abs() {
+            left
+        } else {
+            right
+        };
+        let slow = self.slow.level();
+        let fast = self.fast.process_sample(sample);
+        self.slow.process_sample(sample);
+
+        if self.countdown > 0 {
+            self.countdown -= 1;
+            return None;
+        }
+        if fast > self.floor && fast > self.ratio * slow {
+            self.countdown = self.hold_samples;
+            Some(fast)
+        } else {
+            None
+        }
+    }
+
+    /// Analyses a block of the signal, calling `f` with the offset and strength of each onset.
+    pub fn process(&mut self, input: [&[f32]; 2], mut f: impl FnMut(usize, f32)) {
+        let [left, right] = input;
+        for (offset, (&l, &r)) in left.iter().zip(right).enumerate() {
+            if let Some(strength) = self.process_frame(l, r) {
+                f(offset, strength);
+            }
+        }
+    }
+}
+
+impl ModSource for OnsetDetector {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.fast.set_sample_rate(sample_rate);
+        self.slow.set_sample_rate(sample_rate);
+        self.hold_samples = (self.hold * sample_rate as f32) as usize;
+    }
+
+    fn reset(&mut self) {
+        self.fast.reset();
+        self.slow.reset();
+        self.countdown = 0;
+    }
+
+    fn process(&mut self, input: [&[f32]; 2], output: &mut [f32]) {
+        output.fill(0.0);
+        OnsetDetector::process(self, input, |offset, strength| output[offset] = strength);
+    }
+}
+
+impl OnsetTrigger {
+    /// Creates a trigger which plays a note at each onset, for blocks of up to `max_block_size`
+    /// samples.
+    ///
+    /// # Parameters
+    /// * `detector` - The detector which finds the onsets.
+    /// * `note` - The note which is played at each onset.
+    /// * `velocity` - How the velocity of each note is chosen.
+    /// * `gate` - The time in seconds each note is held for.
+    pub fn new(
+        max_block_size: usize,
+        detector: OnsetDetector,
+        note: Note,
+        velocity: OnsetVelocity,
+        gate: f32,
+    ) -> Self {
+        Self {
+            detector,
+            note,
+            channel: 0,
+            velocity,
+            gate,
+            sample_rate: 48000,
+            held: None,
+            // Each frame releases and triggers a note at most
+            events: Vec::with_capacity(2 * max_block_size),
+        }
+    }
+
+    /// Sets the sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.detector.set_sample_rate(sample_rate);
+    }
+
+    /// Releases the held note, if any, and resets the detector.
+    ///
+    /// # Return
+    /// Returns the event which releases the held note, if a note was held.
+    pub fn reset(&mut self) -> Option<MidiEvent> {
+        self.detector.reset();
+        self.held.take().map(|_| self.note_off())
+    }
+
+    /// Analyses a block of the signal, which must be no larger than the maximum block size.
+    ///
+    /// # Return
+    /// Returns the note on and note off events for the block, paired with their offsets
+    /// from the start of the block and sorted by offset.
+    pub fn process(&mut self, input: [&[f32]; 2]) -> &[(usize, MidiEvent)] {
+        let [left, right] = input;
+        debug_assert!(left.len() <= self.events.capacity() / 2);
+        self.events.clear();
+        let gate = ((self.gate * self.sample_rate as f32) as usize).max(1);
+
+        for (offset, (&l, &r)) in left.iter().zip(right).enumerate() {
+            if let Some(remaining) = &mut self.held {
+                *remaining -= 1;
+                if *remaining == 0 {
+                    self.held = None;
+                    self.events.push((offset, self.note_off()));
+                }
+            }
+
+            if let Some(strength) = self.detector.process_frame(l, r) {
+                if self.held.is_some() {
+                    self.events.push((offset, self.note_off()));
+                }
+                let velocity = match self.velocity {
+                    OnsetVelocity::Fixed(velocity) => velocity,
+                    OnsetVelocity::Strength { min, max } => {
+                        let floor = self.detector.floor;
+                        let x = ((strength - floor) / (1.0 - floor)).clamp(0.0, 1.0);
+                        (min as f32 + x * (max as f32 - min as f32)).round() as u8
+                    }
+                };
+                let velocity = velocity.clamp(1, 127);
+                let (channel, note) = (self.channel, self.note);
+                let event = MidiEvent::NoteOn {
+                    channel,
+                    note,
+                    velocity,
+                };
+                self.events.push((offset, event));
+                self.held = Some(gate);
+            }
+        }
+        &self.events
+    }
+
+    /// Creates the event which releases the note.
+    fn note_off(&self) -> MidiEvent {
+        MidiEvent::NoteOff {
+            channel: self.channel,
+            note: self.note,
+            velocity: 64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a signal with a decaying burst starting at each of the given offsets.
+    fn hits(len: usize, starts: &[usize], level: f32) -> Vec<f32> {
+        let mut signal = vec![0.0; len];
+        for &start in starts {
+            for (n, sample) in signal[start..].iter_mut().take(2400).enumerate() {
+                let sign = if n % 2 == 0 { 1.0 } else { -1.0 };
+                *sample = sign * level * (-(n as f32) / 480.0).exp();
+            }
+        }
+        signal
+    }
+
+    #[test]
+    fn onsets_trigger_notes_at_the_exact_sample() {
+        let detector = OnsetDetector::new(6.0, -50.0, 0.05);
+        let velocity = OnsetVelocity::Strength { min: 40, max: 120 };
+        let mut trigger = OnsetTrigger::new(4800, detector, 36, velocity, 0.01);
+        let signal = hits(4800, &[100, 3000], 0.5);
+        let events = trigger.process([&signal, &signal]).to_vec();
+
+        let note_ons: Vec<_> = events
+            .iter()
+            .filter_map(|&(offset, event)| match event {
+                MidiEvent::NoteOn {
+                    note: 36, velocity, ..
+                } => Some((offset, velocity)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(note_ons.len(), 2);
+        assert_eq!((note_ons[0].0, note_ons[1].0), (100, 3000));
+        assert!(note_ons[0].1 > 40 && note_ons[0].1 < 120);
+
+        // Each note is released after the gate time
+        assert!(events.contains(&(
+            100 + 480,
+            MidiEvent::NoteOff {
+                channel: 0,
+                note: 36,
+                velocity: 64
+            }
+        )));
+    }
+
+    #[test]
+    fn quiet_signals_are_ignored() {
+        let mut detector = OnsetDetector::new(6.0, -50.0, 0.05);
+        let signal = hits(4800, &[100], 0.001);
+        let mut count = 0;
+        detector.process([&signal, &signal], |_, _| count += 1);
+        assert_eq!(count, 0);
+    }
+}
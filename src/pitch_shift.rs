@@ -0,0 +1,164 @@
+use crate::Effect;
+use std::f32::consts::TAU;
+
+/// A pitch shifter, which transposes its input without changing its speed, such as to add an
+/// octave above a lead or to build a shimmer reverb.
+///
+/// The input is read back from a delay line by two taps whose delays sweep through a short
+/// window at a rate set by the shift, and which crossfade so that each one is silent as it
+/// jumps back to the other end of the window. Longer windows sound smoother on sustained notes,
+/// while shorter windows smear transients less.
+///
+/// # Parameters
+/// 0. Shift in semitones, from -24 to 24.
+/// 1. Window in seconds, from 0.01 to 0.1.
+pub struct PitchShifter {
+    /// The delay line of each channel, as a circular buffer.
+    delay: [Vec<f32>; 2],
+    /// The position in the delay line which the next sample is written to.
+    write: usize,
+    /// The position of the first tap in the window, from 0 to 1. The second tap is half a
+    /// window apart.
+    phase: f32,
+    /// The ratio of the output frequency to the input frequency.
+    ratio: f32,
+    /// The length of the window in seconds.
+    window: f32,
+    /// The sample rate in Hz.
+    sample_rate: u32,
+}
+
+/// The largest shift in semitones, in either direction.
+const MAX_SHIFT: f32 = 24.0;
+
+/// The range of the length of the window in seconds.
+const WINDOW_RANGE: (f32, f32) = (0.01, 0.1);
+
+impl PitchShifter {
+    /// Creates a pitch shifter.
+    ///
+    /// # Parameters
+    /// * `semitones` - The shift in semitones, which may be fractional or negative.
+    /// * `window` - The length of the window in seconds, such as 0.05.
+    pub fn new(semitones: f32, window: f32) -> Self {
+        let mut out = Self {
+            delay: [vec![], vec![]],
+            write: 0,
+            phase: 0.0,
+            ratio: 1.0,
+            window: 0.0,
+            sample_rate: 0,
+        };
+        out.set_shift(semitones);
+        out.set_window(window);
+        out.set_sample_rate(48000);
+        out
+    }
+
+    /// Sets the shift in semitones, which is clamped to two octaves in either direction.
+    pub fn set_shift(&mut self, semitones: f32) {
+        let semitones = semitones.clamp(-MAX_SHIFT, MAX_SHIFT);
+        self.ratio = 2f32.powf(semitones / 12.0);
+    }
+
+    /// Sets the length of the window in seconds, which is clamped between 10 and 100 ms.
+    pub fn set_window(&mut self, window: f32) {
+        self.window = window.clamp(WINDOW_RANGE.0, WINDOW_RANGE.1);
+    }
+
+    /// Reads a channel of the delay line, interpolating linearly between samples.
+    fn read(&self, channel: usize, delay: f32) -> f32 {
+        let buffer = &self.delay[channel];
+        let len = buffer.len();
+        let pos = self.write as f32 + len as f32 - delay;
+        let index = pos as usize;
+        let frac = pos - index as f32;
+        let a = buffer[index % len];
+        let b = buffer[(index + 1) % len];
+        a + frac * (b - a)
+    }
+}
+
+impl Effect for PitchShifter {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        if sample_rate != self.sample_rate {
+            self.sample_rate = sample_rate;
+            // Room for the longest window, plus the samples either side of the interpolation
+            let len = (WINDOW_RANGE.1 * sample_rate as f32).ceil() as usize + 3;
+            self.delay = [vec![0.0; len], vec![0.0; len]];
+            self.write = 0;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.delay.iter_mut().for_each(|channel| channel.fill(0.0));
+        self.phase = 0.0;
+    }
+
+    fn process(&mut self, buffer: [&mut [f32]; 2]) {
+        let [left, right] = buffer;
+        let window = self.window * self.sample_rate as f32;
+        // The delay shrinks by the excess of the ratio over 1 each sample
+        let step = (1.0 - self.ratio) / window;
+        let len = self.delay[0].len();
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            self.delay[0][self.write] = *l;
+            self.delay[1][self.write] = *r;
+
+            let mut out = [0.0; 2];
+            for offset in [0.0, 0.5] {
+                let phase = (self.phase + offset).fract();
+                // A raised cosine, so the gains of the two taps always sum to 1
+                let gain = 0.5 - 0.5 * (TAU * phase).cos();
+                let delay = 1.0 + phase * window;
+                out[0] += gain * self.read(0, delay);
+                out[1] += gain * self.read(1, delay);
+            }
+            [*l, *r] = out;
+
+            self.phase = (self.phase + step).rem_euclid(1.0);
+            self.write = (self.write + 1) % len;
+        }
+    }
+
+    fn num_params(&self) -> usize {
+        2
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.set_shift(value),
+            1 => self.set_window(value),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Estimates the frequency of a signal by counting its rising zero crossings.
+    fn frequency(signal: &[f32], sample_rate: f32) -> f32 {
+        let crossings = signal
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count();
+        crossings as f32 * sample_rate / signal.len() as f32
+    }
+
+    #[test]
+    fn shifts_pitch_by_octaves() {
+        for (semitones, expected) in [(12.0, 880.0), (-12.0, 220.0)] {
+            let mut shifter = PitchShifter::new(semitones, 0.05);
+            let tone: Vec<f32> = (0..48000)
+                .map(|n| (TAU * 440.0 * n as f32 / 48000.0).sin())
+                .collect();
+            let (mut left, mut right) = (tone.clone(), tone);
+            shifter.process([&mut left, &mut right]);
+            let freq = frequency(&left[4800..], 48000.0);
+            assert!((freq - expected).abs() < expected * 0.02, "{freq} Hz");
+        }
+    }
+}
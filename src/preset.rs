@@ -0,0 +1,857 @@
+use crate::synth::{random, RNG_SEED};
+use crate::SwapSender;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A named set of parameter values, along with a category and tags for browsing.
+///
+/// Patches are stored as text, with one `name = value` pair per line, preceded by optional
+/// `@name`, `@category` and `@tags` lines. Blank lines and lines starting with `#` are ignored:
+///
+/// ```text
+/// @name Warm Pad
+/// @category Pads
+/// @tags warm, slow
+/// cutoff = 0.45
+/// attack = 1.2
+/// ```
+///
+/// The meaning of each parameter is up to the application, which reads the values of a patch
+/// received on the audio thread and applies them to its voices and effects.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Patch {
+    /// The name of the patch.
+    pub name: String,
+    /// The category of the patch, such as "Bass" or "Pads", if any.
+    pub category: Option<String>,
+    /// The tags of the patch, such as "warm" or "plucked".
+    pub tags: Vec<String>,
+    /// The values of the parameters, ordered by name.
+    params: BTreeMap<String, f32>,
+}
+
+/// Error returned when a patch can't be parsed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PatchError {
+    /// The number of the line which couldn't be parsed, starting from 1.
+    pub line: usize,
+    /// A description of the problem.
+    pub message: String,
+}
+
+/// A collection of patches, typically loaded from a directory of patch files,
+/// which can be browsed by category and tag.
+///
+/// Patches are applied by sending them to the audio thread through a
+/// [SwapQueue](crate::SwapQueue), which swaps in the whole patch at once at a block boundary,
+/// so that a preset change never leaves the synth with a mixture of old and new values
+/// and never allocates on the audio thread.
+#[derive(Clone, Default)]
+pub struct PresetBank {
+    /// The patches in the bank.
+    patches: Vec<Patch>,
+    /// The files which couldn't be parsed when the bank was loaded.
+    errors: Vec<(PathBuf, PatchError)>,
+}
+
+/// A parameter whose value differs between two patches.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ParamChange {
+    /// The name of the parameter.
+    pub name: String,
+    /// The value in the first patch, or `None` if it doesn't have the parameter.
+    pub from: Option<f32>,
+    /// The value in the second patch, or `None` if it doesn't have the parameter.
+    pub to: Option<f32>,
+}
+
+/// One of the two states of a [PatchEditor].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Slot {
+    /// The first version.
+    A,
+    /// The second version.
+    B,
+}
+
+/// Holds a patch being edited, with the editing workflows that UIs commonly offer: comparing
+/// two versions of the patch (A/B), listing the parameters which differ between them or from the
+/// saved patch, reverting to the saved patch, and undoing and redoing edits.
+///
+/// The editor doesn't touch the audio thread. After each change, the application sends the
+/// [current](Self::current) patch through a [SwapQueue](crate::SwapQueue) to be heard.
+#[derive(Clone)]
+pub struct PatchEditor {
+    /// The two versions of the patch being compared.
+    slots: [Patch; 2],
+    /// The version being edited and heard.
+    active: Slot,
+    /// The patch as it was last saved, which is restored when reverting.
+    saved: Patch,
+    /// The states of the slots before each edit, for undoing it.
+    undo: Vec<(Slot, Patch)>,
+    /// The states of the slots before each undone edit, for redoing it.
+    redo: Vec<(Slot, Patch)>,
+    /// The parameter changed by the last edit, if consecutive changes to it are being
+    /// combined into a single step of the undo history, as when a knob is dragged.
+    last_edit: Option<(Slot, String)>,
+}
+
+/// The range of values a parameter can take, registered with a [ParamRegistry].
+#[derive(Clone, PartialEq, Debug)]
+pub struct ParamRange {
+    /// The name of the parameter.
+    pub name: String,
+    /// The lowest value of the parameter.
+    pub min: f32,
+    /// The highest value of the parameter.
+    pub max: f32,
+    /// The value of the parameter in a new patch.
+    pub default: f32,
+    /// The interval between values of the parameter, for parameters which take discrete values
+    /// such as waveform selections, or `None` if the parameter is continuous.
+    pub step: Option<f32>,
+}
+
+/// The parameters of a synth and the ranges of their values, which describe how patches can be
+/// generated and mutated.
+#[derive(Clone, Default)]
+pub struct ParamRegistry {
+    /// The registered parameters, in order of registration.
+    params: Vec<ParamRange>,
+}
+
+/// Generates random patches and mutates existing ones within the ranges of a [ParamRegistry],
+/// for exploring sounds.
+///
+/// The generator is seeded, so the same seed always produces the same sequence of patches.
+/// Locked parameters keep the value they have in the patch being randomised or mutated.
+#[derive(Clone)]
+pub struct PatchRandomizer {
+    /// The state of the random number generator.
+    rng: u32,
+    /// The names of the locked parameters.
+    locks: BTreeSet<String>,
+}
+
+/// The extension of the patch files loaded by [PresetBank::load_dir].
+const PATCH_EXTENSION: &str = "patch";
+
+impl Patch {
+    /// Creates a patch with the given name and no parameters.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Gets the value of a parameter, if the patch has one.
+    pub fn get(&self, param: &str) -> Option<f32> {
+        self.params.get(param).copied()
+    }
+
+    /// Sets the value of a parameter, adding it if the patch doesn't have it yet.
+    pub fn set(&mut self, param: &str, value: f32) {
+        match self.params.get_mut(param) {
+            Some(current) => *current = value,
+            None => {
+                self.params.insert(param.to_owned(), value);
+            }
+        }
+    }
+
+    /// Removes a parameter, returning its value if the patch had it.
+    pub fn remove(&mut self, param: &str) -> Option<f32> {
+        self.params.remove(param)
+    }
+
+    /// Iterates over the names and values of the parameters, ordered by name.
+    pub fn params(&self) -> impl Iterator<Item = (&str, f32)> + '_ {
+        self.params
+            .iter()
+            .map(|(name, &value)| (name.as_str(), value))
+    }
+
+    /// Returns `true` if the patch has the given tag.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    /// Parses a patch from its text format.
+    pub fn parse(text: &str) -> Result<Self, PatchError> {
+        let mut patch = Patch::default();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: &str| PatchError {
+                line: index + 1,
+                message: message.to_owned(),
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(field) = line.strip_prefix('@') {
+                let (key, value) = field.split_once(char::is_whitespace).unwrap_or((field, ""));
+                let value = value.trim();
+                match key {
+                    "name" => patch.name = value.to_owned(),
+                    "category" => patch.category = Some(value.to_owned()),
+                    "tags" => {
+                        patch.tags = value
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|tag| !tag.is_empty())
+                            .map(str::to_owned)
+                            .collect()
+                    }
+                    _ => return Err(error("Unknown field.")),
+                }
+                continue;
+            }
+
+            let (name, value) = line.split_once('=').ok_or_else(|| error("Expected `=`."))?;
+            let name = name.trim();
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(error("Invalid parameter name."));
+            }
+            let value = value
+                .trim()
+                .parse()
+                .map_err(|_| error("Invalid parameter value."))?;
+            patch.set(name, value);
+        }
+        Ok(patch)
+    }
+
+    /// Lists the parameters which differ between this patch and another, ordered by name.
+    pub fn diff(&self, other: &Patch) -> Vec<ParamChange> {
+        let names: BTreeSet<&String> = self.params.keys().chain(other.params.keys()).collect();
+        names
+            .into_iter()
+            .map(|name| ParamChange {
+                name: name.clone(),
+                from: self.get(name),
+                to: other.get(name),
+            })
+            .filter(|change| change.from != change.to)
+            .collect()
+    }
+
+    /// Writes the patch to a file in its text format.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "@name {}", self.name)?;
+        if let Some(category) = &self.category {
+            writeln!(f, "@category {category}")?;
+        }
+        if !self.tags.is_empty() {
+            writeln!(f, "@tags {}", self.tags.join(", "))?;
+        }
+        for (name, value) in self.params() {
+            writeln!(f, "{name} = {value}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+impl PresetBank {
+    /// Creates an empty bank.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads every patch file (with the `.patch` extension) in a directory, in order of their
+    /// file names. Files which can't be parsed are skipped, and reported by [errors](Self::errors).
+    ///
+    /// This reads from disk, so shouldn't be called from the audio thread.
+    pub fn load_dir(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut paths = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == PATCH_EXTENSION) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut bank = Self::new();
+        for path in paths {
+            match Patch::parse(&std::fs::read_to_string(&path)?) {
+                Ok(patch) => bank.patches.push(patch),
+                Err(error) => bank.errors.push((path, error)),
+            }
+        }
+        Ok(bank)
+    }
+
+    /// Gets the files which couldn't be parsed when the bank was loaded, with their errors.
+    pub fn errors(&self) -> &[(PathBuf, PatchError)] {
+        &self.errors
+    }
+
+    /// Adds a patch to the end of the bank, returning its index.
+    pub fn insert(&mut self, patch: Patch) -> usize {
+        self.patches.push(patch);
+        self.patches.len() - 1
+    }
+
+    /// Gets the number of patches in the bank.
+    pub fn len(&self) -> usize {
+        self.patches.len()
+    }
+
+    /// Returns `true` if the bank has no patches.
+    pub fn is_empty(&self) -> bool {
+        self.patches.is_empty()
+    }
+
+    /// Gets the patch at the given index, if there is one.
+    pub fn get(&self, index: usize) -> Option<&Patch> {
+        self.patches.get(index)
+    }
+
+    /// Gets the patches in the bank.
+    pub fn patches(&self) -> &[Patch] {
+        &self.patches
+    }
+
+    /// Finds the index of the first patch with the given name.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.patches.iter().position(|patch| patch.name == name)
+    }
+
+    /// Gets the categories of the patches, sorted and without duplicates.
+    pub fn categories(&self) -> Vec<&str> {
+        let mut categories: Vec<_> = self
+            .patches
+            .iter()
+            .filter_map(|p| p.category.as_deref())
+            .collect();
+        categories.sort_unstable();
+        categories.dedup();
+        categories
+    }
+
+    /// Gets the tags of the patches, sorted and without duplicates.
+    pub fn tags(&self) -> Vec<&str> {
+        let mut tags: Vec<_> = self
+            .patches
+            .iter()
+            .flat_map(|p| &p.tags)
+            .map(String::as_str)
+            .collect();
+        tags.sort_unstable();
+        tags.dedup();
+        tags
+    }
+
+    /// Iterates over the indices and patches in the given category.
+    pub fn in_category<'a>(
+        &'a self,
+        category: &'a str,
+    ) -> impl Iterator<Item = (usize, &'a Patch)> {
+        let matches = move |patch: &Patch| patch.category.as_deref() == Some(category);
+        self.patches
+            .iter()
+            .enumerate()
+            .filter(move |(_, patch)| matches(patch))
+    }
+
+    /// Iterates over the indices and patches with the given tag.
+    pub fn with_tag<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = (usize, &'a Patch)> {
+        self.patches
+            .iter()
+            .enumerate()
+            .filter(move |(_, patch)| patch.has_tag(tag))
+    }
+
+    /// Sends a copy of the patch at the given index to the audio thread, to be swapped in at the
+    /// start of the next block.
+    ///
+    /// This allocates memory, so shouldn't be called from the audio thread.
+    ///
+    /// # Return
+    /// Returns `false` if there is no patch at the index, or the queue is full.
+    pub fn apply(&self, index: usize, sender: &mut SwapSender<Patch>) -> bool {
+        match self.patches.get(index) {
+            Some(patch) => sender.send(patch.clone()).is_ok(),
+            None => false,
+        }
+    }
+}
+
+impl Slot {
+    /// Gets the other slot.
+    pub fn other(self) -> Slot {
+        match self {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+
+    /// Gets the index of the slot.
+    fn index(self) -> usize {
+        match self {
+            Slot::A => 0,
+            Slot::B => 1,
+        }
+    }
+}
+
+impl PatchEditor {
+    /// Starts editing a patch, which is placed in both slots and treated as saved.
+    pub fn new(patch: Patch) -> Self {
+        Self {
+            slots: [patch.clone(), patch.clone()],
+            active: Slot::A,
+            saved: patch,
+            undo: vec![],
+            redo: vec![],
+            last_edit: None,
+        }
+    }
+
+    /// Gets the version of the patch being edited and heard.
+    pub fn current(&self) -> &Patch {
+        self.slot(self.active)
+    }
+
+    /// Gets the version of the patch in the given slot.
+    pub fn slot(&self, slot: Slot) -> &Patch {
+        &self.slots[slot.index()]
+    }
+
+    /// Gets the slot being edited and heard.
+    pub fn active(&self) -> Slot {
+        self.active
+    }
+
+    /// Switches to editing and hearing the given slot.
+    pub fn switch(&mut self, slot: Slot) {
+        self.active = slot;
+        self.last_edit = None;
+    }
+
+    /// Switches to the other slot, for comparing the two versions.
+    pub fn toggle(&mut self) {
+        self.switch(self.active.other());
+    }
+
+    /// Sets the value of a parameter in the current version.
+    ///
+    /// Consecutive changes to the same parameter are undone as a single step.
+    pub fn set(&mut self, param: &str, value: f32) {
+        let index = self.active.index();
+        if self.slots[index].get(param) == Some(value) {
+            return;
+        }
+        let continued = self
+            .last_edit
+            .as_ref()
+            .is_some_and(|(slot, name)| *slot == self.active && name == param);
+        if !continued {
+            self.push_undo();
+            self.last_edit = Some((self.active, param.to_owned()));
+        }
+        self.slots[index].set(param, value);
+    }
+
+    /// Replaces the current version with the given patch, such as a randomised one.
+    pub fn replace(&mut self, patch: Patch) {
+        self.push_undo();
+        self.slots[self.active.index()] = patch;
+    }
+
+    /// Copies the current version into the other slot, so that further edits can be compared
+    /// with it.
+    pub fn copy_to_other(&mut self) {
+        let other = self.active.other();
+        self.undo.push((other, self.slot(other).clone()));
+        self.redo.clear();
+        self.last_edit = None;
+        self.slots[other.index()] = self.current().clone();
+    }
+
+    /// Lists the parameters which differ between the A and B versions.
+    pub fn compare(&self) -> Vec<ParamChange> {
+        self.slot(Slot::A).diff(self.slot(Slot::B))
+    }
+
+    /// Lists the parameters of the current version which have changed since it was saved.
+    pub fn changes(&self) -> Vec<ParamChange> {
+        self.saved.diff(self.current())
+    }
+
+    /// Returns `true` if the current version differs from the saved patch.
+    pub fn is_modified(&self) -> bool {
+        self.saved != *self.current()
+    }
+
+    /// Restores the current version to the saved patch, which can be undone.
+    pub fn revert(&mut self) {
+        if self.is_modified() {
+            self.replace(self.saved.clone());
+        }
+    }
+
+    /// Marks the current version as saved, returning it so that it can be written to disk.
+    pub fn save(&mut self) -> &Patch {
+        self.saved = self.current().clone();
+        &self.saved
+    }
+
+    /// Gets the patch as it was last saved.
+    pub fn saved(&self) -> &Patch {
+        &self.saved
+    }
+
+    /// Undoes the most recent edit, switching to the slot it was made in.
+    ///
+    /// # Return
+    /// Returns `false` if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let undone = Self::restore(
+            &mut self.undo,
+            &mut self.redo,
+            &mut self.slots,
+            &mut self.active,
+        );
+        self.last_edit = None;
+        undone
+    }
+
+    /// Redoes the most recently undone edit, switching to the slot it was made in.
+    ///
+    /// # Return
+    /// Returns `false` if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let redone = Self::restore(
+            &mut self.redo,
+            &mut self.undo,
+            &mut self.slots,
+            &mut self.active,
+        );
+        self.last_edit = None;
+        redone
+    }
+
+    /// Records the state of the current slot before an edit, forgetting any undone edits.
+    fn push_undo(&mut self) {
+        self.undo.push((self.active, self.current().clone()));
+        self.redo.clear();
+        self.last_edit = None;
+    }
+
+    /// Restores the most recent state from one history, recording the replaced state in the other.
+    fn restore(
+        from: &mut Vec<(Slot, Patch)>,
+        to: &mut Vec<(Slot, Patch)>,
+        slots: &mut [Patch; 2],
+        active: &mut Slot,
+    ) -> bool {
+        let Some((slot, patch)) = from.pop() else {
+            return false;
+        };
+        let replaced = std::mem::replace(&mut slots[slot.index()], patch);
+        to.push((slot, replaced));
+        *active = slot;
+        true
+    }
+}
+
+impl ParamRange {
+    /// Creates a continuous range of values.
+    pub fn new(name: impl Into<String>, min: f32, max: f32, default: f32) -> Self {
+        Self {
+            name: name.into(),
+            min,
+            max,
+            default,
+            step: None,
+        }
+    }
+
+    /// Restricts the parameter to multiples of the given step above the minimum.
+    pub fn with_step(self, step: f32) -> Self {
+        Self {
+            step: Some(step),
+            ..self
+        }
+    }
+
+    /// Clamps a value into the range, rounding it to the nearest step.
+    pub fn clamp(&self, value: f32) -> f32 {
+        let value = value.clamp(self.min, self.max);
+        match self.step {
+            Some(step) if step > 0.0 => {
+                let value = self.min + ((value - self.min) / step).round() * step;
+                value.min(self.max)
+            }
+            _ => value,
+        }
+    }
+}
+
+impl ParamRegistry {
+    /// Creates a registry with no parameters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a parameter, replacing any parameter with the same name.
+    pub fn register(&mut self, range: ParamRange) {
+        assert!(range.min <= range.max, "Parameter range must not be empty.");
+        match self
+            .params
+            .iter_mut()
+            .find(|param| param.name == range.name)
+        {
+            Some(param) => *param = range,
+            None => self.params.push(range),
+        }
+    }
+
+    /// Gets the range of the parameter with the given name, if it is registered.
+    pub fn get(&self, name: &str) -> Option<&ParamRange> {
+        self.params.iter().find(|param| param.name == name)
+    }
+
+    /// Gets the registered parameters, in order of registration.
+    pub fn params(&self) -> &[ParamRange] {
+        &self.params
+    }
+
+    /// Creates a patch with the default value of every parameter.
+    pub fn default_patch(&self, name: impl Into<String>) -> Patch {
+        let mut patch = Patch::new(name);
+        for param in &self.params {
+            patch.set(&param.name, param.clamp(param.default));
+        }
+        patch
+    }
+}
+
+impl PatchRandomizer {
+    /// Creates a randomizer with the given seed and no locked parameters.
+    pub fn new(seed: u32) -> Self {
+        // The xorshift generator never leaves a state of zero
+        let rng = match seed ^ RNG_SEED {
+            0 => RNG_SEED,
+            rng => rng,
+        };
+        Self {
+            rng,
+            locks: BTreeSet::new(),
+        }
+    }
+
+    /// Locks a parameter, so that it isn't changed.
+    pub fn lock(&mut self, param: &str) {
+        self.locks.insert(param.to_owned());
+    }
+
+    /// Unlocks a parameter, so that it is changed again.
+    pub fn unlock(&mut self, param: &str) {
+        self.locks.remove(param);
+    }
+
+    /// Returns `true` if the parameter is locked.
+    pub fn is_locked(&self, param: &str) -> bool {
+        self.locks.contains(param)
+    }
+
+    /// Creates a copy of a patch with a random value for every registered parameter which
+    /// isn't locked.
+    pub fn randomize(&mut self, registry: &ParamRegistry, patch: &Patch) -> Patch {
+        let mut out = patch.clone();
+        for param in registry.params() {
+            if !self.is_locked(&param.name) {
+                let x = random(&mut self.rng);
+                out.set(
+                    &param.name,
+                    param.clamp(param.min + x * (param.max - param.min)),
+                );
+            }
+        }
+        out
+    }
+
+    /// Creates a copy of a patch with every registered parameter which isn't locked moved
+    /// randomly from its value.
+    ///
+    /// # Parameters
+    /// * `amount` - The furthest each parameter can move, as a proportion of its range,
+    ///   where 0 leaves the patch unchanged and 1 can move a parameter across its whole range.
+    pub fn mutate(&mut self, registry: &ParamRegistry, patch: &Patch, amount: f32) -> Patch {
+        let mut out = patch.clone();
+        for param in registry.params() {
+            if !self.is_locked(&param.name) {
+                let value = patch.get(&param.name).unwrap_or(param.default);
+                let offset = amount * (2.0 * random(&mut self.rng) - 1.0) * (param.max - param.min);
+                out.set(&param.name, param.clamp(value + offset));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SwapQueue;
+
+    const PAD: &str = "
+        # A slow pad
+        @name Warm Pad
+        @category Pads
+        @tags warm, slow
+        cutoff = 0.45
+        attack = 1.2
+    ";
+
+    #[test]
+    fn patches_are_parsed_and_written() {
+        let patch = Patch::parse(PAD).unwrap();
+        assert_eq!(patch.name, "Warm Pad");
+        assert_eq!(patch.category.as_deref(), Some("Pads"));
+        assert_eq!(patch.tags, ["warm", "slow"]);
+        assert_eq!(
+            patch.params().collect::<Vec<_>>(),
+            [("attack", 1.2), ("cutoff", 0.45)]
+        );
+        assert_eq!(Patch::parse(&patch.to_string()), Ok(patch));
+
+        let error = Patch::parse("@name Broken\ncutoff 0.5").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(Patch::parse("cutoff = loud").is_err());
+    }
+
+    #[test]
+    fn bank_is_loaded_from_a_directory_and_applied() {
+        let dir = std::env::temp_dir().join(format!("reson-presets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("b.patch"), PAD).unwrap();
+        std::fs::write(
+            dir.join("a.patch"),
+            "@name Bass\n@category Bass\n@tags warm\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("c.patch"), "cutoff").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a patch").unwrap();
+        let bank = PresetBank::load_dir(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let bank = bank.unwrap();
+
+        assert_eq!(bank.len(), 2);
+        assert_eq!(bank.find("Warm Pad"), Some(1));
+        assert_eq!(bank.errors().len(), 1);
+        assert_eq!(bank.categories(), ["Bass", "Pads"]);
+        assert_eq!(bank.tags(), ["slow", "warm"]);
+        assert_eq!(bank.with_tag("warm").count(), 2);
+        assert_eq!(
+            bank.in_category("Pads")
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>(),
+            [1]
+        );
+
+        let (mut sender, mut receiver) = SwapQueue::new(4).split();
+        assert!(bank.apply(1, &mut sender));
+        assert!(!bank.apply(2, &mut sender));
+        assert!(receiver.receive());
+        assert_eq!(
+            receiver.current().and_then(|patch| patch.get("cutoff")),
+            Some(0.45)
+        );
+    }
+
+    #[test]
+    fn editor_compares_reverts_and_undoes() {
+        let mut editor = PatchEditor::new(Patch::parse(PAD).unwrap());
+        assert!(!editor.is_modified());
+
+        // Dragging a knob is undone in a single step
+        for cutoff in [0.5, 0.6, 0.7] {
+            editor.set("cutoff", cutoff);
+        }
+        editor.set("drive", 0.2);
+        assert_eq!(editor.changes().len(), 2);
+        assert!(editor.undo());
+        assert!(editor.undo());
+        assert!(!editor.is_modified());
+        assert!(editor.redo());
+        assert_eq!(editor.current().get("cutoff"), Some(0.7));
+
+        // A/B comparison
+        editor.copy_to_other();
+        editor.toggle();
+        editor.set("attack", 0.1);
+        let changes = editor.compare();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            changes[0],
+            ParamChange {
+                name: "attack".into(),
+                from: Some(1.2),
+                to: Some(0.1)
+            }
+        );
+
+        // Reverting can be undone
+        editor.revert();
+        assert!(!editor.is_modified());
+        assert!(editor.undo());
+        assert_eq!(editor.current().get("attack"), Some(0.1));
+        editor.save();
+        assert!(!editor.is_modified());
+        assert_eq!(editor.saved().get("attack"), Some(0.1));
+    }
+
+    #[test]
+    fn randomizer_is_reproducible_and_respects_ranges_and_locks() {
+        let mut registry = ParamRegistry::new();
+        registry.register(ParamRange::new("cutoff", 20.0, 20000.0, 1000.0));
+        registry.register(ParamRange::new("waveform", 0.0, 3.0, 0.0).with_step(1.0));
+        registry.register(ParamRange::new("attack", 0.0, 2.0, 0.01));
+        let base = registry.default_patch("Init");
+
+        let mut randomizer = PatchRandomizer::new(7);
+        randomizer.lock("attack");
+        let patches: Vec<_> = (0..20)
+            .map(|_| randomizer.randomize(&registry, &base))
+            .collect();
+        for patch in &patches {
+            let cutoff = patch.get("cutoff").unwrap();
+            assert!((20.0..=20000.0).contains(&cutoff));
+            assert_eq!(patch.get("waveform").unwrap().fract(), 0.0);
+            assert_eq!(patch.get("attack"), Some(0.01));
+        }
+
+        // The same seed produces the same patches
+        let mut again = PatchRandomizer::new(7);
+        again.lock("attack");
+        assert_eq!(again.randomize(&registry, &base), patches[0]);
+
+        // Mutations stay within the amount
+        let mutated = randomizer.mutate(&registry, &base, 0.1);
+        let cutoff = mutated.get("cutoff").unwrap();
+        assert!((cutoff - 1000.0).abs() <= 0.1 * 19980.0);
+        assert_eq!(randomizer.mutate(&registry, &base, 0.0), base);
+    }
+}
@@ -0,0 +1,442 @@
+use crate::MidiEvent;
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A bounded, lock-free queue for sending MIDI events to the audio thread.
+///
+/// The queue is split into a [MidiProducer], which is moved to the UI or MIDI thread,
+/// and a [MidiConsumer], which is drained by the audio thread before each call to `process`.
+/// Neither end allocates or blocks after the queue has been created.
+///
+/// When the queue is full, newly pushed events are dropped and handed back to the producer
+/// (drop-newest), so events which are already queued, such as note-offs, are never lost.
+///
+/// Dropping the oldest event instead isn't supported. Making room for a new event would
+/// require the producer to advance the read position, racing with a consumer which may be
+/// reading that slot at the same moment, and so would need a compare-and-swap on every pop,
+/// or a lock. It would also silently discard note-offs and leave notes hanging, which is worse
+/// than losing the newest event, which the producer gets back and can retry or report.
+pub struct MidiQueue {
+    inner: Arc<RingBuffer<MidiEvent>>,
+}
+
+/// The sending end of a [MidiQueue].
+pub struct MidiProducer {
+    inner: Arc<RingBuffer<MidiEvent>>,
+}
+
+/// The receiving end of a [MidiQueue].
+pub struct MidiConsumer {
+    inner: Arc<RingBuffer<MidiEvent>>,
+}
+
+/// The storage shared by both ends of a single-producer, single-consumer queue.
+pub(crate) struct RingBuffer<T> {
+    /// The slots holding queued values.
+    buffer: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// The number of values which have been popped, modulo twice the capacity,
+    /// which only the consumer writes to.
+    head: AtomicUsize,
+    /// The number of values which have been pushed, modulo twice the capacity,
+    /// which only the producer writes to.
+    tail: AtomicUsize,
+}
+
+// SAFETY: Each slot is written only by the single producer before `tail` is published,
+// and read only by the single consumer before `head` is published, so a slot is never
+// accessed from two threads at once. Values are moved between threads, so `T` must be `Send`.
+unsafe impl<T: Send> Sync for RingBuffer<T> {}
+
+/// A queue for handing whole values, such as patches or samples, to the audio thread.
+///
+/// The queue is split into a [SwapSender], which boxes each value and sends it, and a
+/// [SwapReceiver], which swaps in the newest value at a block boundary. Values which are replaced
+/// are handed back to the sender, which drops them the next time it sends, so the audio thread
+/// never allocates or frees memory.
+pub struct SwapQueue<T> {
+    inner: Arc<SwapInner<T>>,
+}
+
+/// The sending end of a [SwapQueue].
+pub struct SwapSender<T> {
+    inner: Arc<SwapInner<T>>,
+}
+
+/// The receiving end of a [SwapQueue], used by the audio thread.
+pub struct SwapReceiver<T> {
+    inner: Arc<SwapInner<T>>,
+    /// The value which was most recently received, if any.
+    current: Option<Box<T>>,
+}
+
+/// The storage shared by both ends of a [SwapQueue].
+struct SwapInner<T> {
+    /// The values sent to the receiver.
+    values: RingBuffer<Box<T>>,
+    /// The values replaced by the receiver, waiting to be dropped by the sender.
+    garbage: RingBuffer<Box<T>>,
+}
+
+impl MidiQueue {
+    /// Creates a queue which can hold up to `capacity` events.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "MIDI queue must have a capacity of at least one.");
+        Self {
+            inner: Arc::new(RingBuffer::new(capacity)),
+        }
+    }
+
+    /// Splits the queue into its producer and consumer.
+    pub fn split(self) -> (MidiProducer, MidiConsumer) {
+        let producer = MidiProducer { inner: self.inner.clone() };
+        let consumer = MidiConsumer { inner: self.inner };
+        (producer, consumer)
+    }
+}
+
+impl MidiProducer {
+    /// Pushes an event onto the queue.
+    ///
+    /// # Return
+    /// Returns the event back as an error if the queue is full, in which case it is dropped.
+    pub fn push(&mut self, event: MidiEvent) -> Result<(), MidiEvent> {
+        // SAFETY: Each queue has a single producer, which can't be cloned, and it is borrowed
+        // mutably, so only one thread can push at a time.
+        unsafe { self.inner.push(event) }
+    }
+
+    /// Gets the number of events which can be pushed before the queue is full.
+    pub fn free_len(&self) -> usize {
+        self.inner.capacity() - self.inner.len()
+    }
+}
+
+impl MidiConsumer {
+    /// Pops the oldest event from the queue, if there is one.
+    pub fn pop(&mut self) -> Option<MidiEvent> {
+        // SAFETY: Each queue has a single consumer, which can't be cloned, and it is borrowed
+        // mutably, so only one thread can pop at a time.
+        unsafe { self.inner.pop() }
+    }
+
+    /// Gets the number of events waiting in the queue.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns `true` if there are no events waiting in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Iterator for MidiConsumer {
+    type Item = MidiEvent;
+
+    fn next(&mut self) -> Option<MidiEvent> {
+        self.pop()
+    }
+}
+
+impl<T> SwapQueue<T> {
+    /// Creates a queue which can hold up to `capacity` values which haven't been received.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Swap queue must have a capacity of at least one.");
+        Self {
+            inner: Arc::new(SwapInner {
+                values: RingBuffer::new(capacity),
+                garbage: RingBuffer::new(capacity + 1),
+            }),
+        }
+    }
+
+    /// Splits the queue into its sender and receiver.
+    pub fn split(self) -> (SwapSender<T>, SwapReceiver<T>) {
+        let sender = SwapSender { inner: self.inner.clone() };
+        let receiver = SwapReceiver { inner: self.inner, current: None };
+        (sender, receiver)
+    }
+}
+
+impl<T> SwapSender<T> {
+    /// Sends a value to the receiver, after dropping the values it has replaced.
+    ///
+    /// This allocates memory, so shouldn't be called from the audio thread.
+    ///
+    /// # Return
+    /// Returns the value back as an error if the queue is full.
+    pub fn send(&mut self, value: T) -> Result<(), T> {
+        self.collect();
+        // SAFETY: Each queue has a single sender, which can't be cloned, and it is borrowed
+        // mutably, so only one thread can push at a time.
+        unsafe { self.inner.values.push(Box::new(value)) }.map_err(|value| *value)
+    }
+
+    /// Drops the values which have been replaced by the receiver.
+    pub fn collect(&mut self) {
+        // SAFETY: The sender is the only end which pops garbage, and it is borrowed mutably.
+        while unsafe { self.inner.garbage.pop() }.is_some() {}
+    }
+}
+
+impl<T> SwapReceiver<T> {
+    /// Swaps in the newest value which has been sent, skipping any older ones.
+    ///
+    /// # Return
+    /// Returns `true` if a new value was received.
+    pub fn receive(&mut self) -> bool {
+        let mut received = false;
+        // SAFETY: The receiver is the only end which pops values, and it is borrowed mutably.
+        while let Some(value) = unsafe { self.inner.values.pop() } {
+            if let Some(old) = self.current.replace(value) {
+                // SAFETY: The receiver is the only end which pushes garbage, and it is borrowed
+                // mutably. Each value is only returned after being popped from `values`, and
+                // the sender empties the garbage before every send, so between two sends no more
+                // values can be returned than `values` held, plus the one value sent after the
+                // garbage was emptied, and there is always room.
+                let returned = unsafe { self.inner.garbage.push(old) };
+                debug_assert!(returned.is_ok());
+            }
+            received = true;
+        }
+        received
+    }
+
+    /// Gets the value which was most recently received, if any.
+    pub fn current(&self) -> Option<&T> {
+        self.current.as_deref()
+    }
+}
+
+impl<T> RingBuffer<T> {
+    /// Creates a ring buffer which can hold up to `capacity` values.
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            buffer: (0..capacity)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Pushes a value, handing it back if the buffer is full.
+    ///
+    /// # Safety
+    /// Only one thread may push values at a time.
+    pub(crate) unsafe fn push(&self, value: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if self.distance(head, tail) == self.buffer.len() {
+            return Err(value);
+        }
+
+        // SAFETY: The buffer isn't full, so the slot at `tail` holds no value: it was either
+        // never written, or its value was moved out by the consumer before it published the
+        // `head` loaded above. The consumer won't read the slot until `tail` is advanced,
+        // and only one thread pushes at a time, so nothing else accesses it.
+        unsafe { (*self.buffer[self.slot(tail)].get()).write(value) };
+        self.tail.store(self.advance(tail), Ordering::Release);
+        Ok(())
+    }
+
+    /// Pops the oldest value, if there is one.
+    ///
+    /// # Safety
+    /// Only one thread may pop values at a time.
+    pub(crate) unsafe fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+
+        // SAFETY: The buffer isn't empty, so the slot at `head` was initialised by the producer
+        // before it published the `tail` loaded above. The producer won't write to the slot
+        // until `head` is advanced past it, and only one thread pops at a time, so the value is
+        // moved out exactly once and the slot is treated as uninitialised from then on.
+        let value = unsafe { (*self.buffer[self.slot(head)].get()).assume_init_read() };
+        self.head.store(self.advance(head), Ordering::Release);
+        Some(value)
+    }
+
+    /// Gets the maximum number of values the buffer can hold.
+    pub(crate) fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Gets the number of values waiting in the buffer.
+    pub(crate) fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        self.distance(head, tail)
+    }
+
+    /// Gets the index of the slot for the given position.
+    ///
+    /// Positions are kept modulo twice the capacity, so that a full buffer can be told apart
+    /// from an empty one without the index jumping when a counter overflows.
+    fn slot(&self, position: usize) -> usize {
+        let capacity = self.buffer.len();
+        if position >= capacity {
+            position - capacity
+        } else {
+            position
+        }
+    }
+
+    /// Gets the position following the given one.
+    fn advance(&self, position: usize) -> usize {
+        if position + 1 == 2 * self.buffer.len() {
+            0
+        } else {
+            position + 1
+        }
+    }
+
+    /// Gets the number of values between the `head` and `tail` positions.
+    fn distance(&self, head: usize, tail: usize) -> usize {
+        if tail >= head {
+            tail - head
+        } else {
+            tail + 2 * self.buffer.len() - head
+        }
+    }
+}
+
+impl<T> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        // SAFETY: The buffer is borrowed mutably, so neither end can be pushing or popping.
+        while unsafe { self.pop() }.is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    /// A value which counts how many times it has been dropped.
+    struct Counted(Arc<AtomicUsize>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn full_and_empty_boundaries() {
+        let buffer = RingBuffer::new(3);
+        unsafe {
+            assert_eq!(buffer.pop(), None);
+            assert_eq!(buffer.len(), 0);
+            for value in 0..3 {
+                assert_eq!(buffer.push(value), Ok(()));
+            }
+            assert_eq!(buffer.len(), 3);
+            assert_eq!(buffer.push(3), Err(3));
+
+            // Popping a single value makes room for exactly one more
+            assert_eq!(buffer.pop(), Some(0));
+            assert_eq!(buffer.push(4), Ok(()));
+            assert_eq!(buffer.push(5), Err(5));
+            assert_eq!([buffer.pop(), buffer.pop(), buffer.pop()], [Some(1), Some(2), Some(4)]);
+            assert_eq!(buffer.pop(), None);
+        }
+    }
+
+    #[test]
+    fn positions_wrap_around() {
+        // A capacity which doesn't divide the range of the positions
+        let buffer = RingBuffer::new(5);
+        let mut next = 0;
+        for round in 0..100 {
+            let count = round % 6;
+            unsafe {
+                for value in next..next + count {
+                    let expected = if value - next < 5 { Ok(()) } else { Err(value) };
+                    assert_eq!(buffer.push(value), expected);
+                }
+                for value in next..next + count.min(5) {
+                    assert_eq!(buffer.pop(), Some(value));
+                }
+                assert_eq!(buffer.pop(), None);
+            }
+            next += count;
+        }
+    }
+
+    #[test]
+    fn remaining_values_are_dropped_with_the_buffer() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let buffer = RingBuffer::new(4);
+        unsafe {
+            for _ in 0..4 {
+                assert!(buffer.push(Counted(drops.clone())).is_ok());
+            }
+            drop(buffer.push(Counted(drops.clone())));
+            assert_eq!(drops.load(Ordering::Relaxed), 1);
+            drop(buffer.pop());
+            assert_eq!(drops.load(Ordering::Relaxed), 2);
+        }
+        drop(buffer);
+        assert_eq!(drops.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn events_arrive_in_order_across_threads() {
+        const COUNT: u32 = 100_000;
+        let (mut producer, mut consumer) = MidiQueue::new(7).split();
+        let thread = std::thread::spawn(move || {
+            for value in 0..COUNT {
+                let mut event = MidiEvent::PitchBend { channel: 0, value: (value % 16384) as u16 };
+                while let Err(rejected) = producer.push(event) {
+                    event = rejected;
+                    std::thread::yield_now();
+                }
+            }
+        });
+        let mut expected = 0;
+        while expected < COUNT {
+            match consumer.pop() {
+                Some(MidiEvent::PitchBend { value, .. }) => {
+                    assert_eq!(value as u32, expected % 16384);
+                    expected += 1;
+                }
+                Some(event) => panic!("Unexpected event {event:?}"),
+                None => std::thread::yield_now(),
+            }
+        }
+        thread.join().unwrap();
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn replaced_values_are_dropped_by_the_sender() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut sender, mut receiver) = SwapQueue::new(2).split();
+        assert!(!receiver.receive());
+        assert!(receiver.current().is_none());
+
+        // Only the newest of several values is swapped in
+        for _ in 0..2 {
+            assert!(sender.send(Counted(drops.clone())).is_ok());
+        }
+        assert!(sender.send(Counted(drops.clone())).is_err());
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+        assert!(receiver.receive());
+        assert!(receiver.current().is_some());
+
+        // The skipped value is only dropped once the sender collects it
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+        sender.collect();
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+        assert!(sender.send(Counted(drops.clone())).is_ok());
+        assert!(receiver.receive());
+        sender.collect();
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+    }
+}
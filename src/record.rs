@@ -0,0 +1,262 @@
+use crate::queue::RingBuffer;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A bounded, lock-free queue for recording the output of the audio thread to disk.
+///
+/// The queue is split into a [RecordTap], which copies each processed block into the queue on
+/// the audio thread, and a [RecordConsumer], which is drained into a [WavWriter] by a background
+/// thread. The tap never allocates, blocks or touches the disk.
+///
+/// When a block doesn't fit in the queue because the background thread has fallen behind,
+/// the whole block is dropped, and its frames are counted by [RecordConsumer::dropped].
+///
+/// Recordings can only be written as WAV files. FLAC output is not implemented, but the frames
+/// can be popped from the consumer and passed to an encoder from another crate.
+pub struct RecordQueue {
+    inner: Arc<RecordInner>,
+}
+
+/// The sending end of a [RecordQueue], used by the audio thread.
+pub struct RecordTap {
+    inner: Arc<RecordInner>,
+}
+
+/// The receiving end of a [RecordQueue].
+pub struct RecordConsumer {
+    inner: Arc<RecordInner>,
+}
+
+struct RecordInner {
+    /// The queued stereo frames.
+    frames: RingBuffer<[f32; 2]>,
+    /// The number of frames which were dropped because the queue was full.
+    dropped: AtomicUsize,
+}
+
+/// Writes stereo audio to a WAV file, as 32-bit floating point samples.
+///
+/// The sizes in the header are filled in by [finish](Self::finish), which should be called once
+/// recording is complete. Until then, the file is incomplete. The sizes are 32-bit, so a file
+/// can't grow beyond 4 GiB, after which [write_frame](Self::write_frame) returns an error.
+/// This is the only file format supported, as there is no FLAC writer.
+pub struct WavWriter<W: Write + Seek> {
+    /// The destination of the file.
+    writer: W,
+    /// The number of frames written so far.
+    frames: u32,
+}
+
+/// The number of bytes in the header of a WAV file written by [WavWriter].
+const WAV_HEADER_LEN: u32 = 58;
+
+/// The number of bytes in each stereo frame of 32-bit samples.
+const WAV_FRAME_LEN: u32 = 8;
+
+impl RecordQueue {
+    /// Creates a queue which can hold up to `capacity` stereo frames.
+    ///
+    /// The capacity should hold at least several blocks of audio, and more if the background
+    /// thread drains the queue infrequently.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Record queue must have a capacity of at least one.");
+        Self {
+            inner: Arc::new(RecordInner {
+                frames: RingBuffer::new(capacity),
+                dropped: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Splits the queue into its tap and consumer.
+    pub fn split(self) -> (RecordTap, RecordConsumer) {
+        let tap = RecordTap { inner: self.inner.clone() };
+        let consumer = RecordConsumer { inner: self.inner };
+        (tap, consumer)
+    }
+}
+
+impl RecordTap {
+    /// Copies a block of stereo audio into the queue, dropping the whole block if it doesn't fit.
+    pub fn write(&mut self, output: [&[f32]; 2]) {
+        let [left, right] = output;
+        debug_assert!(left.len() == right.len());
+
+        let frames = &self.inner.frames;
+        if frames.capacity() - frames.len() < left.len() {
+            self.inner.dropped.fetch_add(left.len(), Ordering::Relaxed);
+            return;
+        }
+        for (&l, &r) in left.iter().zip(right) {
+            // SAFETY: The tap is the only end which pushes, and it is borrowed mutably.
+            // There is room for the whole block, as only the consumer can change the length.
+            let _ = unsafe { frames.push([l, r]) };
+        }
+    }
+}
+
+impl RecordConsumer {
+    /// Pops the oldest frame from the queue, if there is one.
+    pub fn pop(&mut self) -> Option<[f32; 2]> {
+        // SAFETY: The consumer is the only end which pops, and it is borrowed mutably.
+        unsafe { self.inner.frames.pop() }
+    }
+
+    /// Gets the number of frames which have been dropped because the queue was full.
+    ///
+    /// A recording with dropped frames has gaps, and should be considered damaged.
+    pub fn dropped(&self) -> usize {
+        self.inner.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Writes every frame waiting in the queue to a WAV file.
+    ///
+    /// This should be called periodically from a background thread, often enough that the
+    /// queue never fills up.
+    ///
+    /// # Return
+    /// Returns the number of frames written.
+    pub fn drain<W: Write + Seek>(&mut self, writer: &mut WavWriter<W>) -> io::Result<usize> {
+        let mut count = 0;
+        while let Some(frame) = self.pop() {
+            writer.write_frame(frame)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl Iterator for RecordConsumer {
+    type Item = [f32; 2];
+
+    fn next(&mut self) -> Option<[f32; 2]> {
+        self.pop()
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Starts a WAV file at the given sample rate, writing its header.
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(WAV_HEADER_LEN - 8).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        // Format chunk, for 32-bit floating point stereo
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&18u32.to_le_bytes())?;
+        writer.write_all(&3u16.to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * WAV_FRAME_LEN).to_le_bytes())?;
+        writer.write_all(&(WAV_FRAME_LEN as u16).to_le_bytes())?;
+        writer.write_all(&32u16.to_le_bytes())?;
+        writer.write_all(&0u16.to_le_bytes())?;
+
+        // Fact chunk, holding the number of frames, which is required for non-PCM formats
+        writer.write_all(b"fact")?;
+        writer.write_all(&4u32.to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self { writer, frames: 0 })
+    }
+
+    /// Appends a stereo frame to the file.
+    pub fn write_frame(&mut self, frame: [f32; 2]) -> io::Result<()> {
+        // The sizes in the header are 32-bit, so limit the file to 4 GiB
+        let len = WAV_HEADER_LEN as u64 + (self.frames as u64 + 1) * WAV_FRAME_LEN as u64;
+        if len > u32::MAX as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "WAV file is full."));
+        }
+        self.writer.write_all(&frame[0].to_le_bytes())?;
+        self.writer.write_all(&frame[1].to_le_bytes())?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Appends a block of stereo audio to the file.
+    pub fn write(&mut self, output: [&[f32]; 2]) -> io::Result<()> {
+        let [left, right] = output;
+        for (&l, &r) in left.iter().zip(right) {
+            self.write_frame([l, r])?;
+        }
+        Ok(())
+    }
+
+    /// Gets the number of frames written so far.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Completes the file by filling in the sizes in its header, and returns the destination.
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_len = self.frames * WAV_FRAME_LEN;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(WAV_HEADER_LEN - 8 + data_len).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(46))?;
+        self.writer.write_all(&self.frames.to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(54))?;
+        self.writer.write_all(&data_len.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Reads the little-endian 32-bit value at the given offset.
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn header_sizes_are_filled_in_when_finished() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 48000).unwrap();
+        assert_eq!(writer.writer.get_ref().len(), WAV_HEADER_LEN as usize);
+        writer.write([&[0.25, 0.5, 0.75], &[-0.25, -0.5, -0.75]]).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 58 + 3 * 8);
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(read_u32(&bytes, 4), bytes.len() as u32 - 8);
+        assert_eq!(read_u32(&bytes, 24), 48000);
+        assert_eq!(&bytes[38..42], b"fact");
+        assert_eq!(read_u32(&bytes, 46), 3);
+        assert_eq!(&bytes[50..54], b"data");
+        assert_eq!(read_u32(&bytes, 54), 3 * 8);
+        assert_eq!(f32::from_le_bytes(bytes[62..66].try_into().unwrap()), -0.25);
+    }
+
+    #[test]
+    fn files_are_limited_to_4_gib() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 48000).unwrap();
+        let max_frames = (u32::MAX - WAV_HEADER_LEN) / WAV_FRAME_LEN;
+        writer.frames = max_frames - 1;
+        assert!(writer.write_frame([0.0; 2]).is_ok());
+        let error = writer.write_frame([0.0; 2]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(writer.frames(), max_frames);
+    }
+
+    #[test]
+    fn blocks_which_dont_fit_are_dropped_and_counted() {
+        let (mut tap, mut consumer) = RecordQueue::new(5).split();
+        tap.write([&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]]);
+        tap.write([&[7.0, 8.0, 9.0], &[0.0; 3]]);
+        assert_eq!(consumer.dropped(), 3);
+
+        // A block which fits is still recorded once there is room
+        tap.write([&[7.0, 8.0], &[0.0; 2]]);
+        assert_eq!(consumer.dropped(), 3);
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 48000).unwrap();
+        assert_eq!(consumer.drain(&mut writer).unwrap(), 5);
+        assert_eq!(writer.frames(), 5);
+    }
+}
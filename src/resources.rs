@@ -0,0 +1,97 @@
+use std::any::{Any, TypeId};
+use std::sync::{Arc, Mutex, Weak};
+
+/// Large, immutable data shared between voices, such as wavetables, samples or impulse responses.
+///
+/// Each resource is stored behind an [Arc] and identified by its type, so cloning the prototype
+/// voice for every note of polyphony only clones the handles rather than the data itself.
+/// Voices receive the resources of their synth through [Voice::set_resources](crate::Voice::set_resources).
+#[derive(Clone, Default)]
+pub struct Resources {
+    entries: Vec<(TypeId, Arc<dyn Any + Send + Sync>)>,
+}
+
+impl Resources {
+    /// Creates an empty set of resources.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a resource, replacing any existing resource of the same type.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, resource: Arc<T>) {
+        let id = TypeId::of::<T>();
+        self.entries.retain(|(entry, _)| *entry != id);
+        self.entries.push((id, resource));
+    }
+
+    /// Gets a handle to the resource of the given type, if there is one.
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let id = TypeId::of::<T>();
+        let (_, resource) = self.entries.iter().find(|(entry, _)| *entry == id)?;
+        resource.clone().downcast().ok()
+    }
+}
+
+/// A cache of resources shared between all the synths in a process, such as the many instances
+/// of a plugin created by a host, so that each resource is only built once.
+///
+/// Resources are held weakly, so they are freed once no synth uses them.
+/// The cache can be created in a `static`, and should only be used off the audio thread.
+pub struct ResourceCache {
+    entries: Mutex<Vec<CacheEntry>>,
+}
+
+/// A resource in a [ResourceCache], identified by its type and a key.
+struct CacheEntry {
+    id: TypeId,
+    key: String,
+    resource: Weak<dyn Any + Send + Sync>,
+}
+
+impl ResourceCache {
+    /// Creates an empty cache.
+    pub const fn new() -> Self {
+        Self {
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Gets the resource of the given type with the given key, building it with `f`
+    /// if it isn't in the cache or has since been freed.
+    ///
+    /// Other callers wait while a resource is built, so it is never built twice at once.
+    pub fn get_or_insert_with<T: Send + Sync + 'static>(
+        &self,
+        key: &str,
+        f: impl FnOnce() -> T,
+    ) -> Arc<T> {
+        let id = TypeId::of::<T>();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+
+        let cached = entries
+            .iter()
+            .find(|entry| entry.id == id && entry.key == key)
+            .and_then(|entry| entry.resource.upgrade())
+            .and_then(|resource| resource.downcast().ok());
+        if let Some(resource) = cached {
+            return resource;
+        }
+
+        // Forget resources which have been freed, then build the resource
+        entries.retain(|entry| entry.resource.strong_count() > 0);
+        let resource = Arc::new(f());
+        let weak = Arc::downgrade(&resource);
+        entries.push(CacheEntry {
+            id,
+            key: key.to_owned(),
+            resource: weak,
+        });
+        resource
+    }
+}
+
+impl Default for ResourceCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
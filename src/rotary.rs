@@ -0,0 +1,280 @@
+use crate::biquad::{Biquad, Response, BUTTERWORTH_Q};
+use crate::smooth::Smoothed;
+use crate::Effect;
+use std::f32::consts::TAU;
+
+/// A rotary speaker, which simulates the spinning horn and drum of a Leslie cabinet, and suits
+/// organ voices in particular.
+///
+/// The input is split at 800 Hz by a Linkwitz-Riley crossover. The treble is sent to the horn
+/// and the bass to the drum, each of which modulates the amplitude of its band and, through a
+/// short modulated delay, its pitch, as its sound sweeps towards and away from the listener.
+/// The two channels of the output are heard from either side of the cabinet. Switching between
+/// the slow (chorale) and fast (tremolo) speeds ramps the rotors up or down gradually, with the
+/// light horn changing speed faster than the heavy drum.
+///
+/// # Parameters
+/// 0. Speed, where values of 0.5 and above select the fast speed.
+/// 1. Width, from 0 (mono) to 1 (the two sides of the cabinet in full stereo).
+pub struct Rotary {
+    /// Filters the input into the treble for the horn.
+    highpass: [Biquad; 2],
+    /// Filters the input into the bass for the drum.
+    lowpass: [Biquad; 2],
+    /// The horn, which spins the treble.
+    horn: Rotor,
+    /// The drum, which spins the bass.
+    drum: Rotor,
+    /// Whether the rotors are at the fast speed.
+    fast: bool,
+    /// The stereo width.
+    width: f32,
+    /// The sample rate in Hz.
+    sample_rate: u32,
+}
+
+/// A rotating horn or drum of a [Rotary].
+struct Rotor {
+    /// The speeds of the rotor in revolutions per second.
+    speeds: RotorSpeeds,
+    /// The speed in revolutions per second, which ramps between the two speeds.
+    speed: Smoothed,
+    /// The current speed in revolutions per second.
+    current_speed: f32,
+    /// The angle of the rotor, from 0 to 1.
+    angle: f32,
+    /// The delay line which models the movement of the rotor, as a circular buffer.
+    delay: Vec<f32>,
+    /// The position in the delay line which the next sample is written to.
+    write: usize,
+}
+
+/// The speeds and modulation of a [Rotor].
+#[derive(Copy, Clone)]
+struct RotorSpeeds {
+    /// The slow speed in revolutions per second.
+    slow: f32,
+    /// The fast speed in revolutions per second.
+    fast: f32,
+    /// The time constant in seconds of the change between speeds.
+    ramp: f32,
+    /// The depth of the amplitude modulation, from 0 to 1.
+    tremolo: f32,
+    /// The depth of the delay modulation in seconds, which sets the doppler shift.
+    doppler: f32,
+}
+
+/// The speeds of the horn, which is light and changes speed quickly.
+const HORN: RotorSpeeds = RotorSpeeds {
+    slow: 0.8,
+    fast: 6.7,
+    ramp: 0.5,
+    tremolo: 0.4,
+    doppler: 0.0004,
+};
+
+/// The speeds of the drum, which is heavy and changes speed slowly.
+const DRUM: RotorSpeeds = RotorSpeeds {
+    slow: 0.7,
+    fast: 5.7,
+    ramp: 2.0,
+    tremolo: 0.25,
+    doppler: 0.0002,
+};
+
+/// The frequency in Hz at which the input is split between the horn and the drum.
+const CROSSOVER_FREQ: f32 = 800.0;
+
+impl Rotary {
+    /// Creates a rotary speaker at the slow speed.
+    pub fn new() -> Self {
+        let mut out = Self {
+            highpass: Default::default(),
+            lowpass: Default::default(),
+            horn: Rotor::new(HORN),
+            drum: Rotor::new(DRUM),
+            fast: false,
+            width: 1.0,
+            sample_rate: 0,
+        };
+        out.set_sample_rate(48000);
+        out
+    }
+
+    /// Selects the fast or slow speed, which the rotors ramp up or down to.
+    pub fn set_fast(&mut self, fast: bool) {
+        self.fast = fast;
+        self.horn.set_fast(fast);
+        self.drum.set_fast(fast);
+    }
+
+    /// Returns `true` if the fast speed is selected.
+    pub fn is_fast(&self) -> bool {
+        self.fast
+    }
+
+    /// Sets the stereo width, from 0 (mono) to 1 (full stereo).
+    pub fn set_width(&mut self, width: f32) {
+        self.width = width.clamp(0.0, 1.0);
+    }
+
+    /// Gets the current speeds of the horn and the drum in revolutions per second,
+    /// such as to animate them.
+    pub fn speeds(&self) -> (f32, f32) {
+        (self.horn.current_speed, self.drum.current_speed)
+    }
+}
+
+impl Default for Rotary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rotor {
+    /// Creates a rotor at its slow speed.
+    fn new(speeds: RotorSpeeds) -> Self {
+        Self {
+            speeds,
+            speed: Smoothed::new(speeds.slow),
+            current_speed: speeds.slow,
+            angle: 0.0,
+            delay: vec![],
+            write: 0,
+        }
+    }
+
+    /// Selects the fast or slow speed.
+    fn set_fast(&mut self, fast: bool) {
+        let speeds = self.speeds;
+        self.speed
+            .set_target(if fast { speeds.fast } else { speeds.slow });
+    }
+
+    /// Sets the sample rate, reallocating the delay line.
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.speed.set_time(self.speeds.ramp, sample_rate);
+        // Room for the full sweep of the delay, plus the samples either side of the interpolation
+        let len = (2.0 * self.speeds.doppler * sample_rate as f32).ceil() as usize + 3;
+        self.delay = vec![0.0; len];
+        self.write = 0;
+    }
+
+    /// Clears the delay line.
+    fn reset(&mut self) {
+        self.delay.fill(0.0);
+    }
+
+    /// Spins a sample of the band of the rotor, returning what is heard from either side of
+    /// the cabinet.
+    fn process(&mut self, x: f32, sample_rate: u32) -> [f32; 2] {
+        let len = self.delay.len();
+        self.delay[self.write] = x;
+
+        let depth = self.speeds.doppler * sample_rate as f32;
+        let out = [0.0, 0.25].map(|offset| {
+            // Each side hears the rotor a quarter of a turn apart
+            let (sin, cos) = (TAU * (self.angle + offset)).sin_cos();
+            let delay = 1.0 + depth * (1.0 + sin);
+            let pos = self.write as f32 + len as f32 - delay;
+            let index = pos as usize;
+            let frac = pos - index as f32;
+            let a = self.delay[index % len];
+            let b = self.delay[(index + 1) % len];
+            let gain = 1.0 - self.speeds.tremolo * 0.5 * (1.0 + cos);
+            gain * (a + frac * (b - a))
+        });
+
+        self.current_speed = self.speed.next();
+        self.angle = (self.angle + self.current_speed / sample_rate as f32).fract();
+        self.write = (self.write + 1) % len;
+        out
+    }
+}
+
+impl Effect for Rotary {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        for filter in &mut self.highpass {
+            filter.set(
+                Response::Highpass,
+                CROSSOVER_FREQ,
+                BUTTERWORTH_Q,
+                sample_rate,
+            );
+        }
+        for filter in &mut self.lowpass {
+            filter.set(
+                Response::Lowpass,
+                CROSSOVER_FREQ,
+                BUTTERWORTH_Q,
+                sample_rate,
+            );
+        }
+        self.horn.set_sample_rate(sample_rate);
+        self.drum.set_sample_rate(sample_rate);
+    }
+
+    fn reset(&mut self) {
+        self.highpass.iter_mut().for_each(Biquad::reset);
+        self.lowpass.iter_mut().for_each(Biquad::reset);
+        self.horn.reset();
+        self.drum.reset();
+    }
+
+    fn process(&mut self, buffer: [&mut [f32]; 2]) {
+        let [left, right] = buffer;
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let x = 0.5 * (*l + *r);
+            let treble = self
+                .highpass
+                .iter_mut()
+                .fold(x, |x, f| f.process_sample(0, x));
+            let bass = self
+                .lowpass
+                .iter_mut()
+                .fold(x, |x, f| f.process_sample(0, x));
+            let horn = self.horn.process(treble, self.sample_rate);
+            let drum = self.drum.process(bass, self.sample_rate);
+
+            let [side_a, side_b] = [horn[0] + drum[0], horn[1] + drum[1]];
+            let mid = 0.5 * (side_a + side_b);
+            *l = mid + self.width * (side_a - mid);
+            *r = mid + self.width * (side_b - mid);
+        }
+    }
+
+    fn num_params(&self) -> usize {
+        2
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.set_fast(value >= 0.5),
+            1 => self.set_width(value),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn horn_ramps_up_faster_than_the_drum() {
+        let mut rotary = Rotary::new();
+        rotary.set_fast(true);
+        let (mut left, mut right) = (vec![0.5; 48000], vec![0.5; 48000]);
+        rotary.process([&mut left, &mut right]);
+        let (horn, drum) = rotary.speeds();
+        assert!(horn > 5.5 && drum < 3.0, "{horn} {drum}");
+
+        for _ in 0..10 {
+            let (mut left, mut right) = (vec![0.0; 48000], vec![0.0; 48000]);
+            rotary.process([&mut left, &mut right]);
+        }
+        let (horn, drum) = rotary.speeds();
+        assert!((horn - 6.7).abs() < 0.01 && (drum - 5.7).abs() < 0.01);
+    }
+}
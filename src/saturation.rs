@@ -0,0 +1,41 @@
+/// A gentle soft-knee saturator, applied to the mix of voices to add "glue"
+/// and to tame hot transients.
+#[derive(Copy, Clone, Debug)]
+pub struct Saturation {
+    /// The gain applied before saturating, as a linear gain.
+    /// Higher values result in more saturation.
+    pub drive: f32,
+    /// If `true`, the output is attenuated by the drive, so that quiet signals
+    /// pass through at the same level and only peaks are saturated.
+    pub auto_gain: bool,
+}
+
+/// The level above which the saturator begins to bend the signal.
+const KNEE: f32 = 0.5;
+
+impl Saturation {
+    /// Applies the saturation to a block of stereo audio in place.
+    pub fn process(&self, output: [&mut [f32]; 2]) {
+        let makeup = if self.auto_gain && self.drive > 0.0 {
+            self.drive.recip()
+        } else {
+            1.0
+        };
+
+        for channel in output {
+            for sample in channel.iter_mut() {
+                *sample = makeup * saturate(self.drive * *sample);
+            }
+        }
+    }
+}
+
+/// Saturates a sample, leaving it untouched below the knee and approaching ±1 above it.
+fn saturate(x: f32) -> f32 {
+    let magnitude = x.abs();
+    if magnitude <= KNEE {
+        return x;
+    }
+    let excess = (magnitude - KNEE) / (1.0 - KNEE);
+    x.signum() * (KNEE + (1.0 - KNEE) * excess.tanh())
+}
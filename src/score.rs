@@ -0,0 +1,379 @@
+use crate::{Channel, Note, NoteId, Synth, Velocity, Voice};
+
+/// A curve through breakpoints over the lifetime of a note, such as the pitch, pressure or
+/// timbre expression exported with a note by notation software or a DAW.
+///
+/// Between breakpoints the value is interpolated linearly, and before the first breakpoint and
+/// after the last the value is held. Two breakpoints at the same time make a step.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct ExpressionCurve {
+    /// The breakpoints, as times in seconds from the start of the note and values, in order of
+    /// time.
+    points: Vec<(f64, f32)>,
+}
+
+/// A note of a [ScorePlayer], with its expression curves.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ScoreNote {
+    /// The time the note starts, in seconds from the start of the score.
+    pub start: f64,
+    /// The time the note is held for, in seconds.
+    pub duration: f64,
+    /// The MIDI channel the note is played on.
+    pub channel: Channel,
+    /// The MIDI note.
+    pub note: Note,
+    /// The velocity of the note.
+    pub velocity: Velocity,
+    /// The bend of the note in semitones, passed to [Synth::set_note_pitch].
+    pub pitch: ExpressionCurve,
+    /// The pressure of the note from 0 to 1, passed to [Synth::set_note_pressure].
+    pub pressure: ExpressionCurve,
+    /// The timbre of the note from 0 to 1, passed to [Synth::set_note_timbre].
+    pub timbre: ExpressionCurve,
+}
+
+/// Plays a score of notes with expression curves into a synth, for offline rendering.
+///
+/// Notes start and end at the exact sample. The curves are evaluated at the exact sample at
+/// each of their breakpoints, and at least every [resolution](Self::resolution) samples in
+/// between, as the synth renders the pitch of each voice once per block.
+///
+/// The score can be rendered from any position. When a render doesn't follow on from the
+/// previous one, the notes which were playing are released, and the notes which are held at the
+/// new position are started there, with their curves evaluated from that point in the note.
+pub struct ScorePlayer {
+    /// The notes, in order of their start.
+    notes: Vec<ScoreNote>,
+    /// The index of the next note to start.
+    next: usize,
+    /// The indices of the notes which are playing, with their identifiers in the synth.
+    active: Vec<(usize, NoteId)>,
+    /// The position of the next sample to be rendered, in samples from the start of the score.
+    position: u64,
+    /// The largest number of samples between evaluations of the curves.
+    pub resolution: usize,
+}
+
+/// A method of [Synth] which sets an expression of a note.
+type NoteSetter<V> = fn(&mut Synth<V>, NoteId, f32);
+
+/// The default number of samples between evaluations of the curves of a [ScorePlayer].
+const RESOLUTION: usize = 16;
+
+/// The velocity with which notes are released.
+const RELEASE_VELOCITY: Velocity = Velocity::new(64);
+
+impl ExpressionCurve {
+    /// Creates a curve without breakpoints, which leaves the expression of the note unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a breakpoint, after any others at the same time.
+    ///
+    /// # Parameters
+    /// * `time` - The time in seconds from the start of the note.
+    /// * `value` - The value of the expression at that time.
+    pub fn with_point(mut self, time: f64, value: f32) -> Self {
+        let index = self.points.partition_point(|&(t, _)| t <= time);
+        self.points.insert(index, (time, value));
+        self
+    }
+
+    /// Gets the breakpoints, as times in seconds and values, in order of time.
+    pub fn points(&self) -> &[(f64, f32)] {
+        &self.points
+    }
+
+    /// Gets the value of the curve at a time in seconds from the start of the note, or `None`
+    /// if it has no breakpoints.
+    pub fn value_at(&self, time: f64) -> Option<f32> {
+        let index = self.points.partition_point(|&(t, _)| t <= time);
+        let before = index.checked_sub(1).map(|i| self.points[i]);
+        match (before, self.points.get(index)) {
+            (Some((t0, v0)), Some(&(t1, v1))) => {
+                let frac = ((time - t0) / (t1 - t0)) as f32;
+                Some(v0 + frac * (v1 - v0))
+            }
+            (Some((_, value)), None) | (None, Some(&(_, value))) => Some(value),
+            (None, None) => None,
+        }
+    }
+
+    /// Gets the time of the first breakpoint after the given time, if any.
+    fn next_point(&self, time: f64) -> Option<f64> {
+        let index = self.points.partition_point(|&(t, _)| t <= time);
+        self.points.get(index).map(|&(t, _)| t)
+    }
+}
+
+impl ScoreNote {
+    /// Creates a note on the first MIDI channel, without expression curves.
+    ///
+    /// # Parameters
+    /// * `start` - The time the note starts, in seconds from the start of the score.
+    /// * `duration` - The time the note is held for, in seconds.
+    /// * `note` - The MIDI note, between 0 and 127.
+    /// * `velocity` - The velocity of the note.
+    pub fn new(start: f64, duration: f64, note: Note, velocity: Velocity) -> Self {
+        Self {
+            start,
+            duration,
+            channel: Channel::default(),
+            note,
+            velocity,
+            pitch: ExpressionCurve::new(),
+            pressure: ExpressionCurve::new(),
+            timbre: ExpressionCurve::new(),
+        }
+    }
+
+    /// Gets the curves of the note, with the synth method each is passed to.
+    fn curves<V: Voice + Clone>(&self) -> [(&ExpressionCurve, NoteSetter<V>); 3] {
+        [
+            (&self.pitch, Synth::set_note_pitch),
+            (&self.pressure, Synth::set_note_pressure),
+            (&self.timbre, Synth::set_note_timbre),
+        ]
+    }
+}
+
+impl ScorePlayer {
+    /// Creates a player for a score of notes, in any order, positioned at its start.
+    pub fn new(mut notes: Vec<ScoreNote>) -> Self {
+        notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+        Self {
+            notes,
+            next: 0,
+            active: vec![],
+            position: 0,
+            resolution: RESOLUTION,
+        }
+    }
+
+    /// Gets the notes of the score, in order of their start.
+    pub fn notes(&self) -> &[ScoreNote] {
+        &self.notes
+    }
+
+    /// Gets the position of the next sample to be rendered, in samples from the start of the
+    /// score.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Renders a block of the score through a synth.
+    ///
+    /// # Parameters
+    /// * `synth` - The synth which plays the notes, at the sample rate to render at.
+    /// * `position` - The position of the first sample of the block, in samples from the start
+    ///   of the score.
+    /// * `output` - The left and right audio buffers for writing the output.
+    pub fn render<V: Voice + Clone>(
+        &mut self,
+        synth: &mut Synth<V>,
+        position: u64,
+        output: [&mut [f32]; 2],
+    ) {
+        let sample_rate = synth.sample_rate() as f64;
+        if position != self.position {
+            self.seek(synth, position, sample_rate);
+        }
+
+        let [left, right] = output;
+        let len = left.len();
+        let mut start = 0;
+        while start < len {
+            let now = self.position;
+            self.update(synth, now, sample_rate);
+
+            // Render up to the next change: a note starting or ending, or a breakpoint
+            let mut end = now + self.resolution.max(1).min(len - start) as u64;
+            if let Some(note) = self.notes.get(self.next) {
+                end = end.min(to_samples(note.start, sample_rate));
+            }
+            for &(index, _) in &self.active {
+                let note = &self.notes[index];
+                let note_start = to_samples(note.start, sample_rate);
+                end = end.min(to_samples(note.start + note.duration, sample_rate));
+                let time = (now - note_start) as f64 / sample_rate;
+                for (curve, _) in note.curves::<V>() {
+                    if let Some(next) = curve.next_point(time) {
+                        let next = note_start + (next * sample_rate).ceil() as u64;
+                        end = end.min(next);
+                    }
+                }
+            }
+
+            let end = start + (end.max(now + 1) - now) as usize;
+            synth.process([&mut left[start..end], &mut right[start..end]]);
+            self.position += (end - start) as u64;
+            start = end;
+        }
+    }
+
+    /// Releases the notes which have ended, starts those which begin, and applies the curves of
+    /// the notes which are playing, at the given position.
+    fn update<V: Voice + Clone>(&mut self, synth: &mut Synth<V>, now: u64, sample_rate: f64) {
+        let notes = &self.notes;
+        self.active.retain(|&(index, id)| {
+            let note = &notes[index];
+            let ended = to_samples(note.start + note.duration, sample_rate) <= now;
+            if ended {
+                synth.release_id(id, RELEASE_VELOCITY);
+            }
+            !ended
+        });
+
+        while let Some(note) = notes.get(self.next) {
+            if to_samples(note.start, sample_rate) > now {
+                break;
+            }
+            if to_samples(note.start + note.duration, sample_rate) > now {
+                let id = synth.note_on(note.channel, note.note, note.velocity);
+                self.active.push((self.next, id));
+            }
+            self.next += 1;
+        }
+
+        for &(index, id) in &self.active {
+            let note = &notes[index];
+            let time = (now - to_samples(note.start, sample_rate)) as f64 / sample_rate;
+            for (curve, apply) in note.curves::<V>() {
+                if let Some(value) = curve.value_at(time) {
+                    apply(synth, id, value);
+                }
+            }
+        }
+    }
+
+    /// Moves to a new position, releasing the notes which are playing, so that the notes held
+    /// at the new position are started there.
+    fn seek<V: Voice + Clone>(&mut self, synth: &mut Synth<V>, position: u64, sample_rate: f64) {
+        for (_, id) in self.active.drain(..) {
+            synth.release_id(id, RELEASE_VELOCITY);
+        }
+        // Start again from the first note, skipping those which have already ended
+        self.next = 0;
+        while let Some(note) = self.notes.get(self.next) {
+            if to_samples(note.start, sample_rate) > position {
+                break;
+            }
+            if to_samples(note.start + note.duration, sample_rate) > position {
+                let id = synth.note_on(note.channel, note.note, note.velocity);
+                self.active.push((self.next, id));
+            }
+            self.next += 1;
+        }
+        self.position = position;
+    }
+}
+
+/// Converts a time in seconds to the nearest sample.
+fn to_samples(time: f64, sample_rate: f64) -> u64 {
+    (time * sample_rate).round().max(0.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SynthCapacity, SynthOpts};
+    use std::sync::{Arc, Mutex};
+
+    /// A voice which records the pitch and pressure it renders each block at, and outputs its
+    /// pitch.
+    #[derive(Clone, Default)]
+    struct ExpressionVoice {
+        /// The pitch and pressure of every block rendered by any clone of the voice.
+        blocks: Arc<Mutex<Vec<(f32, f32)>>>,
+        pressure: f32,
+        on: bool,
+    }
+
+    impl Voice for ExpressionVoice {
+        fn set_sample_rate(&mut self, _sample_rate: u32) {}
+
+        fn reset(&mut self) {
+            self.on = false;
+        }
+
+        fn trigger(&mut self, _note: Note, _velocity: Velocity) {
+            self.pressure = 0.0;
+            self.on = true;
+        }
+
+        fn release(&mut self, _velocity: Velocity) {
+            self.on = false;
+        }
+
+        fn set_pressure(&mut self, pressure: f32) {
+            self.pressure = pressure;
+        }
+
+        fn process(&mut self, pitch: f32, output: [&mut [f32]; 2]) -> bool {
+            self.blocks.lock().unwrap().push((pitch, self.pressure));
+            output
+                .into_iter()
+                .for_each(|channel| channel.fill(if self.on { 1.0 } else { 0.0 }));
+            self.on
+        }
+    }
+
+    #[test]
+    fn curves_interpolate_between_breakpoints() {
+        let curve = ExpressionCurve::new()
+            .with_point(1.0, 4.0)
+            .with_point(0.0, 2.0)
+            .with_point(1.0, 0.0);
+        assert_eq!(curve.value_at(-1.0), Some(2.0));
+        assert_eq!(curve.value_at(0.25), Some(2.5));
+        assert_eq!(curve.value_at(1.0), Some(0.0));
+        assert_eq!(ExpressionCurve::new().value_at(0.0), None);
+    }
+
+    #[test]
+    fn notes_play_their_curves_from_any_position() {
+        let capacity = SynthCapacity {
+            max_block_size: 64,
+            max_voices: 4,
+            max_buses: 1,
+        };
+        let opts = SynthOpts {
+            max_voices: 4,
+            ..Default::default()
+        };
+        let voice = ExpressionVoice::default();
+        let mut synth = Synth::new(capacity, opts, voice.clone());
+        synth.set_sample_rate(1000);
+
+        // An octave bend over the first 100 ms of a note starting 10 ms in, with rising pressure
+        let mut note = ScoreNote::new(0.01, 0.2, 69, Velocity::new(100));
+        note.pitch = ExpressionCurve::new()
+            .with_point(0.0, 0.0)
+            .with_point(0.1, 12.0);
+        note.pressure = ExpressionCurve::new()
+            .with_point(0.0, 0.0)
+            .with_point(0.2, 1.0);
+        let mut player = ScorePlayer::new(vec![note]);
+
+        let (mut left, mut right) = ([0.0; 64], [0.0; 64]);
+        player.render(&mut synth, 0, [&mut left, &mut right]);
+        assert_eq!(left[9], 0.0);
+        assert_eq!(left[10], 1.0);
+        let blocks = std::mem::take(&mut *voice.blocks.lock().unwrap());
+        let (pitch, pressure) = blocks[0];
+        assert!((pitch - 440.0).abs() < 0.01 && pressure == 0.0);
+
+        // Jump to the middle of the note, past the end of the bend
+        player.render(&mut synth, 150, [&mut left, &mut right]);
+        let blocks = std::mem::take(&mut *voice.blocks.lock().unwrap());
+        let resumed = |&(pitch, pressure): &(f32, f32)| {
+            (pitch - 880.0).abs() < 0.01 && (pressure - 0.7).abs() < 1e-4
+        };
+        assert!(blocks.iter().any(resumed), "{blocks:?}");
+        assert_eq!(player.position(), 214);
+        assert_eq!(left[59], 1.0);
+        assert!(left[61] < 1.0);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::{Effect, PitchShifter};
+
+/// A shimmer reverb, whose tail rises in pitch as it decays, giving a bright, choral wash
+/// behind pads and plucks.
+///
+/// The reverb is a feedback delay network of four delay lines, mixed into each other through a
+/// Hadamard matrix and damped by a lowpass filter in each line. Some of the feedback is passed
+/// through a pitch shifter, an octave up by default, so that each pass through the network
+/// raises part of the tail by another octave. The shifted feedback replaces rather than adds to
+/// the unshifted feedback, so the tail decays however much shimmer there is.
+///
+/// # Parameters
+/// 0. Decay time in seconds, from 0.1 to 20, which is the time for the tail to fall by 60 dB.
+/// 1. Damping, from 0 (bright) to 1 (dark).
+/// 2. Shimmer, from 0 (a plain reverb) to 1 (all of the feedback is shifted).
+/// 3. Mix, from 0 (dry) to 1 (wet).
+pub struct Shimmer {
+    /// The delay lines of the network, as circular buffers.
+    lines: [Vec<f32>; LINES],
+    /// The position in each delay line.
+    positions: [usize; LINES],
+    /// The gain applied to each delay line on each pass, which sets the decay time.
+    gains: [f32; LINES],
+    /// The state of the lowpass filter in each delay line.
+    lowpass: [f32; LINES],
+    /// Shifts the pitch of the feedback.
+    shifter: PitchShifter,
+    /// The decay time in seconds.
+    decay: f32,
+    /// The damping, from 0 to 1.
+    damping: f32,
+    /// The proportion of the feedback which is shifted.
+    shimmer: f32,
+    /// The balance between the dry input and the reverb.
+    mix: f32,
+    /// The sample rate in Hz.
+    sample_rate: u32,
+}
+
+/// The number of delay lines in the network.
+const LINES: usize = 4;
+
+/// The lengths of the delay lines in seconds, which are chosen to have no common factors in
+/// tenths of a millisecond, so that their echoes don't line up.
+const LINE_TIMES: [f32; LINES] = [0.0297, 0.0371, 0.0419, 0.0437];
+
+/// The range of the decay time in seconds.
+const DECAY_RANGE: (f32, f32) = (0.1, 20.0);
+
+/// The length in seconds of the window of the pitch shifter.
+const SHIFT_WINDOW: f32 = 0.05;
+
+impl Shimmer {
+    /// Creates a shimmer reverb which shifts its feedback up an octave.
+    ///
+    /// # Parameters
+    /// * `decay` - The decay time in seconds.
+    /// * `shimmer` - The proportion of the feedback which is shifted, from 0 to 1.
+    /// * `mix` - The balance between the dry input and the reverb, from 0 to 1.
+    pub fn new(decay: f32, shimmer: f32, mix: f32) -> Self {
+        let mut out = Self {
+            lines: Default::default(),
+            positions: [0; LINES],
+            gains: [0.0; LINES],
+            lowpass: [0.0; LINES],
+            shifter: PitchShifter::new(12.0, SHIFT_WINDOW),
+            decay: 0.0,
+            damping: 0.3,
+            shimmer: 0.0,
+            mix: 0.0,
+            sample_rate: 0,
+        };
+        out.set_shimmer(shimmer);
+        out.set_mix(mix);
+        out.set_sample_rate(48000);
+        out.set_decay(decay);
+        out
+    }
+
+    /// Sets the time in seconds for the tail to fall by 60 dB, clamped between 0.1 and 20.
+    pub fn set_decay(&mut self, decay: f32) {
+        self.decay = decay.clamp(DECAY_RANGE.0, DECAY_RANGE.1);
+        for (gain, line) in self.gains.iter_mut().zip(&self.lines) {
+            let seconds = line.len() as f32 / self.sample_rate as f32;
+            *gain = 10f32.powf(-3.0 * seconds / self.decay);
+        }
+    }
+
+    /// Sets the damping of high frequencies in the tail, from 0 (bright) to 1 (dark).
+    pub fn set_damping(&mut self, damping: f32) {
+        // Leave some high frequencies at full damping, so that the tail doesn't stall
+        self.damping = 0.95 * damping.clamp(0.0, 1.0);
+    }
+
+    /// Sets the proportion of the feedback which is shifted, from 0 to 1.
+    pub fn set_shimmer(&mut self, shimmer: f32) {
+        self.shimmer = shimmer.clamp(0.0, 1.0);
+    }
+
+    /// Sets the shift of the feedback in semitones, which is an octave up by default.
+    pub fn set_shift(&mut self, semitones: f32) {
+        self.shifter.set_shift(semitones);
+    }
+
+    /// Sets the balance between the dry input and the reverb, from 0 (dry) to 1 (wet).
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+}
+
+impl Effect for Shimmer {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        if sample_rate != self.sample_rate {
+            self.sample_rate = sample_rate;
+            for (line, time) in self.lines.iter_mut().zip(LINE_TIMES) {
+                *line = vec![0.0; (time * sample_rate as f32) as usize];
+            }
+            self.positions = [0; LINES];
+            self.shifter.set_sample_rate(sample_rate);
+            self.set_decay(self.decay);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.lines.iter_mut().for_each(|line| line.fill(0.0));
+        self.lowpass = [0.0; LINES];
+        self.shifter.reset();
+    }
+
+    fn process(&mut self, buffer: [&mut [f32]; 2]) {
+        let [left, right] = buffer;
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let taps: [f32; LINES] = std::array::from_fn(|i| self.lines[i][self.positions[i]]);
+            let wet = [0.5 * (taps[0] + taps[2]), 0.5 * (taps[1] + taps[3])];
+
+            // Shift the output of the network, to be fed back in
+            let (mut shifted_left, mut shifted_right) = ([wet[0]], [wet[1]]);
+            self.shifter
+                .process([&mut shifted_left, &mut shifted_right]);
+            let shifted = [shifted_left[0], shifted_right[0]];
+
+            // Mix the lines into each other, preserving their energy
+            let [a, b, c, d] = taps;
+            let mixed = [
+                0.5 * (a + b + c + d),
+                0.5 * (a - b + c - d),
+                0.5 * (a + b - c - d),
+                0.5 * (a - b - c + d),
+            ];
+
+            let input = [*l, *r];
+            for i in 0..LINES {
+                let lowpass = &mut self.lowpass[i];
+                *lowpass = mixed[i] + self.damping * (*lowpass - mixed[i]);
+                let feedback = *lowpass + self.shimmer * (shifted[i % 2] - *lowpass);
+                let line = &mut self.lines[i];
+                line[self.positions[i]] = input[i % 2] + self.gains[i] * feedback;
+                self.positions[i] = (self.positions[i] + 1) % line.len();
+            }
+
+            *l += self.mix * (wet[0] - *l);
+            *r += self.mix * (wet[1] - *r);
+        }
+    }
+
+    fn num_params(&self) -> usize {
+        4
+    }
+
+    fn set_param(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.set_decay(value),
+            1 => self.set_damping(value),
+            2 => self.set_shimmer(value),
+            3 => self.set_mix(value),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tail_decays_with_full_shimmer() {
+        let mut shimmer = Shimmer::new(1.0, 1.0, 1.0);
+        let (mut left, mut right) = (vec![0.0; 4800], vec![0.0; 4800]);
+        left[0] = 1.0;
+        right[0] = 1.0;
+        shimmer.process([&mut left, &mut right]);
+        let peak = |signal: &[f32]| signal.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+        assert!(peak(&left) > 0.05);
+
+        // After three times the decay time, the tail has fallen by well over 60 dB
+        for _ in 0..30 {
+            left.fill(0.0);
+            right.fill(0.0);
+            shimmer.process([&mut left, &mut right]);
+        }
+        assert!(peak(&left) < 1e-3, "{}", peak(&left));
+    }
+}
@@ -0,0 +1,106 @@
+/// A parameter which moves smoothly towards its target value to avoid zipper noise.
+#[derive(Copy, Clone)]
+pub(crate) struct Smoothed {
+    /// The current value.
+    value: f32,
+    /// The value being moved towards.
+    target: f32,
+    /// The one-pole filter coefficient, where zero results in no smoothing.
+    coeff: f32,
+}
+
+impl Smoothed {
+    /// Creates a smoothed parameter which is settled at the given value.
+    pub fn new(value: f32) -> Self {
+        Self {
+            value,
+            target: value,
+            coeff: 0.0,
+        }
+    }
+
+    /// Sets the time constant of the smoothing in seconds.
+    pub fn set_time(&mut self, time: f32, sample_rate: u32) {
+        let samples = time * sample_rate as f32;
+        self.coeff = if samples > 0.0 { (-1.0 / samples).exp() } else { 0.0 };
+    }
+
+    /// Sets the value to move towards.
+    pub fn set_target(&mut self, target: f32) {
+        self.target = target;
+    }
+
+    /// Gets the value being moved towards.
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    /// Returns `true` if the value has reached its target.
+    pub fn is_settled(&self) -> bool {
+        self.value == self.target
+    }
+
+    /// Advances by one sample and returns the new value.
+    pub fn next(&mut self) -> f32 {
+        let value = self.target + self.coeff * (self.value - self.target);
+        self.settle(value);
+        self.value
+    }
+
+    /// Advances by the given number of samples at once and returns the new value.
+    pub fn skip(&mut self, samples: usize) -> f32 {
+        if !self.is_settled() {
+            let value = self.target + self.coeff.powi(samples as i32) * (self.value - self.target);
+            self.settle(value);
+        }
+        self.value
+    }
+
+    /// Moves to the given value, snapping to the target once it is close enough, or once
+    /// rounding stops the value from moving any closer, as happens with slow smoothing.
+    fn settle(&mut self, value: f32) {
+        let stalled = value == self.value;
+        self.value = if stalled || (value - self.target).abs() < 1e-6 {
+            self.target
+        } else {
+            value
+        };
+    }
+
+    /// Applies the value as a gain to a block of stereo audio, advancing by one sample per frame.
+    pub fn apply_gain(&mut self, output: [&mut [f32]; 2]) {
+        let [left, right] = output;
+        if self.is_settled() {
+            if self.value != 1.0 {
+                left.iter_mut().for_each(|s| *s *= self.value);
+                right.iter_mut().for_each(|s| *s *= self.value);
+            }
+            return;
+        }
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let gain = self.next();
+            *l *= gain;
+            *r *= gain;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slow_smoothing_settles_exactly_on_the_target() {
+        let mut smoothed = Smoothed::new(0.0);
+        smoothed.set_time(0.01, 48000);
+        smoothed.set_target(1.0);
+        for _ in 0..48000 {
+            smoothed.next();
+        }
+        assert!(smoothed.is_settled());
+
+        smoothed.set_target(0.0);
+        smoothed.skip(48000);
+        assert!(smoothed.is_settled());
+    }
+}
@@ -0,0 +1,609 @@
+use crate::queue::RingBuffer;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// A sample in a WAV file which is streamed from disk as it plays, so that large sampler
+/// instruments don't need to fit in memory.
+///
+/// The first frames of the sample, known as its head, are loaded into memory when it is opened,
+/// so that a note can start playing at once while the rest of the sample, its body, is read
+/// from disk by a [DiskStreamer].
+///
+/// 16-bit and 24-bit integer and 32-bit floating point files are supported. Mono files are
+/// played on both channels, and channels beyond the first two are ignored.
+pub struct StreamedSample {
+    /// The path of the file.
+    path: PathBuf,
+    /// The layout of the samples in the file.
+    format: WavFormat,
+    /// The first frames of the sample.
+    head: Box<[[f32; 2]]>,
+}
+
+/// Plays a [StreamedSample] on the audio thread, such as for one voice of a sampler.
+///
+/// The head of the sample is played from memory, and the body from a lock-free queue which is
+/// filled by a [DiskStreamer]. If the streamer falls behind, the missing frames are played as
+/// silence and counted as underruns, and the frames which arrive late are skipped, so the
+/// sample stays in time rather than the audio thread waiting for the disk.
+pub struct SampleStream {
+    /// The storage shared with the streamer.
+    inner: Arc<StreamInner>,
+    /// The sample being played, if any.
+    sample: Option<Arc<StreamedSample>>,
+    /// The generation of the current playback, which tags the frames streamed for it.
+    generation: u32,
+    /// The number of frames of the sample which have been played, including skipped frames.
+    position: usize,
+    /// The number of frames which were played as silence, and so are skipped when they arrive.
+    skip: usize,
+}
+
+/// Reads the bodies of [StreamedSample]s from disk for each [SampleStream].
+///
+/// The streamer should be [serviced](Self::service) periodically from a background thread,
+/// often enough that the queue of each stream never runs dry, or [spawned](Self::spawn) onto
+/// a thread of its own.
+pub struct DiskStreamer {
+    /// The streams being filled.
+    streams: Vec<StreamState>,
+    /// The bytes read from a file, before they are decoded.
+    bytes: Vec<u8>,
+}
+
+/// A [DiskStreamer] running on a thread of its own, which is stopped when this is dropped.
+pub struct StreamerThread {
+    /// Tells the thread to stop.
+    stop: Arc<AtomicBool>,
+    /// The thread, which returns the first error it encountered, if any.
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+/// The storage shared by a [SampleStream] and its [DiskStreamer].
+struct StreamInner {
+    /// The streamed frames, each tagged with the generation of the playback it is for.
+    frames: RingBuffer<(u32, [f32; 2])>,
+    /// The requests sent from the stream to the streamer.
+    requests: RingBuffer<Request>,
+    /// The number of frames which were played as silence because the frames hadn't arrived.
+    underruns: AtomicUsize,
+}
+
+/// The state of a stream in a [DiskStreamer].
+struct StreamState {
+    /// The storage shared with the stream.
+    inner: Arc<StreamInner>,
+    /// The playback being streamed, if any.
+    active: Option<ActiveStream>,
+}
+
+/// A playback being streamed by a [DiskStreamer].
+struct ActiveStream {
+    /// The generation of the playback.
+    generation: u32,
+    /// The sample being played.
+    sample: Arc<StreamedSample>,
+    /// The open file, once it has been opened.
+    file: Option<File>,
+    /// The index of the next frame to be read.
+    next: usize,
+}
+
+/// A request from a [SampleStream] to its [DiskStreamer].
+enum Request {
+    /// Starts streaming the body of a sample.
+    Start(u32, Arc<StreamedSample>),
+    /// Stops streaming.
+    Stop,
+}
+
+/// The layout of the samples in a WAV file.
+#[derive(Copy, Clone, Debug)]
+struct WavFormat {
+    /// The number of channels.
+    channels: usize,
+    /// The number of bytes in each sample of a channel.
+    bytes_per_sample: usize,
+    /// Whether the samples are floating point rather than integers.
+    float: bool,
+    /// The sample rate in Hz.
+    sample_rate: u32,
+    /// The offset in bytes of the first frame.
+    data_offset: u64,
+    /// The number of frames.
+    frames: usize,
+}
+
+/// The number of requests which can be waiting for a streamer, for each stream.
+const REQUEST_CAPACITY: usize = 8;
+
+/// The largest number of frames read from a file at a time.
+const READ_FRAMES: usize = 4096;
+
+impl StreamedSample {
+    /// Opens a WAV file, loading its head into memory.
+    ///
+    /// This reads from disk, so shouldn't be called from the audio thread.
+    ///
+    /// # Parameters
+    /// * `path` - The path of the file.
+    /// * `head` - The number of frames to load into memory, which should cover the time the
+    ///   streamer takes to start filling a stream, such as 32768 frames.
+    pub fn open(path: impl AsRef<Path>, head: usize) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let mut file = File::open(&path)?;
+        let format = WavFormat::read(&mut file)?;
+        let mut frames = Vec::with_capacity(head.min(format.frames));
+        let mut bytes = vec![];
+        format.read_frames(&mut file, 0, head.min(format.frames), &mut bytes, |frame| {
+            frames.push(frame)
+        })?;
+        Ok(Self {
+            path,
+            format,
+            head: frames.into(),
+        })
+    }
+
+    /// Gets the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Gets the number of frames in the sample.
+    pub fn frames(&self) -> usize {
+        self.format.frames
+    }
+
+    /// Gets the sample rate of the sample in Hz.
+    pub fn sample_rate(&self) -> u32 {
+        self.format.sample_rate
+    }
+
+    /// Gets the frames which are loaded into memory.
+    pub fn head(&self) -> &[[f32; 2]] {
+        &self.head
+    }
+}
+
+impl SampleStream {
+    /// Starts playing a sample from its first frame, replacing the sample being played.
+    ///
+    /// The head of the sample plays at once, while the streamer is asked to read its body.
+    /// The sample should also be held elsewhere, such as in the resources of the voice, so that
+    /// it isn't freed on the audio thread when the stream lets go of it.
+    pub fn start(&mut self, sample: Arc<StreamedSample>) {
+        self.generation = self.generation.wrapping_add(1);
+        self.position = 0;
+        self.skip = 0;
+        if sample.frames() > sample.head.len() {
+            // SAFETY: The stream is the only end which sends requests, and it is borrowed
+            // mutably. If the queue of requests is full, the body plays as an underrun.
+            let _ = unsafe {
+                self.inner
+                    .requests
+                    .push(Request::Start(self.generation, sample.clone()))
+            };
+        }
+        self.sample = Some(sample);
+    }
+
+    /// Stops playing, and tells the streamer to stop reading the sample.
+    pub fn stop(&mut self) {
+        if self.sample.take().is_some() {
+            self.generation = self.generation.wrapping_add(1);
+            // SAFETY: As in `start`.
+            let _ = unsafe { self.inner.requests.push(Request::Stop) };
+        }
+    }
+
+    /// Returns `true` if a sample is playing and hasn't reached its end.
+    pub fn is_playing(&self) -> bool {
+        self.sample.is_some()
+    }
+
+    /// Gets the number of frames of the sample which have been played.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Gets the number of frames which have been played as silence because the streamer had
+    /// fallen behind, since the stream was created.
+    pub fn underruns(&self) -> usize {
+        self.inner.underruns.load(Ordering::Relaxed)
+    }
+
+    /// Plays the next frames of the sample into a block of stereo audio, without allocating,
+    /// blocking or touching the disk.
+    ///
+    /// # Return
+    /// Returns the number of frames of the sample which were played, which is less than the
+    /// length of the block once the sample has ended, in which case the rest of the block is
+    /// filled with silence.
+    pub fn read(&mut self, output: [&mut [f32]; 2]) -> usize {
+        let [left, right] = output;
+        let Some(sample) = &self.sample else {
+            left.fill(0.0);
+            right.fill(0.0);
+            return 0;
+        };
+        let len = left.len().min(sample.frames() - self.position);
+        let head = &sample.head;
+
+        let mut underruns = 0;
+        for (l, r) in left[..len].iter_mut().zip(&mut right[..len]) {
+            let frame = if self.position < head.len() {
+                Some(head[self.position])
+            } else {
+                pop_frame(&self.inner, self.generation, &mut self.skip)
+            };
+            [*l, *r] = frame.unwrap_or_else(|| {
+                // The frame is skipped if it arrives later, to keep the sample in time
+                self.skip += 1;
+                underruns += 1;
+                [0.0; 2]
+            });
+            self.position += 1;
+        }
+        left[len..].fill(0.0);
+        right[len..].fill(0.0);
+
+        if underruns > 0 {
+            self.inner.underruns.fetch_add(underruns, Ordering::Relaxed);
+        }
+        if self.position == sample.frames() {
+            self.stop();
+        }
+        len
+    }
+}
+
+/// Pops the next streamed frame of the current playback of a [SampleStream], skipping the frames
+/// of previous playbacks and those which arrived too late.
+fn pop_frame(inner: &StreamInner, current: u32, skip: &mut usize) -> Option<[f32; 2]> {
+    loop {
+        // SAFETY: The stream is the only end which pops frames, and it is borrowed mutably by
+        // its only caller.
+        let (generation, frame) = unsafe { inner.frames.pop() }?;
+        if generation != current {
+            continue;
+        }
+        if *skip > 0 {
+            *skip -= 1;
+            continue;
+        }
+        return Some(frame);
+    }
+}
+
+impl DiskStreamer {
+    /// Creates a streamer with no streams.
+    pub fn new() -> Self {
+        Self {
+            streams: vec![],
+            bytes: vec![],
+        }
+    }
+
+    /// Adds a stream, such as for one voice of a sampler.
+    ///
+    /// This allocates memory, so shouldn't be called from the audio thread.
+    ///
+    /// # Parameters
+    /// * `capacity` - The number of frames which can be read ahead, which should cover the
+    ///   longest time the streamer may take between services, at the highest playback speed.
+    pub fn add_stream(&mut self, capacity: usize) -> SampleStream {
+        assert!(capacity > 0, "Stream must have a capacity of at least one.");
+        let inner = Arc::new(StreamInner {
+            frames: RingBuffer::new(capacity),
+            requests: RingBuffer::new(REQUEST_CAPACITY),
+            underruns: AtomicUsize::new(0),
+        });
+        self.streams.push(StreamState {
+            inner: inner.clone(),
+            active: None,
+        });
+        SampleStream {
+            inner,
+            sample: None,
+            generation: 0,
+            position: 0,
+            skip: 0,
+        }
+    }
+
+    /// Gets the total number of frames which have been played as silence by every stream
+    /// because the streamer had fallen behind.
+    pub fn underruns(&self) -> usize {
+        let count = |stream: &StreamState| stream.inner.underruns.load(Ordering::Relaxed);
+        self.streams.iter().map(count).sum()
+    }
+
+    /// Handles the requests of every stream, and reads ahead as far as each stream has room.
+    ///
+    /// # Return
+    /// Returns the number of frames read.
+    pub fn service(&mut self) -> io::Result<usize> {
+        let mut count = 0;
+        for stream in &mut self.streams {
+            // SAFETY: The streamer is the only end which pops requests, and it is borrowed
+            // mutably.
+            while let Some(request) = unsafe { stream.inner.requests.pop() } {
+                stream.active = match request {
+                    Request::Start(generation, sample) => Some(ActiveStream {
+                        generation,
+                        next: sample.head.len(),
+                        sample,
+                        file: None,
+                    }),
+                    Request::Stop => None,
+                };
+            }
+            let Some(active) = &mut stream.active else {
+                continue;
+            };
+
+            let frames = &stream.inner.frames;
+            let room = frames.capacity() - frames.len();
+            let len = room
+                .min(active.sample.frames() - active.next)
+                .min(READ_FRAMES);
+            if len == 0 {
+                continue;
+            }
+            let file = match &mut active.file {
+                Some(file) => file,
+                None => active.file.insert(File::open(&active.sample.path)?),
+            };
+            let generation = active.generation;
+            active
+                .sample
+                .format
+                .read_frames(file, active.next, len, &mut self.bytes, |frame| {
+                    // SAFETY: The streamer is the only end which pushes frames, and it is borrowed
+                    // mutably. There is room for every frame, as only the stream can change the
+                    // length.
+                    let _ = unsafe { frames.push((generation, frame)) };
+                })?;
+            active.next += len;
+            count += len;
+            if active.next == active.sample.frames() {
+                stream.active = None;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Moves the streamer onto a thread of its own, which services it at the given interval.
+    ///
+    /// The thread stops at the first error reading a file, which is returned when the thread
+    /// is [stopped](StreamerThread::stop).
+    pub fn spawn(mut self, interval: Duration) -> StreamerThread {
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        let thread = std::thread::spawn(move || {
+            while !flag.load(Ordering::Relaxed) {
+                // Keep reading while there is work to do, and rest once every stream is full
+                if self.service()? == 0 {
+                    std::thread::sleep(interval);
+                }
+            }
+            Ok(())
+        });
+        StreamerThread {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Default for DiskStreamer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamerThread {
+    /// Stops the thread and waits for it to finish.
+    ///
+    /// # Return
+    /// Returns the error which stopped the thread early, if any.
+    pub fn stop(mut self) -> io::Result<()> {
+        self.join()
+    }
+
+    /// Stops the thread and waits for it to finish, if it hasn't already.
+    fn join(&mut self) -> io::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("Streamer thread panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for StreamerThread {
+    fn drop(&mut self) {
+        let _ = self.join();
+    }
+}
+
+impl WavFormat {
+    /// Reads the header of a WAV file, leaving the file positioned at an unspecified offset.
+    fn read(file: &mut File) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut header = [0; 12];
+        file.read_exact(&mut header)?;
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            return Err(invalid("Not a WAV file"));
+        }
+
+        let mut format = None;
+        loop {
+            let mut chunk = [0; 8];
+            file.read_exact(&mut chunk)?;
+            let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+            match &chunk[0..4] {
+                b"fmt " => {
+                    let mut fmt = [0; 16];
+                    file.read_exact(&mut fmt)?;
+                    let mut tag = u16::from_le_bytes([fmt[0], fmt[1]]);
+                    if tag == 0xfffe && size >= 26 {
+                        // The extensible format gives the real format at the start of its GUID
+                        let mut extension = [0; 10];
+                        file.read_exact(&mut extension)?;
+                        tag = u16::from_le_bytes([extension[8], extension[9]]);
+                        file.seek(SeekFrom::Current(size as i64 - 26))?;
+                    } else {
+                        file.seek(SeekFrom::Current(size as i64 - 16))?;
+                    }
+                    let channels = u16::from_le_bytes([fmt[2], fmt[3]]) as usize;
+                    let sample_rate = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
+                    let bits = u16::from_le_bytes([fmt[14], fmt[15]]);
+                    let float = match (tag, bits) {
+                        (1, 16 | 24) => false,
+                        (3, 32) => true,
+                        _ => return Err(invalid("Unsupported WAV sample format")),
+                    };
+                    if channels == 0 {
+                        return Err(invalid("WAV file has no channels"));
+                    }
+                    format = Some((channels, bits as usize / 8, float, sample_rate));
+                }
+                b"data" => {
+                    let (channels, bytes_per_sample, float, sample_rate) =
+                        format.ok_or_else(|| invalid("WAV data comes before its format"))?;
+                    return Ok(Self {
+                        channels,
+                        bytes_per_sample,
+                        float,
+                        sample_rate,
+                        data_offset: file.stream_position()?,
+                        frames: size as usize / (channels * bytes_per_sample),
+                    });
+                }
+                // Chunks are padded to an even number of bytes
+                _ => {
+                    file.seek(SeekFrom::Current((size + size % 2) as i64))?;
+                }
+            }
+        }
+    }
+
+    /// Reads and decodes frames from the file, passing each one to `f` as a stereo frame.
+    fn read_frames(
+        &self,
+        file: &mut File,
+        start: usize,
+        len: usize,
+        bytes: &mut Vec<u8>,
+        mut f: impl FnMut([f32; 2]),
+    ) -> io::Result<()> {
+        let frame_len = self.channels * self.bytes_per_sample;
+        file.seek(SeekFrom::Start(
+            self.data_offset + (start * frame_len) as u64,
+        ))?;
+        bytes.resize(len * frame_len, 0);
+        file.read_exact(bytes)?;
+
+        for frame in bytes.chunks_exact(frame_len) {
+            let sample = |channel: usize| {
+                let b = &frame[channel * self.bytes_per_sample..][..self.bytes_per_sample];
+                match (self.bytes_per_sample, self.float) {
+                    (2, _) => i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+                    (3, _) => i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2147483648.0,
+                    _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+                }
+            };
+            let left = sample(0);
+            let right = if self.channels > 1 { sample(1) } else { left };
+            f([left, right]);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a mono 16-bit WAV file whose frames count up from zero.
+    fn write_wav(name: &str, frames: usize) -> PathBuf {
+        let mut bytes = vec![];
+        let data_len = 2 * frames as u32;
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&48000u32.to_le_bytes());
+        bytes.extend_from_slice(&96000u32.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        for n in 0..frames {
+            bytes.extend_from_slice(&(n as i16).to_le_bytes());
+        }
+        let path = std::env::temp_dir().join(format!("reson-{}-{name}.wav", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    /// Converts a frame of a file written by `write_wav` back to its index.
+    fn index(sample: f32) -> usize {
+        (sample * 32768.0).round() as usize
+    }
+
+    #[test]
+    fn streams_the_body_after_the_head() {
+        let path = write_wav("stream", 1000);
+        let sample = Arc::new(StreamedSample::open(&path, 100).unwrap());
+        assert_eq!((sample.frames(), sample.head().len()), (1000, 100));
+
+        let mut streamer = DiskStreamer::new();
+        let mut stream = streamer.add_stream(256);
+        stream.start(sample);
+        let mut played = vec![];
+        while stream.is_playing() {
+            streamer.service().unwrap();
+            let (mut left, mut right) = ([1.0; 64], [1.0; 64]);
+            let len = stream.read([&mut left, &mut right]);
+            played.extend(left[..len].iter().map(|&s| index(s)));
+            assert!(right[len..].iter().all(|&s| s == 0.0));
+        }
+        assert_eq!(played, (0..1000).collect::<Vec<_>>());
+        assert_eq!(stream.underruns(), 0);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn underruns_play_silence_and_stay_in_time() {
+        let path = write_wav("underrun", 1000);
+        let sample = Arc::new(StreamedSample::open(&path, 100).unwrap());
+        let mut streamer = DiskStreamer::new();
+        let mut stream = streamer.add_stream(1000);
+        stream.start(sample);
+
+        // The streamer falls behind for the first 200 frames, past the end of the head
+        let (mut left, mut right) = ([0.0; 200], [0.0; 200]);
+        stream.read([&mut left, &mut right]);
+        assert_eq!(index(left[99]), 99);
+        assert!(left[100..].iter().all(|&s| s == 0.0));
+        assert_eq!(stream.underruns(), 100);
+
+        streamer.service().unwrap();
+        let (mut left, mut right) = ([0.0; 10], [0.0; 10]);
+        stream.read([&mut left, &mut right]);
+        assert_eq!(index(left[0]), 200);
+        assert_eq!(streamer.underruns(), 100);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,7 +1,16 @@
 use crate::fade::FadeBuffer;
+use crate::gate::{Gate, GateState};
+use crate::limiter::{Limiter, LimiterState};
+use crate::saturation::Saturation;
+use crate::smooth::Smoothed;
+use crate::trace::{Trace, TraceEvent};
 use crate::tuning::Tuning;
 use crate::voice::Voice;
-use crate::{MidiEvent, Note};
+use crate::{
+    Channel, LogLevel, LogRecord, Logger, MidiEvent, Note, Program, Resources, Velocity,
+};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU8, Ordering};
 use std::sync::Arc;
 
 /// A polyphonic synthesizer.
@@ -10,40 +19,256 @@ pub struct Synth<V: Voice + Clone> {
     opts: SynthOpts,
     /// Buffer used to hold the output from each voice before mixing.
     buffer: Vec<f32>,
+    /// Buffer used to hold the output before it is converted into the layout requested by the caller.
+    scratch: Vec<f32>,
     /// The prototype voice used to instantiate new voices.
     voice: V,
-    /// The bank of voices.
+    /// The shared data made available to each voice.
+    resources: Resources,
+    /// The gain applied to the mix to compensate for the nominal level of the prototype voice.
+    trim: f32,
+    /// The amount of memory allocated for voices and buffers.
+    capacity: SynthCapacity,
+    /// The bank of voices, of which the first `opts.max_voices` are in use.
     voices: Vec<VoiceHandle<V>>,
+    /// The stack of held notes in monophonic mode, with the most recently triggered note last.
+    held: Vec<HeldNote>,
     /// Monotonic counter used to track the order in which voices were triggered and released.
     counter: usize,
     /// Small buffer used to gracefully fade out stolen voices
-    fade_out: FadeBuffer<256>,
-    /// The current pitch bend ratio, to be multiplied with the base frequency of each voice.
-    pitch_bend: f32,
+    fade_out: FadeBuffer,
+    /// The current pitch bend ratio on each MIDI channel, to be multiplied with the base frequency
+    /// of each voice playing a note on that channel.
+    pitch_bend: [Smoothed; 16],
+    /// The master fine tune as a pitch ratio.
+    fine_tune: Smoothed,
+    /// The pitch ratio applied to voices on each MIDI channel, combining the pitch bend
+    /// and fine tune, which is updated at the start of each block.
+    pitch_ratio: [f32; 16],
+    /// The bank most recently selected via CC0/CC32 on each MIDI channel.
+    banks: [u16; 16],
+    /// The program most recently selected on each MIDI channel.
+    programs: [Program; 16],
+    /// Whether portamento is enabled, as set by the portamento on/off switch (CC65).
+    portamento_on: bool,
+    /// The portamento time overriding the settings, as set by portamento time (CC5), if any.
+    portamento_time: Option<f32>,
+    /// Whether legato is enabled overriding the settings, as set by the legato footswitch
+    /// (CC68), if any.
+    legato_switch: Option<bool>,
+    /// The note from which the next triggered note should glide, as set by portamento control (CC84).
+    /// This is ignored in polyphonic mode unless poly portamento is enabled.
+    portamento_source: Option<Note>,
+    /// The identifier to assign to the next triggered note.
+    next_id: u64,
+    /// The index of the voice which round-robin allocation considers first.
+    next_voice: usize,
+    /// Called when a voice starts playing a note.
+    on_voice_start: VoiceCallback,
+    /// Called when a voice is taken from the note it was playing to play another.
+    on_voice_steal: VoiceCallback,
+    /// Called when a voice stops playing a note and falls silent.
+    on_voice_end: VoiceCallback,
+    /// Called when a voice panics while processing a note, and is disabled.
+    on_voice_panic: VoiceCallback,
+    /// The solo and mute state of each voice, shared with every [VoiceDebug].
+    debug: VoiceDebug,
+    /// The trace recording everything which happens to the synth, if any.
+    trace: Option<Trace>,
+    /// The logger which diagnostics are sent to, if any.
+    logger: Option<Logger>,
+    /// The pitch of the most recently triggered note, which notes glide from in [GlideMode::Always].
+    last_pitch: Option<f32>,
     /// The sample rate.
     sample_rate: u32,
+    /// The number of samples processed since the synth was created.
+    position: u64,
+    /// The state of the random number generator used for panning.
+    rng: u32,
+    /// Whether the next alternately panned note should be panned to the right.
+    pan_right: bool,
+    /// The gain set by the volume controller (CC7) of each MIDI channel, which is applied to the
+    /// notes on that channel when `mpe` is enabled.
+    channel_volume: [f32; 16],
+    /// The stereo position set by the pan controller (CC10) of each MIDI channel, if any,
+    /// which is applied to the notes on that channel when `mpe` is enabled.
+    channel_pan: [Option<f32>; 16],
+    /// The gain set by the expression controller (CC11).
+    expression: Smoothed,
+    /// The master gain.
+    gain: Smoothed,
+    /// The state of the noise gate of each output bus.
+    gate: Vec<GateState>,
+    /// The state of the limiter of each output bus.
+    limiter: Vec<LimiterState>,
 }
 
-/// Configuration options for [Synth].
+/// Configuration options for [Synth] which determine how much memory is allocated,
+/// and so can only be set when the synth is created.
+#[derive(Copy, Clone, Debug)]
+pub struct SynthCapacity {
+    /// The number of samples processed at a time, used for allocating the internal buffers.
+    /// Larger blocks passed to `process` are split into blocks of this size.
+    /// This must be at least one.
+    pub max_block_size: usize,
+    /// The number of voices allocated, which bounds the polyphony set in [SynthOpts].
+    pub max_voices: usize,
+    /// The largest number of output buses which [Synth::process_buses] can render into,
+    /// each of which has its own noise gate and limiter.
+    pub max_buses: usize,
+}
+
+/// Configuration options for [Synth] which can be changed at any time,
+/// including from the audio thread.
 #[derive(Clone)]
 pub struct SynthOpts {
     /// The tuning system, which relates notes to their pitch in Hz.
     pub tuning: Arc<Tuning>,
-    /// The maximum number of samples that will be requested in one call to `process`.
-    /// Used for allocating the internal buffer.
-    pub max_block_size: usize,
     /// The maximum number of voices that can be simultaneously played.
+    /// This must not exceed the `max_voices` of the synth's [SynthCapacity].
     pub max_voices: usize,
     /// If `true`, the synthesizer acts as a monophonic synth, despite the value of `max_voices`.
+    ///
+    /// Every held key is remembered, so releasing the sounding note returns to the held note
+    /// chosen by `note_priority`, retriggering or gliding to it, rather than going silent.
     pub mono: bool,
-    /// The portamento setting. This only has an effect is `mono` is true.
+    /// Determines which held note is played in monophonic mode.
+    pub note_priority: NotePriority,
+    /// The portamento setting. This only has an effect if `mono` or `poly_portamento` is true.
     pub portamento: Portamento,
+    /// Determines which notes glide from the previous note when portamento is enabled.
+    pub glide_mode: GlideMode,
+    /// If `true`, portamento also applies in polyphonic mode, with each newly allocated voice
+    /// gliding from the pitch of the most recently triggered note, as chosen by `glide_mode`.
+    pub poly_portamento: bool,
+    /// The intervals in semitones, relative to the played note, of the chord played by every
+    /// triggered note, if any. For example, `[0, 4, 7]` plays a major triad on each note.
+    ///
+    /// Each interval of the chord is played by a voice of its own, and releasing the note
+    /// releases the whole chord. This only has an effect if `mono` is false.
+    pub chord_memory: Option<Arc<[i8]>>,
+    /// If `true`, notes played while another is held glide to the new pitch without
+    /// retriggering the voice. This only has an effect if `mono` is true,
+    /// and can be overridden at runtime with the legato footswitch (CC68).
+    ///
+    /// Notes which glide with portamento never retrigger the voice, whatever this is set to.
+    pub legato: bool,
+    /// Determines how a note triggered while the previous note is releasing continues from its
+    /// release tail. This only has an effect if `mono` is true.
+    pub mono_tail: MonoTail,
+    /// If `true`, notes keep playing after their keys are released, and are instead released
+    /// by pressing the key again, or all at once with [Synth::all_notes_off].
+    /// Turning latch off releases every latched note.
+    pub latch: bool,
+    /// How much the velocity of triggered notes affects the voices, from 0 to 1.
+    /// At 1, velocities are passed on unchanged, and at 0 every note is played with a fixed
+    /// velocity of 100, as on an organ. Values in between interpolate between the two.
+    pub velocity_sensitivity: f32,
+    /// If `true`, MIDI note on messages with a velocity of zero passed to [Synth::midi_event]
+    /// release the note, as many devices send them in place of note off messages.
+    /// This defaults to `true`, as otherwise such devices leave notes stuck on.
+    pub zero_velocity_note_off: bool,
+    /// The number of semitones by which triggered notes are transposed.
+    pub transpose: i8,
+    /// The number of octaves by which triggered notes are shifted, in addition to `transpose`.
+    pub octave: i8,
+    /// The master fine tune in cents, applied on top of the pitch given by the tuning.
+    /// Changes are smoothed to avoid audible steps.
+    pub fine_tune: f32,
     /// The maximum pitch bend of a MIDI pitch bend event in semitones.
     pub max_pitch_bend: f32,
+    /// The time constant in seconds used to smooth changes in pitch bend, which avoids audible
+    /// steps during slow bends. Zero applies changes in pitch bend immediately.
+    pub pitch_bend_smoothing: f32,
+    /// If `true`, the pitch of a voice is frozen when its note is released, so pitch bends and
+    /// the rest of any glide no longer affect it. Otherwise, released voices follow both,
+    /// as on most synthesizers.
+    pub freeze_release_pitch: bool,
+    /// How each triggered note is positioned in the stereo field.
+    pub pan: PanMode,
+    /// The stereo width of each voice, where 0 collapses the voice to mono
+    /// and 1 leaves it unchanged.
+    pub width: f32,
+    /// Determines which output bus of [Synth::process_buses] each note is rendered to.
+    pub bus_routing: BusRouting,
+    /// If `true`, each note is expected on a MIDI channel of its own, as sent by MPE controllers,
+    /// so the volume (CC7) and pan (CC10) controllers of a channel set the gain and stereo
+    /// position of its notes, as with [Synth::set_note_gain] and [Synth::set_note_pan].
+    /// Notes triggered on a channel start with the volume and pan it was last set to.
+    pub mpe: bool,
+    /// Determines which voice is stolen when a note is triggered and no voice is free.
+    pub steal_policy: StealPolicy,
+    /// The time in seconds over which a stolen voice is faded out, up to 50 milliseconds.
+    pub fade_time: f32,
+    /// The shape of the fade applied to a stolen voice.
+    pub fade_curve: FadeCurve,
+    /// If `true`, free voices are allocated in turn, rather than always preferring the first,
+    /// so that voices with per-voice character are not repeated immediately.
+    pub round_robin: bool,
+    /// The number of voices to keep free for new notes, by stealing the oldest released voices
+    /// as soon as fewer voices are free, so that fast passages don't need to steal voices which
+    /// are still held. This must be less than `max_voices`, and only applies in polyphonic mode.
+    pub reserved_voices: usize,
+    /// The maximum number of voices which can play the same note on the same channel at once,
+    /// if any. Retriggering a note which has reached the limit steals its oldest voice,
+    /// rather than a voice from another note. This only applies in polyphonic mode.
+    pub max_voices_per_note: Option<usize>,
+    /// If `true`, the released voice whose pitch is closest to a new note is stolen before any
+    /// other released voice, so that the remains of its release tail blend into the new note
+    /// rather than jumping. This overrides `steal_policy` when choosing between released voices.
+    pub nearest_release: bool,
+    /// The saturation applied to the mix of voices, if any.
+    pub saturation: Option<Saturation>,
+    /// The noise gate applied to the output, if any.
+    pub gate: Option<Gate>,
+    /// The limiting applied at the end of processing, to keep the output within ±1.
+    pub limiter: Limiter,
+}
+
+impl Default for SynthOpts {
+    /// Creates settings for an eight voice polyphonic synth in concert pitch, with every
+    /// optional feature disabled.
+    fn default() -> Self {
+        Self {
+            tuning: Tuning::concert_pitch(),
+            max_voices: 8,
+            mono: false,
+            note_priority: NotePriority::Last,
+            portamento: Portamento::Off,
+            glide_mode: GlideMode::Legato,
+            poly_portamento: false,
+            chord_memory: None,
+            legato: true,
+            mono_tail: MonoTail::Restart,
+            latch: false,
+            velocity_sensitivity: 1.0,
+            zero_velocity_note_off: true,
+            transpose: 0,
+            octave: 0,
+            fine_tune: 0.0,
+            max_pitch_bend: 2.0,
+            pitch_bend_smoothing: 0.0,
+            freeze_release_pitch: false,
+            pan: PanMode::Center,
+            width: 1.0,
+            bus_routing: BusRouting::Voice,
+            mpe: false,
+            steal_policy: StealPolicy::SameNoteFirst,
+            fade_time: 0.005,
+            fade_curve: FadeCurve::Linear,
+            round_robin: false,
+            reserved_voices: 0,
+            max_voices_per_note: None,
+            nearest_release: false,
+            saturation: None,
+            gate: None,
+            limiter: Limiter::Off,
+        }
+    }
 }
 
 /// The portamento setting for a synthesizer.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 pub enum Portamento {
     /// Portamento is disabled.
     Off,
@@ -53,16 +278,231 @@ pub enum Portamento {
     /// The voice will glide from one note to the next at a fixed rate,
     /// denoted in seconds per octave.
     Variable(f32),
+    /// The voice will step through the notes of the tuning from one note to the next
+    /// at a fixed rate, denoted in seconds per octave.
+    Glissando(f32),
+}
+
+/// Determines when a triggered note glides from the previous note.
+///
+/// In polyphonic mode, the previous note is the most recently triggered note on any voice.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum GlideMode {
+    /// Notes only glide when the previous key is still held (fingered portamento).
+    /// Detached notes start at exactly their own pitch.
+    Legato,
+    /// Notes also glide when the previous note has been released but is still sounding.
+    Sounding,
+    /// Notes always glide from the pitch of the previously triggered note,
+    /// even after it has been released and has fallen silent.
+    Always,
+}
+
+/// Determines what happens to the release tail of the previous note when a new note is
+/// triggered in monophonic mode.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MonoTail {
+    /// The tail is faded out and the voice restarts, gliding only as allowed by the glide mode.
+    Restart,
+    /// The tail is faded out and the voice restarts, always gliding from the pitch of the tail
+    /// when portamento is enabled.
+    Glide,
+    /// The new note is played on the releasing voice without resetting it, gliding from the
+    /// pitch of the tail. The voice is triggered with [Voice::retrigger], so that its envelope
+    /// can continue from its current level rather than restarting from silence.
+    Legato,
+}
+
+/// Determines which note is played when multiple notes are held in monophonic mode.
+///
+/// When the sounding note is released, the synth falls back to the held note with the
+/// next highest priority.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum NotePriority {
+    /// The most recently triggered note is played.
+    Last,
+    /// The lowest held note is played.
+    Low,
+    /// The highest held note is played.
+    High,
+}
+
+/// Determines which voice is reused when a note is triggered in polyphonic mode.
+///
+/// Unused voices are always preferred, followed by voices which have been released.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StealPolicy {
+    /// Steal the voice which was triggered or released the longest time ago.
+    Oldest,
+    /// Steal the voice which was triggered or released most recently.
+    Newest,
+    /// Steal the voice playing the lowest note.
+    LowestNote,
+    /// Steal the voice playing the highest note.
+    HighestNote,
+    /// Steal the voice whose note was triggered with the lowest velocity.
+    Quietest,
+    /// Steal the voice with the lowest output level, as reported by [Voice::current_level]
+    /// or otherwise as measured by the synth.
+    LowestLevel,
+    /// Reuse the voice already playing or releasing the same note on the same channel, if any,
+    /// and otherwise steal the oldest voice.
+    SameNoteFirst,
+}
+
+/// Determines which output bus of [Synth::process_buses] each note is rendered to.
+///
+/// The bus of a sounding note can also be set with [Synth::set_note_bus].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BusRouting {
+    /// Every note is rendered to the first bus.
+    Main,
+    /// Each note is rendered to the bus numbered by its MIDI channel, so that each part of a
+    /// multi-timbral setup, or each note from an MPE controller, can be processed separately.
+    Channel,
+    /// Each voice renders to the bus numbered by its index, so that with as many buses as
+    /// voices, every sounding note has a bus of its own.
+    Voice,
+}
+
+/// The shape of the fade applied to a voice which is stolen or silenced.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FadeCurve {
+    /// The gain falls linearly.
+    Linear,
+    /// The gain follows a quarter cosine, which keeps the power of the voice constant
+    /// when it is crossfaded with the voice replacing it.
+    EqualPower,
+}
+
+/// Determines how notes are positioned in the stereo field when triggered.
+#[derive(Copy, Clone)]
+pub enum PanMode {
+    /// Every note is centred.
+    Center,
+    /// Every note is panned to the same position, from -1 (left) to 1 (right).
+    Fixed(f32),
+    /// Each note is panned to a random position.
+    Random {
+        /// The maximum distance from the centre, between 0 and 1.
+        spread: f32,
+        /// If `true`, notes alternate between the left and right sides,
+        /// and only their distance from the centre is random.
+        alternate: bool,
+    },
+    /// Notes are panned from left to right across a range of the keyboard.
+    KeyTrack {
+        /// The note panned furthest to the left; lower notes are panned the same.
+        low: Note,
+        /// The note panned furthest to the right; higher notes are panned the same.
+        high: Note,
+        /// The distance from the centre of the lowest and highest notes, between 0 and 1.
+        width: f32,
+    },
+    /// Notes alternate between the left and right sides.
+    RoundRobin {
+        /// The distance of each note from the centre, between 0 and 1.
+        width: f32,
+    },
+}
+
+/// The sample rate a synth starts at, until the host sets its own with [Synth::set_sample_rate].
+const DEFAULT_SAMPLE_RATE: u32 = 48000;
+
+/// The time constant in seconds used to smooth changes to gain parameters.
+const SMOOTHING_TIME: f32 = 0.01;
+
+/// The portamento time in seconds (or seconds per octave) when portamento time (CC5) is at its maximum.
+const MAX_PORTAMENTO_TIME: f32 = 2.0;
+
+/// The velocity every note is played with when the velocity sensitivity is zero.
+const FIXED_VELOCITY: u8 = 100;
+
+/// The longest time in seconds over which a stolen voice can be faded out.
+const MAX_FADE_TIME: f32 = 0.05;
+
+/// The release velocity of notes released by a note on message with a velocity of zero.
+const DEFAULT_RELEASE_VELOCITY: u8 = 64;
+
+/// The level below which a released voice is considered silent, and is stolen first (-60 dB).
+const SILENT_LEVEL: f32 = 0.001;
+
+/// The initial state of the random number generator used for panning.
+pub(crate) const RNG_SEED: u32 = 0x9e37_79b9;
+
+/// An opaque identifier for a triggered note, which can be used to release exactly that note
+/// with [Synth::release_id], even when the same note has been triggered several times.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct NoteId(u64);
+
+/// A note which is currently held down.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct HeldNote {
+    /// The identifier of the note, as returned when it was triggered.
+    pub id: NoteId,
+    /// The MIDI channel the note was triggered on.
+    pub channel: Channel,
+    /// The MIDI note, between 0 and 127.
+    pub note: Note,
+    /// The velocity the note was triggered with.
+    pub velocity: Velocity,
+    /// The time the note was triggered, in samples since the synth was created.
+    pub start: u64,
 }
 
+/// A hook called with the note a voice is playing when the voice changes state.
+type VoiceCallback = Option<Box<dyn FnMut(HeldNote) + Send>>;
+
+/// A handle for soloing and muting individual voices of a [Synth] while it plays,
+/// which helps when diagnosing how notes are allocated to voices and stolen.
+///
+/// The handle is obtained with [Synth::voice_debug], and can be cloned and sent to another
+/// thread. Voices are identified by their index in the bank of voices. Muted voices are still
+/// allocated and processed as usual, but aren't heard, and while any voice is soloed only the
+/// soloed voices are heard.
+#[derive(Clone)]
+pub struct VoiceDebug(Arc<[AtomicU8]>);
+
+/// The flag of a voice in [VoiceDebug] which is set when the voice is muted.
+const MUTE: u8 = 1;
+
+/// The flag of a voice in [VoiceDebug] which is set when the voice is soloed.
+const SOLO: u8 = 2;
+
 /// Contextual information provided to a [VoiceHandle] when triggered or released.
 struct VoiceCtx {
     /// The sample rate in Hz.
     sample_rate: u32,
     /// The current portamento setting.
     portamento: Portamento,
+    /// The MIDI channel of the triggered note.
+    channel: u8,
+    /// The identifier of the triggered note.
+    id: NoteId,
+    /// The velocity the triggered note was played with, before velocity sensitivity is applied.
+    velocity: u8,
+    /// Whether overlapping notes should be played legato.
+    legato: bool,
+    /// Whether a releasing voice should be retriggered without being reset.
+    resume_tail: bool,
+    /// The pitch to glide from regardless of the voice's current pitch, if any.
+    glide_from: Option<f32>,
+    /// The tuning to quantize glides to, when the portamento is a glissando.
+    tuning: Option<Arc<Tuning>>,
+    /// The stereo position of the triggered note, from -1 (left) to 1 (right).
+    pan: f32,
+    /// The gain of the triggered note.
+    gain: f32,
+    /// The stereo width of the triggered note.
+    width: f32,
     /// The current value of the monotonic counter.
-    counter: usize
+    counter: usize,
+    /// The number of samples processed by the synth so far.
+    position: u64,
+    /// Whether the pitch of released voices should be frozen.
+    freeze_release_pitch: bool,
+    /// The pitch ratio applied to voices on each MIDI channel for the current block.
+    pitch_ratio: [f32; 16],
 }
 
 struct VoiceHandle<V: Voice> {
@@ -72,21 +512,79 @@ struct VoiceHandle<V: Voice> {
     phase: VoicePhase,
     /// The pitch of the currently playing note.
     pitch: f32,
+    /// The MIDI channel of the currently playing note.
+    channel: u8,
+    /// The identifier of the currently playing note.
+    id: NoteId,
+    /// The velocity of the currently playing note, as passed to the voice.
+    velocity: u8,
+    /// The velocity the currently playing note was played with, before velocity sensitivity.
+    played_velocity: u8,
+    /// The time the currently playing note was triggered, in samples.
+    start: u64,
+    /// The stereo position of the currently playing note, from -1 (left) to 1 (right).
+    pan: Smoothed,
+    /// The gain applied to the currently playing note.
+    gain: Smoothed,
+    /// The stereo width of the currently playing note.
+    width: f32,
+    /// The pitch ratio of the currently playing note's own pitch expression, which is kept when
+    /// the pitch of the voice is frozen.
+    bend: f32,
+    /// The output bus of the currently playing note.
+    bus: usize,
     /// Information about the current note glide, if one is in progress.
     glide: Option<GlideState>,
     /// The value of the monotonic counter at the time this voice was last triggered/released.
     counter: usize,
+    /// Whether the voice has been disabled after panicking, so is never triggered again.
+    disabled: bool,
+    /// Whether the pitch of the voice was frozen when it was released, including pitch bend.
+    frozen: bool,
+    /// The peak absolute sample value output in the most recent block.
+    peak: f32,
+    /// The RMS level output in the most recent block.
+    rms: f32,
+    /// The end of the output of the voice in the most recent block, before panning,
+    /// from which the voice is faded out if it panics.
+    #[cfg(feature = "catch-unwind")]
+    last: [Vec<f32>; 2],
+    /// The number of samples held in `last`.
+    #[cfg(feature = "catch-unwind")]
+    last_len: usize,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-enum VoicePhase {
+/// The phase of a voice.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VoicePhase {
+    /// The voice is playing a note which is held.
     On(Note),
+    /// The voice is playing a note which has been released.
     Released(Note),
+    /// The voice is inactive and producing silence.
     Off,
 }
 
+/// A snapshot of the state of a voice, which can be used to display voice activity.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct VoiceStatus {
+    /// The phase of the voice, including the note being played.
+    pub phase: VoicePhase,
+    /// The current pitch in Hz, accounting for glides and pitch bend.
+    pub pitch: f32,
+    /// The current offset from the pitch of the note in semitones, due to pitch bend
+    /// and the master fine tune, but not glides.
+    pub bend: f32,
+    /// The number of samples since the note was triggered.
+    pub age: u64,
+    /// The peak absolute sample value output by the voice in the most recent block.
+    pub peak: f32,
+    /// The RMS level output by the voice in the most recent block, over both channels.
+    pub rms: f32,
+}
+
 /// Represents the pitch of a voice, which may be in the middle of a glide.
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 struct GlideState {
     /// The base-2 logarithm of the start pitch.
     start: f32,
@@ -95,281 +593,1774 @@ struct GlideState {
     /// The duration of the glide in samples.
     duration: usize,
     /// The current elapsed time of the glide in samples.
-    time: usize
+    time: usize,
+    /// The tuning whose notes the pitch is quantized to, if the glide is a glissando.
+    tuning: Option<Arc<Tuning>>,
 }
 
 impl<V: Voice + Clone> Synth<V> {
     /// Creates a new polyphonic synth with a fixed number of voices.
     ///
+    /// The synth starts at a sample rate of 48 kHz, so that voices, smoothing and the fades of
+    /// stolen voices work straight away, but [set_sample_rate](Self::set_sample_rate) should be
+    /// called with the actual sample rate before processing.
+    ///
+    /// # Migrating from 0.1
+    /// In version 0.1, this took only the options and the voice. The settings which determine how
+    /// much memory is allocated, `max_block_size` and the number of voices, have since moved from
+    /// [SynthOpts] to [SynthCapacity], so that the options can be changed at any time with
+    /// [update_opts](Self::update_opts). Pass a capacity with the old values, and the number of
+    /// output buses passed to [process_buses](Self::process_buses) (or 1 if it isn't used).
+    /// The `max_voices` of [SynthOpts] remains, and sets the polyphony within that capacity.
+    ///
+    /// # Parameters
+    /// * `capacity` - The number of voices, output buses and the block size to allocate memory for.
+    /// * `opts` - Configuration options for the polyphonic synth.
+    /// * `voice` - A prototypical voice from which the bank of voices will be cloned.
+    pub fn new(capacity: SynthCapacity, opts: SynthOpts, voice: V) -> Self {
+        Self::with_resources(capacity, opts, voice, Resources::new())
+    }
+
+    /// Creates a new polyphonic synth whose voices share the given resources.
+    ///
+    /// The resources are given to the prototype voice before it is cloned,
+    /// so every voice shares the same data.
+    ///
     /// # Parameters
+    /// * `capacity` - The number of voices and the block size to allocate memory for.
     /// * `opts` - Configuration options for the polyphonic synth.
     /// * `voice` - A prototypical voice from which the bank of voices will be cloned.
-    pub fn new(opts: SynthOpts, voice: V) -> Self {
-        Self::validate_opts(&opts);
+    /// * `resources` - The shared data made available to each voice.
+    ///
+    /// # Panics
+    /// Panics if `capacity.max_block_size` is zero, or the settings are invalid
+    /// as described by [update_opts](Self::update_opts).
+    pub fn with_resources(
+        capacity: SynthCapacity,
+        opts: SynthOpts,
+        mut voice: V,
+        resources: Resources,
+    ) -> Self {
+        Self::validate_opts(&opts, &capacity);
+        voice.set_resources(&resources);
+        voice.set_sample_rate(DEFAULT_SAMPLE_RATE);
+        voice.prepare(capacity.max_block_size);
         let mut out = Self {
             opts,
-            buffer: vec![],
+            buffer: vec![0.0; capacity.max_block_size * 2],
+            scratch: vec![0.0; capacity.max_block_size * 2],
+            trim: trim(&voice),
+            voices: (0..capacity.max_voices)
+                .map(|_| VoiceHandle::new(voice.clone(), capacity.max_block_size))
+                .collect(),
             voice,
-            voices: vec![],
+            resources,
+            capacity,
+            // One entry for every note on every channel, so holding notes never allocates
+            held: Vec::with_capacity(16 * 128),
             counter: 0,
             fade_out: FadeBuffer::new(),
-            pitch_bend: 1.0,
+            pitch_bend: [Smoothed::new(1.0); 16],
+            fine_tune: Smoothed::new(1.0),
+            pitch_ratio: [1.0; 16],
+            banks: [0; 16],
+            programs: [Program::default(); 16],
+            portamento_on: true,
+            portamento_time: None,
+            legato_switch: None,
+            portamento_source: None,
+            next_id: 0,
+            next_voice: 0,
+            on_voice_start: None,
+            on_voice_steal: None,
+            on_voice_end: None,
+            on_voice_panic: None,
+            debug: VoiceDebug::new(capacity.max_voices),
+            trace: None,
+            logger: None,
+            last_pitch: None,
             sample_rate: 0,
+            position: 0,
+            rng: RNG_SEED,
+            pan_right: false,
+            channel_volume: [1.0; 16],
+            channel_pan: [None; 16],
+            expression: Smoothed::new(1.0),
+            gain: Smoothed::new(1.0),
+            gate: vec![GateState::default(); capacity.max_buses.max(1)],
+            limiter: vec![LimiterState::default(); capacity.max_buses.max(1)],
         };
+        out.set_sample_rate(DEFAULT_SAMPLE_RATE);
         out.update_opts(|_| {});
         out
     }
 
     /// Updates the settings for the synth.
     ///
-    /// This might result in the allocation of memory, if for example,
-    /// the maximum number of voices is increased or the maximum block size is increased.
+    /// This never allocates memory, so it is safe to call from the audio thread.
+    /// Voices which are no longer in use because `max_voices` was decreased are faded out.
+    ///
+    /// # Panics
+    /// Panics if the updated settings are invalid, which should be avoided on the audio thread:
+    /// * `max_voices` is zero or greater than the capacity of the synth.
+    /// * `reserved_voices` is not less than `max_voices`.
+    /// * `max_voices_per_note` is `Some(0)`.
     pub fn update_opts(&mut self, f: impl FnOnce(&mut SynthOpts)) {
+        let (latch, legato, portamento) = (self.opts.latch, self.opts.legato, self.opts.portamento);
         f(&mut self.opts);
-        Self::validate_opts(&self.opts);
-        self.voices.resize_with(self.opts.max_voices, || {
-            VoiceHandle::new(self.voice.clone())
-        });
-        self.buffer.resize(self.opts.max_block_size * 2, 0.0);
+        Self::validate_opts(&self.opts, &self.capacity);
+
+        // Changed settings take precedence over the controllers which override them
+        if self.opts.legato != legato {
+            self.legato_switch = None;
+        }
+        if self.opts.portamento != portamento {
+            self.portamento_time = None;
+        }
+        if let Some(trace) = &mut self.trace {
+            trace.record_opts(self.position, &self.opts);
+        }
+        self.update_fade();
+        self.fine_tune.set_target(2f32.powf(self.opts.fine_tune / 1200.0));
+        self.update_pitch_bend_smoothing();
+        if !self.opts.mono {
+            self.held.clear();
+        }
+        if latch && !self.opts.latch {
+            self.release_all(None);
+        }
+        for voice in &mut self.voices[self.opts.max_voices..] {
+            if voice.active() {
+                notify(&mut self.on_voice_end, voice.note());
+                self.fade_out.add_voice(|buf| voice.process(&self.pitch_ratio, self.position, buf));
+                voice.reset();
+            }
+        }
+    }
+
+    /// Sets a hook which is called with the note being played whenever a voice starts playing a note.
+    pub fn on_voice_start(&mut self, f: impl FnMut(HeldNote) + Send + 'static) {
+        self.on_voice_start = Some(Box::new(f));
+    }
+
+    /// Sets a hook which is called with the note a voice was playing whenever the voice is taken
+    /// to play another note before it has fallen silent.
+    ///
+    /// In monophonic mode, this includes the voice being retriggered or taken from a release
+    /// tail, but not gliding to a new note legato, where the voice is only re-pitched.
+    pub fn on_voice_steal(&mut self, f: impl FnMut(HeldNote) + Send + 'static) {
+        self.on_voice_steal = Some(Box::new(f));
+    }
+
+    /// Sets a hook which is called with the note a voice was playing whenever the voice falls
+    /// silent, or is silenced by [panic](Self::panic) or a change of options.
+    pub fn on_voice_end(&mut self, f: impl FnMut(HeldNote) + Send + 'static) {
+        self.on_voice_end = Some(Box::new(f));
+    }
+
+    /// Sets a hook which is called with the note a voice was playing whenever the voice panics
+    /// while processing. The voice is faded out over the rest of the block and never triggered
+    /// again, until it is replaced with [revive_voices](Self::revive_voices),
+    /// [update_voice](Self::update_voice) or [reset](Self::reset).
+    ///
+    /// Panics are only caught when the `catch-unwind` feature is enabled. The panic is still
+    /// reported to the panic hook first, and the default hook prints it to stderr from the audio
+    /// thread, which may block. Applications which catch panics in voices should install a hook
+    /// with [std::panic::set_hook] which doesn't block, such as one which only records the message.
+    pub fn on_voice_panic(&mut self, f: impl FnMut(HeldNote) + Send + 'static) {
+        self.on_voice_panic = Some(Box::new(f));
+    }
+
+    /// Gets a handle for soloing and muting individual voices, which is shared with the synth.
+    pub fn voice_debug(&self) -> VoiceDebug {
+        self.debug.clone()
+    }
+
+    /// Starts recording everything which happens to the synth into the given trace,
+    /// beginning with the current sample rate, settings and gain.
+    ///
+    /// For the trace to be replayed faithfully, it should be started as soon as the synth
+    /// is created.
+    pub fn start_trace(&mut self, mut trace: Trace) {
+        trace.record(self.position, TraceEvent::SampleRate(self.sample_rate));
+        trace.record_opts(self.position, &self.opts);
+        trace.record(self.position, TraceEvent::Gain(self.gain.target()));
+        self.trace = Some(trace);
+    }
+
+    /// Stops recording, returning the trace which was being recorded, if any.
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        self.trace.take()
+    }
+
+    /// Sets the logger which diagnostics, such as voices being stolen or panicking, are sent to.
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.logger = Some(logger);
+    }
+
+    /// Records an event into the trace, if one is being recorded.
+    fn record(&mut self, event: TraceEvent) {
+        if let Some(trace) = &mut self.trace {
+            trace.record(self.position, event);
+        }
+    }
+
+    /// Gets the amount of memory allocated for voices and buffers.
+    pub fn capacity(&self) -> SynthCapacity {
+        self.capacity
     }
 
     /// Updates the bank of voices by cloning the provided prototype voice.
     ///
     /// This results in all notes being immediately reset and silenced.
-    pub fn update_voice(&mut self, voice: V) {
+    pub fn update_voice(&mut self, mut voice: V) {
+        voice.set_resources(&self.resources);
+        voice.set_sample_rate(self.sample_rate);
+        voice.prepare(self.capacity.max_block_size);
+        self.trim = trim(&voice);
         self.voice = voice;
+        for index in 0..self.voices.len() {
+            notify(&mut self.on_voice_end, self.voices[index].note());
+            self.voices[index] = self.clone_voice();
+        }
+    }
+
+    /// Replaces every voice which was disabled after panicking with a fresh clone of the prototype
+    /// voice, so that it can play notes again. Notes which are playing are not affected.
+    ///
+    /// Cloning the voices may allocate memory, depending on the voice.
+    pub fn revive_voices(&mut self) {
+        for index in 0..self.voices.len() {
+            if self.voices[index].disabled {
+                self.voices[index] = self.clone_voice();
+            }
+        }
+    }
+
+    /// Clones the prototype voice, configured for the current sample rate.
+    fn clone_voice(&self) -> VoiceHandle<V> {
+        let mut voice = VoiceHandle::new(self.voice.clone(), self.capacity.max_block_size);
+        voice.set_sample_rate(self.sample_rate);
+        voice.voice.prepare(self.capacity.max_block_size);
+        voice
+    }
+
+    /// Replaces the shared data made available to each voice.
+    ///
+    /// Notes which are playing continue without being reset.
+    pub fn set_resources(&mut self, resources: Resources) {
+        self.resources = resources;
+        self.voice.set_resources(&self.resources);
         for voice in &mut self.voices {
-            *voice = VoiceHandle::new(self.voice.clone());
+            voice.voice.set_resources(&self.resources);
         }
     }
 
-    /// Sets the sample rate.
+    /// Swaps in new shared data for each voice, leaving the previous data in `resources`.
+    ///
+    /// Unlike [set_resources](Self::set_resources), the previous data isn't dropped, so it can
+    /// be handed back to another thread to be freed, as a [ResourceLoader](crate::ResourceLoader)
+    /// does. Notes which are playing continue without being reset.
+    pub fn swap_resources(&mut self, resources: &mut Resources) {
+        std::mem::swap(&mut self.resources, resources);
+        self.voice.set_resources(&self.resources);
+        for voice in &mut self.voices {
+            voice.voice.set_resources(&self.resources);
+        }
+    }
+
+    /// Gets the shared data made available to each voice.
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    /// Sets the sample rate, and [prepares](Voice::prepare) every voice for it.
+    ///
+    /// This allocates memory, so shouldn't be called from the audio thread.
     pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.record(TraceEvent::SampleRate(sample_rate));
         self.sample_rate = sample_rate;
+        self.fade_out.set_capacity((MAX_FADE_TIME * sample_rate as f32).ceil() as usize);
+        self.update_fade();
+        self.expression.set_time(SMOOTHING_TIME, sample_rate);
+        self.gain.set_time(SMOOTHING_TIME, sample_rate);
+        self.fine_tune.set_time(SMOOTHING_TIME, sample_rate);
+        self.update_pitch_bend_smoothing();
+        for limiter in &mut self.limiter {
+            limiter.set_sample_rate(sample_rate);
+        }
         self.voice.set_sample_rate(sample_rate);
+        self.voice.prepare(self.capacity.max_block_size);
         for voice in &mut self.voices {
             voice.set_sample_rate(sample_rate);
+            voice.voice.prepare(self.capacity.max_block_size);
         }
     }
 
-    /// Triggers a note.
-    ///
-    /// # Parameters
-    /// * `note` - The MIDI note being triggered, between 0 and 127.
-    /// * `velocity` - The velocity of the note, between 0 and 127.
-    pub fn trigger(&mut self, note: Note, velocity: u8) {
-        let ctx = self.voice_ctx();
+    /// Gets the sample rate in Hz.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
 
-        let voice = if self.opts.mono {
-            &mut self.voices[0]
-        } else {
-            let voice = self
-                .voices
-                .iter_mut()
-                .min_by_key(|v| v.priority(note))
-                .unwrap();
+    /// Gets the number of samples by which the output is delayed, which should be reported to the
+    /// host so that it can compensate. This is only non-zero with a [Limiter::TruePeak], and
+    /// changes with its lookahead and the sample rate.
+    pub fn latency(&self) -> usize {
+        self.opts.limiter.latency(self.sample_rate)
+    }
 
-            if voice.active() {
-                // Voice is stolen, so fade out
-                self.fade_out.add_voice(|buf| voice.process(self.pitch_bend, buf));
-                voice.reset();
+    /// Gets the position of the sample clock, which is the number of samples processed since the
+    /// synth was created. Effects following the synth can use this to stay in time with voices.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Returns the synth to the state it was in when it was created, so that rendering the same
+    /// events again produces exactly the same output.
+    ///
+    /// Every voice is silenced immediately and recloned from the prototype voice, including
+    /// voices which were disabled after panicking, and the fade buffer, limiter, pitch bend,
+    /// controllers, programs, sample clock and random panning are reset. The settings, master
+    /// gain, sample rate, resources and hooks are kept. Cloning the voices may allocate memory,
+    /// depending on the voice.
+    pub fn reset(&mut self) {
+        self.record(TraceEvent::Reset);
+        for index in 0..self.voices.len() {
+            if self.voices[index].active() {
+                notify(&mut self.on_voice_end, self.voices[index].note());
             }
+            self.voices[index] = self.clone_voice();
+        }
+        self.held.clear();
+        self.counter = 0;
+        self.fade_out.clear();
 
-            voice
+        // Smoothed parameters start settled, as when the synth was created
+        let settled = |value: f32| {
+            let mut smoothed = Smoothed::new(value);
+            smoothed.set_time(SMOOTHING_TIME, self.sample_rate);
+            smoothed
         };
+        self.fine_tune = settled(1.0);
+        self.fine_tune.set_target(2f32.powf(self.opts.fine_tune / 1200.0));
+        self.expression = settled(1.0);
+        self.gain = settled(self.gain.target());
+        self.pitch_bend = [Smoothed::new(1.0); 16];
+        self.update_pitch_bend_smoothing();
+        self.pitch_ratio = [1.0; 16];
 
-        let pitch = self.opts.tuning.pitch(note);
-        voice.trigger(note, velocity, pitch, &ctx);
-        self.counter += 1;
+        self.banks = [0; 16];
+        self.programs = [Program::default(); 16];
+        self.portamento_on = true;
+        self.portamento_time = None;
+        self.legato_switch = None;
+        self.portamento_source = None;
+        self.next_id = 0;
+        self.next_voice = 0;
+        self.last_pitch = None;
+        self.position = 0;
+        self.rng = RNG_SEED;
+        self.pan_right = false;
+        self.channel_volume = [1.0; 16];
+        self.channel_pan = [None; 16];
+        self.gate.fill(GateState::default());
+        self.limiter.iter_mut().for_each(LimiterState::reset);
     }
 
-    /// Releases a note.
-    pub fn release(&mut self, note: Note) {
-        let ctx = self.voice_ctx();
+    /// Triggers a note on MIDI channel 0.
+    ///
+    /// # Parameters
+    /// * `note` - The MIDI note being triggered, between 0 and 127.
+    /// * `velocity` - The velocity of the note, between 0 and 127.
+    ///
+    /// # Return
+    /// Returns an identifier which can be passed to [release_id](Self::release_id).
+    pub fn trigger(&mut self, note: Note, velocity: Velocity) -> NoteId {
+        self.note_on(Channel::new(0), note, velocity)
+    }
 
-        let voice = if self.opts.mono {
-            let voice = &mut self.voices[0];
-            (voice.note_on() == Some(note)).then_some(voice)
-        } else {
-            self.voices.iter_mut().find(|v| v.note_on() == Some(note))
-        };
+    /// Triggers a note on the given MIDI channel.
+    ///
+    /// The voice playing the note is affected by the pitch bend of that channel.
+    ///
+    /// # Parameters
+    /// * `channel` - The MIDI channel, between 0 and 15.
+    /// * `note` - The MIDI note being triggered, between 0 and 127.
+    /// * `velocity` - The velocity of the note, between 0 and 127.
+    ///
+    /// # Return
+    /// Returns an identifier which can be passed to [release_id](Self::release_id).
+    /// If a latched note is released by the key being pressed again, its identifier is returned.
+    pub fn note_on(&mut self, channel: Channel, note: Note, velocity: Velocity) -> NoteId {
+        let channel = channel.get();
+        let event = MidiEvent::NoteOn { channel, note, velocity: velocity.get() };
+        self.record(TraceEvent::Midi(event));
 
-        if let Some(voice) = voice {
-            voice.release(&ctx);
-            self.counter += 1;
+        // With latch, pressing the key of a latched note releases it
+        if self.opts.latch {
+            let same_key = |held: &HeldNote| (held.channel.get(), held.note) == (channel, note);
+            let latched = self.held_notes().find(same_key);
+            if let Some(latched) = latched {
+                self.release_matching(Velocity::new(0), |held| held.id == latched.id);
+                return latched.id;
+            }
         }
-    }
 
-    /// Sets the global pitch bend as a raw 14-bit MIDI value.
-    pub fn set_pitch_bend_raw(&mut self, value: u16) {
-        let semitones = ((value as f32 - 8192.0) / 8192.0) * self.opts.max_pitch_bend;
-        self.set_pitch_bend(semitones);
-    }
+        let id = NoteId(self.next_id);
+        self.next_id += 1;
 
-    /// Sets the global pitch bend in semitones.
-    pub fn set_pitch_bend(&mut self, semitones: f32) {
-        self.pitch_bend = 2f32.powf(semitones / 12.0);
-    }
+        let held = HeldNote {
+            id,
+            channel: Channel::new(channel),
+            note,
+            velocity,
+            start: self.position,
+        };
+        if self.opts.mono {
+            // Add the note to the top of the stack of held notes
+            self.held.retain(|held| (held.channel.get(), held.note) != (channel, note));
+            self.held.push(held);
 
-    /// Processes a MIDI message.
-    pub fn midi_event(&mut self, event: MidiEvent) {
-        match event {
-            MidiEvent::NoteOn { note, velocity, .. } => self.trigger(note, velocity),
-            MidiEvent::NoteOff { note, .. } => self.release(note),
-            MidiEvent::PitchBend { value, .. } => self.set_pitch_bend_raw(value),
+            // Only play the note if it takes priority over the other held notes,
+            // or the voice isn't already playing the note which does
+            let next = self.mono_note().unwrap();
+            let playing = self.voices[0].held().map(|held| held.id);
+            if next.id == id || playing != Some(next.id) {
+                self.trigger_voice(next);
+            }
+        } else {
+            self.trigger_voice(held);
         }
+
+        id
     }
 
-    /// Synthesizes a block of audio into `output`.
-    pub fn process(&mut self, output: [&mut [f32]; 2]) {
-        let [left, right] = output;
+    /// Triggers a note on newly allocated voices, or on the only voice in monophonic mode.
+    fn trigger_voice(&mut self, held: HeldNote) {
+        let HeldNote { id, note, .. } = held;
+        let channel = held.channel.get();
+        let mut ctx = VoiceCtx {
+            pan: self.next_pan(note),
+            channel,
+            id,
+            velocity: held.velocity.get(),
+            ..self.voice_ctx()
+        };
+        if self.opts.mpe {
+            ctx.gain = self.channel_volume[channel as usize];
+            ctx.pan = self.channel_pan[channel as usize].unwrap_or(ctx.pan);
+        }
 
-        let len = left.len();
-        assert_eq!(right.len(), len);
-        assert!(len <= self.opts.max_block_size);
+        if self.opts.mono {
+            let voice = &mut self.voices[0];
+            // Overlapping notes are played legato when enabled, and whenever they glide with
+            // portamento, as a glide between held notes never retriggers the voice
+            let overlapping = voice.note_on().is_some();
+            let legato = overlapping && (ctx.legato || ctx.portamento != Portamento::Off);
+            ctx.legato = legato;
+            let tail = voice.active() && voice.note_on().is_none();
 
-        // Prepare temporary buffers for each voice's output.
-        let (left_temp, right_temp) = self.buffer[..2 * len].split_at_mut(len);
+            // The voice is only stolen if it is reassigned, rather than re-pitched legato
+            if voice.active() && !legato {
+                notify(&mut self.on_voice_steal, voice.note());
+            }
 
-        // Track whether any audio has been written to output.
-        let mut written = false;
+            // Continue from the release tail of the previous note, if enabled
+            if tail && self.opts.mono_tail != MonoTail::Restart {
+                ctx.glide_from.get_or_insert(voice.pitch());
+            }
+            ctx.resume_tail = tail && self.opts.mono_tail == MonoTail::Legato;
 
-        // Process each active voice in turn.
-        let voices = if self.opts.mono {
-            &mut self.voices[..1]
+            if voice.active() && !legato && !ctx.resume_tail {
+                // Retrigger the voice, gliding from its current pitch if its note is still held,
+                // or still sounding when the glide mode allows it
+                if voice.note_on().is_some() || self.opts.glide_mode != GlideMode::Legato {
+                    ctx.glide_from.get_or_insert(voice.pitch());
+                }
+                if self.debug.heard(0, self.debug.any_soloed()) {
+                    let (pitch_ratio, position) = (&self.pitch_ratio, self.position);
+                    self.fade_out.add_voice(|buf| voice.process(pitch_ratio, position, buf));
+                }
+                voice.reset();
+            } else if !voice.active() && self.opts.glide_mode == GlideMode::Always {
+                // Glide from the last note, even though it is no longer sounding
+                if let Some(pitch) = self.last_pitch {
+                    ctx.glide_from.get_or_insert(pitch);
+                }
+            }
+
+            self.start_voice(0, held, 0, &ctx);
         } else {
-            &mut self.voices
-        };
-        for handle in voices {
-            if !handle.active() {
-                continue;
+            // Glide from the last note when poly portamento is enabled, before a voice is stolen
+            let glide = self.opts.poly_portamento
+                && match self.opts.glide_mode {
+                    GlideMode::Legato => self.voices.iter().any(|v| v.note_on().is_some()),
+                    GlideMode::Sounding => self.voices.iter().any(|v| v.active()),
+                    GlideMode::Always => true,
+                };
+            if let Some(pitch) = self.last_pitch.filter(|_| glide) {
+                ctx.glide_from.get_or_insert(pitch);
             }
-            if written {
-                handle.process(self.pitch_bend, [left_temp, right_temp]);
-                add_buffers(left, left_temp);
-                add_buffers(right, right_temp);
-            } else {
-                handle.process(self.pitch_bend, [left, right]);
-                written = true;
+
+            if let Some(max) = self.opts.max_voices_per_note {
+                self.limit_instances(channel, note, max - 1);
             }
-        }
 
-        // If no voices are sounding, ensure the output buffer is filled with silence.
-        if !written {
-            left.fill(0.0);
-            right.fill(0.0);
+            // With chord memory, the note plays a voice for each interval of the chord
+            let chord = self.opts.chord_memory.clone();
+            for &interval in chord.as_deref().unwrap_or(&[0]) {
+                let index = self.allocate_voice(channel, note, self.sounding(note, interval));
+                self.start_voice(index, held, interval, &ctx);
+            }
+            self.reserve_voices();
         }
 
-        // Apply the fade buffer
-        self.fade_out.process([left, right]);
+        self.counter += 1;
+        self.portamento_source = None;
     }
 
-    /// Validates the synthesiser options.
-    fn validate_opts(opts: &SynthOpts) {
-        if opts.max_voices == 0 {
-            panic!("Synth must have at least one voice.");
-        }
-    }
+    /// Chooses the voice to play a note in polyphonic mode, stealing it if it is still sounding.
+    ///
+    /// # Parameters
+    /// * `channel` - The MIDI channel of the note being triggered.
+    /// * `note` - The MIDI note being triggered.
+    /// * `sounding` - The note after transposition, whose pitch will be played.
+    ///
+    /// # Return
+    /// Returns the index of the voice.
+    fn allocate_voice(&mut self, channel: u8, note: Note, sounding: Note) -> usize {
+        let nearest = self.opts.nearest_release.then(|| self.opts.tuning.pitch(sounding));
 
-    /// Gets the context to pass to a voice being triggered/released.
-    fn voice_ctx(&self) -> VoiceCtx {
-        VoiceCtx {
-            sample_rate: self.sample_rate,
-            portamento: self.opts.portamento,
-            counter: self.counter
+        // With round-robin allocation, ties are broken in favour of the voice following
+        // the one most recently allocated
+        let num_voices = self.opts.max_voices;
+        let next_voice = if self.opts.round_robin { self.next_voice } else { 0 };
+        let (index, _) = self.voices[..num_voices]
+            .iter()
+            .enumerate()
+            .min_by_key(|(i, v)| {
+                let turn = (i + num_voices - next_voice % num_voices) % num_voices;
+                (v.priority(channel, note, self.opts.steal_policy, nearest), turn)
+            })
+            .unwrap();
+        self.next_voice = index + 1;
+
+        if self.voices[index].active() {
+            self.steal_voice(index, "Voice stolen");
         }
+
+        index
     }
-}
 
-impl<V: Voice> VoiceHandle<V> {
-    fn new(voice: V) -> Self {
-        Self {
-            voice,
-            phase: VoicePhase::Off,
-            pitch: 0.0,
-            glide: None,
-            counter: 0,
+    /// Steals the voices playing the oldest instances of a note until no more than the given
+    /// number of instances remain, where the voices of a chord count as a single instance.
+    fn limit_instances(&mut self, channel: u8, note: Note, max: usize) {
+        let same_key = |v: &VoiceHandle<V>| {
+            v.note().is_some_and(|held| (held.channel.get(), held.note) == (channel, note))
+        };
+        loop {
+            // Count each instance by the first of its voices
+            let voices = &self.voices[..self.opts.max_voices];
+            let instances = voices
+                .iter()
+                .enumerate()
+                .filter(|&(i, v)| same_key(v) && !voices[..i].iter().any(|w| w.id == v.id))
+                .count();
+            if instances <= max {
+                break;
+            }
+            let oldest = voices.iter().filter(|v| same_key(v)).min_by_key(|v| v.counter);
+            let id = oldest.unwrap().id;
+            for index in 0..self.opts.max_voices {
+                if same_key(&self.voices[index]) && self.voices[index].id == id {
+                    self.steal_voice(index, "Voice stolen");
+                }
+            }
         }
     }
 
-    /// Returns `true` if the voice is not producing silence.
-    fn active(&self) -> bool {
-        self.phase != VoicePhase::Off
+    /// Steals the oldest released voices until the reserved number of voices are free,
+    /// so that new notes don't need to steal a voice which is still sounding.
+    fn reserve_voices(&mut self) {
+        let voices = &self.voices[..self.opts.max_voices];
+        let free = voices.iter().filter(|v| !v.active() && !v.disabled).count();
+        for _ in free..self.opts.reserved_voices {
+            let released = self.voices[..self.opts.max_voices]
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| matches!(v.phase, VoicePhase::Released(_)) && !v.disabled)
+                .min_by_key(|(_, v)| v.counter);
+            match released {
+                Some((index, _)) => self.steal_voice(index, "Voice reserved"),
+                None => break,
+            }
+        }
     }
 
-    /// Gets the note that the voice is currently playing, if it is in the `On` phase.
-    fn note_on(&self) -> Option<Note> {
-        if let VoicePhase::On(note) = self.phase {
-            Some(note)
-        } else {
-            None
+    /// Fades out and resets the active voice with the given index so that it can play another
+    /// note, logging the given message.
+    fn steal_voice(&mut self, index: usize, message: &'static str) {
+        let voice = &mut self.voices[index];
+        notify(&mut self.on_voice_steal, voice.note());
+        let stolen = voice.note().map(|held| held.note as f32);
+        log(&mut self.logger, LogLevel::Debug, message, stolen, self.position);
+        if self.debug.heard(index, self.debug.any_soloed()) {
+            self.fade_out.add_voice(|buf| voice.process(&self.pitch_ratio, self.position, buf));
         }
+        voice.reset();
     }
 
-    /// Gets the priority used for voice allocation, with the lowest priority being preferred.
-    fn priority(&self, note: Note) -> usize {
-        match self.phase {
-            // Note has been re-triggered
-            VoicePhase::On(n) if n == note => 0,
-            // Unused voice
-            VoicePhase::Off => 1,
-            // Released voice for the same note
-            VoicePhase::Released(n) if n == note => 2,
-            // Oldest released note
-            VoicePhase::Released(_) => 3 + self.counter,
-            // Oldest triggered note
-            VoicePhase::On(_) => usize::MAX / 2 + self.counter,
-        }
+    /// Triggers a note on the voice with the given index, shifted by the given interval
+    /// in semitones.
+    fn start_voice(&mut self, index: usize, held: HeldNote, interval: i8, ctx: &VoiceCtx) {
+        let HeldNote { note, velocity, .. } = held;
+        let sounding = self.sounding(note, interval);
+        let pitch = self.opts.tuning.pitch(sounding);
+        let velocity = self.apply_sensitivity(velocity).get();
+        let bus = match self.opts.bus_routing {
+            BusRouting::Main => 0,
+            BusRouting::Channel => ctx.channel as usize,
+            BusRouting::Voice => index,
+        };
+        let voice = &mut self.voices[index];
+        voice.trigger(note, sounding, velocity, pitch, ctx);
+        voice.bus = bus;
+        notify(&mut self.on_voice_start, voice.note());
+        self.last_pitch = Some(pitch);
     }
 
-    /// Sets the sample rate.
-    fn set_sample_rate(&mut self, sample_rate: u32) {
-        self.voice.set_sample_rate(sample_rate);
+    /// Transposes a note, shifted by the given interval in semitones, to the note whose pitch
+    /// is played, keeping it within the MIDI range.
+    fn sounding(&self, note: Note, interval: i8) -> Note {
+        let shift = self.opts.transpose as i32 + 12 * self.opts.octave as i32 + interval as i32;
+        (note as i32 + shift).clamp(0, 127) as Note
     }
 
-    /// Resets the voice.
-    fn reset(&mut self) {
-        self.voice.reset();
-        self.phase = VoicePhase::Off;
+    /// Releases a note on MIDI channel 0.
+    ///
+    /// # Parameters
+    /// * `note` - The MIDI note being released, between 0 and 127.
+    /// * `velocity` - The release velocity of the note, between 0 and 127.
+    pub fn release(&mut self, note: Note, velocity: Velocity) {
+        self.note_off(Channel::new(0), note, velocity);
     }
 
-    /// Triggers a note.
-    fn trigger(&mut self, note: Note, velocity: u8, pitch: f32, ctx: &VoiceCtx) {
-        if let Some(glide) = self.calc_glide(pitch, ctx) {
-            self.glide = Some(glide);
-        } else {
-            self.voice.trigger(note, velocity);
-            self.glide = None;
+    /// Releases a note on the given MIDI channel.
+    ///
+    /// # Parameters
+    /// * `channel` - The MIDI channel, between 0 and 15.
+    /// * `note` - The MIDI note being released, between 0 and 127.
+    /// * `velocity` - The release velocity of the note, between 0 and 127.
+    pub fn note_off(&mut self, channel: Channel, note: Note, velocity: Velocity) {
+        let channel = channel.get();
+        let event = MidiEvent::NoteOff { channel, note, velocity: velocity.get() };
+        self.record(TraceEvent::Midi(event));
+        if self.opts.latch {
+            return;
         }
+        self.release_matching(velocity, |held| (held.channel.get(), held.note) == (channel, note));
+    }
 
-        self.pitch = pitch;
-        self.phase = VoicePhase::On(note);
-        self.counter = ctx.counter;
+    /// Releases the note with the given identifier, if it is still held.
+    ///
+    /// Unlike [note_off](Self::note_off), this releases exactly the note which was triggered,
+    /// even if the same note has been triggered several times on the same channel.
+    ///
+    /// # Parameters
+    /// * `id` - The identifier returned when the note was triggered.
+    /// * `velocity` - The release velocity of the note, between 0 and 127.
+    pub fn release_id(&mut self, id: NoteId, velocity: Velocity) {
+        self.record(TraceEvent::ReleaseId(id, velocity.get()));
+        self.release_matching(velocity, |held| held.id == id);
     }
 
-    /// Releases the current note.
-    pub fn release(&mut self, ctx: &VoiceCtx) {
+    /// Releases the first held note which satisfies the given predicate.
+    fn release_matching(&mut self, velocity: Velocity, matches: impl Fn(&HeldNote) -> bool) {
+        let ctx = self.voice_ctx();
+        let plays = |voice: &VoiceHandle<V>| voice.held().is_some_and(|held| matches(&held));
+
+        if self.opts.mono {
+            self.held.retain(|held| !matches(held));
+
+            if !plays(&self.voices[0]) {
+                return;
+            }
+
+            // Fall back to the most appropriate note which is still held
+            if let Some(next) = self.mono_note() {
+                self.trigger_voice(next);
+                return;
+            }
+
+            self.voices[0].release(velocity, &ctx);
+        } else {
+            // Release every voice playing the note, of which there are several with chord memory
+            let Some(held) = self.voices.iter().find_map(|v| v.held().filter(|h| matches(h)))
+            else {
+                return;
+            };
+            for voice in &mut self.voices {
+                if voice.held().is_some_and(|h| h.id == held.id) {
+                    voice.release(velocity, &ctx);
+                }
+            }
+        }
+        self.counter += 1;
+    }
+
+    /// Sets the gain of a sounding note as a linear amplitude, which is smoothed to avoid clicks.
+    ///
+    /// Each note starts with a gain of 1. This has no effect if the note is no longer sounding.
+    ///
+    /// # Parameters
+    /// * `id` - The identifier returned when the note was triggered.
+    /// * `gain` - The gain of the note.
+    pub fn set_note_gain(&mut self, id: NoteId, gain: f32) {
+        self.record(TraceEvent::NoteGain(id, gain));
+        self.apply_note_gain(|voice| voice.id == id, gain);
+    }
+
+    /// Sets the stereo position of a sounding note, which is smoothed to avoid clicks.
+    ///
+    /// This replaces the position chosen by the synth's [PanMode] when the note was triggered,
+    /// and has no effect if the note is no longer sounding.
+    ///
+    /// # Parameters
+    /// * `id` - The identifier returned when the note was triggered.
+    /// * `pan` - The stereo position of the note, from -1 (left) to 1 (right).
+    pub fn set_note_pan(&mut self, id: NoteId, pan: f32) {
+        self.record(TraceEvent::NotePan(id, pan));
+        self.apply_note_pan(|voice| voice.id == id, pan);
+    }
+
+    /// Bends the pitch of a sounding note by the given number of semitones, on top of the pitch
+    /// bend of its channel, such as to play a per-note pitch expression.
+    ///
+    /// Each note starts unbent. Unlike pitch bend, this continues to affect a note after it is
+    /// released, even when the synth freezes the pitch of released notes. This has no effect if
+    /// the note is no longer sounding.
+    ///
+    /// # Parameters
+    /// * `id` - The identifier returned when the note was triggered.
+    /// * `semitones` - The bend of the note in semitones.
+    pub fn set_note_pitch(&mut self, id: NoteId, semitones: f32) {
+        self.record(TraceEvent::NotePitch(id, semitones));
+        let ratio = 2f32.powf(semitones / 12.0);
+        for voice in self.voices.iter_mut().filter(|voice| voice.active() && voice.id == id) {
+            voice.bend = ratio;
+        }
+    }
+
+    /// Sets the pressure of a sounding note, from 0 to 1, which is passed to its voice through
+    /// [Voice::set_pressure].
+    ///
+    /// This has no effect if the note is no longer sounding.
+    ///
+    /// # Parameters
+    /// * `id` - The identifier returned when the note was triggered.
+    /// * `pressure` - The pressure of the note.
+    pub fn set_note_pressure(&mut self, id: NoteId, pressure: f32) {
+        self.record(TraceEvent::NotePressure(id, pressure));
+        let pressure = pressure.clamp(0.0, 1.0);
+        for voice in self.voices.iter_mut().filter(|voice| voice.active() && voice.id == id) {
+            voice.voice.set_pressure(pressure);
+        }
+    }
+
+    /// Sets the timbre of a sounding note, from 0 to 1, which is passed to its voice through
+    /// [Voice::set_timbre].
+    ///
+    /// This has no effect if the note is no longer sounding.
+    ///
+    /// # Parameters
+    /// * `id` - The identifier returned when the note was triggered.
+    /// * `timbre` - The timbre of the note.
+    pub fn set_note_timbre(&mut self, id: NoteId, timbre: f32) {
+        self.record(TraceEvent::NoteTimbre(id, timbre));
+        let timbre = timbre.clamp(0.0, 1.0);
+        for voice in self.voices.iter_mut().filter(|voice| voice.active() && voice.id == id) {
+            voice.voice.set_timbre(timbre);
+        }
+    }
+
+    /// Processes a MIDI 2.0 registered per-note controller, which affects a single sounding note.
+    ///
+    /// Per-note volume (controller 7) sets the gain of the note, with the same curve as the
+    /// volume controller (CC7), and per-note pan (controller 10) sets its stereo position,
+    /// centred at `0x8000_0000`. Other controllers are ignored.
+    ///
+    /// # Parameters
+    /// * `channel` - The MIDI channel of the note, between 0 and 15.
+    /// * `note` - The MIDI note, between 0 and 127, as it was triggered.
+    /// * `controller` - The registered per-note controller number.
+    /// * `value` - The 32-bit controller value.
+    pub fn per_note_controller(&mut self, channel: Channel, note: Note, controller: u8, value: u32) {
+        self.record(TraceEvent::PerNoteController(channel.get(), note, controller, value));
+        let plays = |voice: &VoiceHandle<V>| {
+            voice.note().is_some_and(|held| (held.channel, held.note) == (channel, note))
+        };
+        let value = (value as f64 / u32::MAX as f64) as f32;
+        match controller {
+            7 => self.apply_note_gain(plays, value * value),
+            10 => self.apply_note_pan(plays, 2.0 * value - 1.0),
+            _ => {}
+        }
+    }
+
+    /// Sets the gain of the sounding voices which satisfy the given predicate.
+    fn apply_note_gain(&mut self, matches: impl Fn(&VoiceHandle<V>) -> bool, gain: f32) {
+        for voice in self.voices.iter_mut().filter(|voice| voice.active() && matches(voice)) {
+            voice.gain.set_target(gain.max(0.0));
+        }
+    }
+
+    /// Sets the stereo position of the sounding voices which satisfy the given predicate.
+    fn apply_note_pan(&mut self, matches: impl Fn(&VoiceHandle<V>) -> bool, pan: f32) {
+        for voice in self.voices.iter_mut().filter(|voice| voice.active() && matches(voice)) {
+            voice.pan.set_target(pan.clamp(-1.0, 1.0));
+        }
+    }
+
+    /// Sets the pitch bend of every channel as a raw 14-bit MIDI value.
+    pub fn set_pitch_bend_raw(&mut self, value: u16) {
+        self.set_pitch_bend(self.raw_pitch_bend(value));
+    }
+
+    /// Sets the pitch bend of every channel in semitones.
+    pub fn set_pitch_bend(&mut self, semitones: f32) {
+        self.record(TraceEvent::PitchBend(semitones));
+        let ratio = 2f32.powf(semitones / 12.0);
+        self.pitch_bend.iter_mut().for_each(|bend| bend.set_target(ratio));
+    }
+
+    /// Sets the pitch bend of a single MIDI channel in semitones,
+    /// which only affects voices playing notes triggered on that channel.
+    pub fn set_channel_pitch_bend(&mut self, channel: Channel, semitones: f32) {
+        self.record(TraceEvent::ChannelPitchBend(channel.get(), semitones));
+        self.pitch_bend[channel.get() as usize].set_target(2f32.powf(semitones / 12.0));
+    }
+
+    /// Identifies the chord formed by the notes which are currently held down, if any.
+    ///
+    /// This is only available with the `analysis` feature.
+    #[cfg(feature = "analysis")]
+    pub fn held_chord(&self) -> Option<crate::Chord> {
+        crate::Chord::identify(self.held_notes().map(|held| held.note))
+    }
+
+    /// Gets the intervals in cents between the lowest held note and each held note,
+    /// under the active tuning.
+    ///
+    /// The intervals are in the same order as the notes returned by [held_notes](Self::held_notes).
+    pub fn held_intervals(&self) -> impl Iterator<Item = f32> + '_ {
+        let bass = self.held_notes().map(|held| held.note).min();
+        self.held_notes().map(move |held| {
+            bass.map_or(0.0, |bass| self.opts.tuning.cents(bass, held.note))
+        })
+    }
+
+    /// Sets the master gain as a linear amplitude.
+    ///
+    /// Changes are smoothed to avoid zipper noise.
+    pub fn set_gain(&mut self, gain: f32) {
+        self.record(TraceEvent::Gain(gain));
+        self.gain.set_target(gain);
+    }
+
+    /// Gets the master gain as a linear amplitude.
+    pub fn gain(&self) -> f32 {
+        self.gain.target()
+    }
+
+    /// Gets the notes which are currently held down, in no particular order.
+    ///
+    /// This can be used to capture the chord or voicing currently being played.
+    pub fn held_notes(&self) -> impl Iterator<Item = HeldNote> + '_ {
+        // In monophonic mode, notes may be held without a voice playing them
+        let (held, voices) = if self.opts.mono {
+            (&self.held[..], &[][..])
+        } else {
+            (&[][..], &self.voices[..self.opts.max_voices])
+        };
+
+        // With chord memory, several voices play the same note, which is only reported once
+        let first = |i: usize, held: &HeldNote| {
+            !voices[..i].iter().any(|voice| voice.held().is_some_and(|h| h.id == held.id))
+        };
+        let voices = voices.iter().enumerate();
+        held.iter().copied().chain(
+            voices.filter_map(move |(i, voice)| voice.held().filter(|held| first(i, held))),
+        )
+    }
+
+    /// Gets the status of each voice, in a fixed order.
+    ///
+    /// In monophonic mode, only the status of the single voice in use is reported.
+    pub fn voice_status(&self) -> impl Iterator<Item = VoiceStatus> + '_ {
+        let voices = if self.opts.mono {
+            &self.voices[..1]
+        } else {
+            &self.voices[..self.opts.max_voices]
+        };
+        voices.iter().map(|voice| {
+            let ratio = if voice.frozen { 1.0 } else { self.pitch_ratio[voice.channel as usize] };
+            VoiceStatus {
+                phase: voice.phase,
+                pitch: voice.rendered_pitch(&self.pitch_ratio),
+                bend: 12.0 * ratio.log2(),
+                age: self.position - voice.start,
+                peak: if voice.active() { voice.peak } else { 0.0 },
+                rms: if voice.active() { voice.rms } else { 0.0 },
+            }
+        })
+    }
+
+    /// Gets the pitch in Hz which the voice with the given index is currently rendering at,
+    /// accounting for glides and pitch bend, or `None` if the voice is inactive.
+    ///
+    /// Voices are indexed in the same order as [voice_status](Self::voice_status).
+    pub fn voice_pitch(&self, index: usize) -> Option<f32> {
+        let voice = self.voices.get(index).filter(|voice| voice.active())?;
+        Some(voice.rendered_pitch(&self.pitch_ratio))
+    }
+
+    /// Processes a MIDI control change message.
+    ///
+    /// # Parameters
+    /// * `channel` - The MIDI channel, between 0 and 15.
+    /// * `controller` - The controller number, between 0 and 127.
+    /// * `value` - The controller value, between 0 and 127.
+    pub fn control_change(&mut self, channel: Channel, controller: u8, value: u8) {
+        let event = MidiEvent::ControlChange { channel: channel.get(), controller, value };
+        self.record(TraceEvent::Midi(event));
+        let bank = &mut self.banks[channel.get() as usize];
+        match controller {
+            // Bank select MSB
+            0 => *bank = (*bank & 0x7f) | ((value as u16 & 0x7f) << 7),
+            // Bank select LSB
+            32 => *bank = (*bank & !0x7f) | (value as u16 & 0x7f),
+            // Portamento time
+            5 => self.portamento_time = Some(MAX_PORTAMENTO_TIME * (value as f32 / 127.0).powi(2)),
+            // Volume, which sets the gain of each note on the channel with MPE
+            7 => {
+                let gain = (value as f32 / 127.0).powi(2);
+                self.channel_volume[channel.get() as usize] = gain;
+                if self.opts.mpe {
+                    self.apply_note_gain(|voice| voice.channel == channel.get(), gain);
+                }
+            }
+            // Pan, which sets the stereo position of each note on the channel with MPE
+            10 => {
+                let pan = ((value as f32 - 64.0) / 63.0).clamp(-1.0, 1.0);
+                self.channel_pan[channel.get() as usize] = Some(pan);
+                if self.opts.mpe {
+                    self.apply_note_pan(|voice| voice.channel == channel.get(), pan);
+                }
+            }
+            // Expression
+            11 => self.expression.set_target((value as f32 / 127.0).powi(2)),
+            // Portamento on/off
+            65 => self.portamento_on = value >= 64,
+            // Portamento control, only where portamento applies
+            84 if self.opts.mono || self.opts.poly_portamento => {
+                self.portamento_source = Some(value & 0x7f)
+            }
+            // Legato footswitch
+            68 => self.legato_switch = Some(value >= 64),
+            // All sound off, which like every channel mode message only affects its own channel
+            120 => self.silence_all(Some(channel.get())),
+            // All notes off
+            123 => self.release_all(Some(channel.get())),
+            _ => {}
+        }
+    }
+
+    /// Selects a program within the bank most recently chosen on the given channel.
+    ///
+    /// # Parameters
+    /// * `channel` - The MIDI channel, between 0 and 15.
+    /// * `program` - The program number within the bank, between 0 and 127.
+    pub fn program_change(&mut self, channel: Channel, program: u8) {
+        self.record(TraceEvent::Midi(MidiEvent::ProgramChange { channel: channel.get(), program }));
+        let channel = channel.get() as usize;
+        self.programs[channel] = Program {
+            bank: self.banks[channel],
+            program: program & 0x7f,
+        };
+    }
+
+    /// Gets the program most recently selected on the given MIDI channel.
+    pub fn program(&self, channel: Channel) -> Program {
+        self.programs[channel.get() as usize]
+    }
+
+    /// Processes a MIDI message.
+    pub fn midi_event(&mut self, event: MidiEvent) {
+        match event {
+            MidiEvent::NoteOn { channel, note, velocity } => {
+                if velocity == 0 && self.opts.zero_velocity_note_off {
+                    self.note_off(channel.into(), note, Velocity::new(DEFAULT_RELEASE_VELOCITY))
+                } else {
+                    self.note_on(channel.into(), note, velocity.into());
+                }
+            }
+            MidiEvent::NoteOff { channel, note, velocity } => {
+                self.note_off(channel.into(), note, velocity.into())
+            }
+            MidiEvent::PitchBend { channel, value } => {
+                self.set_channel_pitch_bend(channel.into(), self.raw_pitch_bend(value))
+            }
+            MidiEvent::ControlChange { channel, controller, value } => {
+                self.control_change(channel.into(), controller, value)
+            }
+            MidiEvent::ProgramChange { channel, program } => {
+                self.program_change(channel.into(), program)
+            }
+            MidiEvent::ControlChange14 { channel, controller, value } => {
+                // Only the MSB controllers (CC0-31) are paired, with the LSB controller 32 above
+                if controller < 32 {
+                    let channel = Channel::new(channel);
+                    self.control_change(channel, controller, (value >> 7) as u8);
+                    self.control_change(channel, controller + 32, (value & 0x7f) as u8);
+                }
+            }
+        }
+    }
+
+    /// Synthesizes a block of audio into `output`.
+    ///
+    /// Blocks larger than the `max_block_size` of the synth's [SynthCapacity] are processed
+    /// in several smaller blocks.
+    pub fn process(&mut self, output: [&mut [f32]; 2]) {
+        let [left, right] = output;
+        assert_eq!(right.len(), left.len());
+
+        let max = self.capacity.max_block_size;
+        for (left, right) in left.chunks_mut(max).zip(right.chunks_mut(max)) {
+            self.process_block([left, right]);
+        }
+    }
+
+    /// Synthesizes a block of audio no larger than the maximum block size into `output`.
+    fn process_block(&mut self, output: [&mut [f32]; 2]) {
+        let [left, right] = output;
+        let len = left.len();
+        self.record(TraceEvent::Block(len));
+        self.update_pitch_ratio(len);
+
+        // Prepare temporary buffers for each voice's output.
+        let (left_temp, right_temp) = self.buffer[..2 * len].split_at_mut(len);
+
+        // Track whether any audio has been written to output.
+        let mut written = false;
+
+        // Process each active voice in turn.
+        let voices = if self.opts.mono {
+            &mut self.voices[..1]
+        } else {
+            &mut self.voices[..self.opts.max_voices]
+        };
+        let soloed = self.debug.any_soloed();
+        for (index, handle) in voices.iter_mut().enumerate() {
+            if !handle.active() {
+                continue;
+            }
+            let note = handle.note();
+            let heard = self.debug.heard(index, soloed);
+            if written || !heard {
+                handle.process(&self.pitch_ratio, self.position, [left_temp, right_temp]);
+                if heard {
+                    add_buffers(left, left_temp);
+                    add_buffers(right, right_temp);
+                }
+            } else {
+                handle.process(&self.pitch_ratio, self.position, [left, right]);
+                written = true;
+            }
+            if handle.disabled {
+                notify(&mut self.on_voice_panic, note);
+                let note = note.map(|held| held.note as f32);
+                log(&mut self.logger, LogLevel::Error, "Voice panicked", note, self.position);
+            } else if !handle.active() {
+                notify(&mut self.on_voice_end, note);
+            }
+        }
+
+        // If no voices are sounding, ensure the output buffer is filled with silence.
+        if !written {
+            left.fill(0.0);
+            right.fill(0.0);
+        }
+
+        // Apply the fade buffer
+        self.fade_out.process([left, right]);
+
+        self.finish_bus(0, [left, right]);
+        self.position += len as u64;
+    }
+
+    /// Applies the stages which follow the mix of voices to the output of a bus: the gain trim
+    /// of the voice, saturation, the noise gate, the expression and master gain, and the limiter.
+    ///
+    /// The noise gate and limiter of each bus have their own state, but the smoothing of the
+    /// expression and master gain is shared, so callers processing several buses should restore
+    /// it before each bus.
+    fn finish_bus(&mut self, bus: usize, output: [&mut [f32]; 2]) {
+        let [left, right] = output;
+
+        // Apply the gain trim of the voice
+        scale_buffer(left, self.trim);
+        scale_buffer(right, self.trim);
+
+        // Apply saturation
+        if let Some(saturation) = &self.opts.saturation {
+            saturation.process([left, right]);
+        }
+
+        // Apply the noise gate
+        if let Some(gate) = &self.opts.gate {
+            gate.process(&mut self.gate[bus], self.sample_rate, [left, right]);
+        }
+
+        // Apply the expression and master gain
+        self.expression.apply_gain([left, right]);
+        self.gain.apply_gain([left, right]);
+
+        // Apply the limiter
+        self.opts.limiter.process(&mut self.limiter[bus], self.sample_rate, [left, right]);
+    }
+
+    /// Updates the pitch ratio of each channel from the pitch bend and fine tune,
+    /// advancing their smoothing by a block of the given length.
+    fn update_pitch_ratio(&mut self, len: usize) {
+        let fine_tune = self.fine_tune.skip(len);
+        for (ratio, bend) in self.pitch_ratio.iter_mut().zip(&mut self.pitch_bend) {
+            *ratio = bend.skip(len) * fine_tune;
+        }
+    }
+
+    /// Updates the smoothing of each channel's pitch bend from the options.
+    fn update_pitch_bend_smoothing(&mut self) {
+        let time = self.opts.pitch_bend_smoothing.max(0.0);
+        for bend in &mut self.pitch_bend {
+            bend.set_time(time, self.sample_rate);
+        }
+    }
+
+    /// Converts a raw 14-bit MIDI pitch bend value into semitones.
+    fn raw_pitch_bend(&self, value: u16) -> f32 {
+        ((value as f32 - 8192.0) / 8192.0) * self.opts.max_pitch_bend
+    }
+
+    /// Chooses the stereo position for a newly triggered note.
+    fn next_pan(&mut self, note: Note) -> f32 {
+        match self.opts.pan {
+            PanMode::Center => 0.0,
+            PanMode::Fixed(pan) => pan.clamp(-1.0, 1.0),
+            PanMode::Random { spread, alternate: false } => {
+                spread * (2.0 * random(&mut self.rng) - 1.0)
+            }
+            PanMode::Random { spread, alternate: true } => {
+                self.pan_right = !self.pan_right;
+                let side = if self.pan_right { 1.0 } else { -1.0 };
+                side * spread * random(&mut self.rng)
+            }
+            PanMode::KeyTrack { low, high, width } => {
+                if high <= low {
+                    return 0.0;
+                }
+                let t = (note.clamp(low, high) - low) as f32 / (high - low) as f32;
+                width * (2.0 * t - 1.0)
+            }
+            PanMode::RoundRobin { width } => {
+                self.pan_right = !self.pan_right;
+                if self.pan_right { width } else { -width }
+            }
+        }
+    }
+
+    /// Gets the held note which should be sounding in monophonic mode, if any.
+    fn mono_note(&self) -> Option<HeldNote> {
+        match self.opts.note_priority {
+            NotePriority::Last => self.held.last().copied(),
+            NotePriority::Low => self.held.iter().copied().min_by_key(|held| held.note),
+            NotePriority::High => self.held.iter().copied().max_by_key(|held| held.note),
+        }
+    }
+
+    /// Synthesizes a block of audio into multiple stereo output buses, without mixing the voices.
+    ///
+    /// Each note is rendered to the bus chosen by the `bus_routing` setting, or set with
+    /// [set_note_bus](Self::set_note_bus), which allows notes to be processed separately,
+    /// such as by external effects. Notes routed to a bus which isn't passed are rendered to the
+    /// first bus, as are stolen voices while they fade out. Every bus passes through the same
+    /// output stage as [process](Self::process), with a noise gate and limiter of its own, and
+    /// blocks larger than the `max_block_size` of the synth are split into smaller blocks.
+    ///
+    /// # Parameters
+    /// * `buses` - The left and right audio buffers for each bus, which must all have the same
+    ///   length. There must be at least one bus, and no more than the `max_buses` of the synth's
+    ///   [SynthCapacity].
+    pub fn process_buses(&mut self, buses: &mut [[&mut [f32]; 2]]) {
+        assert!(!buses.is_empty(), "At least one output bus is required.");
+        assert!(buses.len() <= self.gate.len(), "Too many output buses for the synth's capacity.");
+        let len = buses[0][0].len();
+        assert!(buses.iter().all(|[left, right]| left.len() == len && right.len() == len));
+
+        let max = self.capacity.max_block_size;
+        for start in (0..len).step_by(max) {
+            self.process_buses_block(buses, start..len.min(start + max));
+        }
+    }
+
+    /// Synthesizes the given range of samples, no longer than the maximum block size, into
+    /// multiple stereo output buses.
+    fn process_buses_block(&mut self, buses: &mut [[&mut [f32]; 2]], range: Range<usize>) {
+        let len = range.len();
+        self.record(TraceEvent::Block(len));
+        self.update_pitch_ratio(len);
+
+        for [left, right] in buses.iter_mut() {
+            left[range.clone()].fill(0.0);
+            right[range.clone()].fill(0.0);
+        }
+
+        // Prepare temporary buffers for each voice's output.
+        let (left_temp, right_temp) = self.buffer[..2 * len].split_at_mut(len);
+
+        // Process each active voice into its bus.
+        let voices = if self.opts.mono {
+            &mut self.voices[..1]
+        } else {
+            &mut self.voices[..self.opts.max_voices]
+        };
+        let soloed = self.debug.any_soloed();
+        for (index, handle) in voices.iter_mut().enumerate() {
+            if !handle.active() {
+                continue;
+            }
+            let bus = if handle.bus < buses.len() { handle.bus } else { 0 };
+            let [left, right] = &mut buses[bus];
+            let note = handle.note();
+            handle.process(&self.pitch_ratio, self.position, [left_temp, right_temp]);
+            if self.debug.heard(index, soloed) {
+                add_buffers(&mut left[range.clone()], left_temp);
+                add_buffers(&mut right[range.clone()], right_temp);
+            }
+            if handle.disabled {
+                notify(&mut self.on_voice_panic, note);
+                let note = note.map(|held| held.note as f32);
+                log(&mut self.logger, LogLevel::Error, "Voice panicked", note, self.position);
+            } else if !handle.active() {
+                notify(&mut self.on_voice_end, note);
+            }
+        }
+
+        // Apply the fade buffer to the first bus
+        let [left, right] = &mut buses[0];
+        self.fade_out.process([&mut left[range.clone()], &mut right[range.clone()]]);
+
+        // Apply the output stage to every bus, advancing the smoothing only once
+        let (expression, gain) = (self.expression, self.gain);
+        for (bus, [left, right]) in buses.iter_mut().enumerate() {
+            self.expression = expression;
+            self.gain = gain;
+            self.finish_bus(bus, [&mut left[range.clone()], &mut right[range.clone()]]);
+        }
+
+        self.position += len as u64;
+    }
+
+    /// Moves a sounding note to another output bus of [process_buses](Self::process_buses),
+    /// overriding the bus chosen by the `bus_routing` setting.
+    ///
+    /// # Parameters
+    /// * `id` - The identifier returned when the note was triggered.
+    /// * `bus` - The index of the bus.
+    pub fn set_note_bus(&mut self, id: NoteId, bus: usize) {
+        for voice in self.voices.iter_mut().filter(|voice| voice.active() && voice.id == id) {
+            voice.bus = bus;
+        }
+    }
+
+    /// Synthesizes a block of audio into `output` as interleaved stereo frames.
+    ///
+    /// # Parameters
+    /// * `output` - The audio buffer for writing the output, alternating between
+    ///   left and right samples.
+    pub fn process_interleaved(&mut self, output: &mut [f32]) {
+        assert_eq!(output.len() % 2, 0, "Interleaved stereo buffer must have an even length.");
+
+        // Process into separate channels, then interleave them into the output
+        let mut buffer = std::mem::take(&mut self.scratch);
+        for output in output.chunks_mut(2 * self.capacity.max_block_size) {
+            let len = output.len() / 2;
+            let (left, right) = buffer[..2 * len].split_at_mut(len);
+            self.process_block([&mut *left, &mut *right]);
+            interleave(left, right, output);
+        }
+        self.scratch = buffer;
+    }
+
+    /// Synthesizes a block of mono audio into `output`, by averaging the left and right channels.
+    pub fn process_mono(&mut self, output: &mut [f32]) {
+        let mut buffer = std::mem::take(&mut self.scratch);
+        for output in output.chunks_mut(self.capacity.max_block_size) {
+            let right = &mut buffer[..output.len()];
+            self.process_block([&mut *output, &mut *right]);
+            for (sample, &r) in output.iter_mut().zip(right.iter()) {
+                *sample = 0.5 * (*sample + r);
+            }
+        }
+        self.scratch = buffer;
+    }
+
+    /// Synthesizes a block of audio into `output`, applying MIDI events part way through the block.
+    ///
+    /// # Parameters
+    /// * `events` - MIDI events paired with their offset in samples from the start of the block,
+    ///   sorted by offset. Events beyond the end of the block are applied at the end.
+    /// * `output` - The left and right audio buffers for writing the output.
+    pub fn process_with_events(&mut self, events: &[(usize, MidiEvent)], output: [&mut [f32]; 2]) {
+        let [left, right] = output;
+        let len = left.len();
+
+        let mut start = 0;
+        for &(offset, event) in events {
+            let offset = offset.clamp(start, len);
+            if offset > start {
+                self.process([&mut left[start..offset], &mut right[start..offset]]);
+                start = offset;
+            }
+            self.midi_event(event);
+        }
+        self.process([&mut left[start..], &mut right[start..]]);
+    }
+
+    /// Releases every held note on every channel, allowing the voices to finish their release
+    /// gracefully.
+    ///
+    /// An all notes off message (CC123) does the same, but only for the notes on its channel.
+    pub fn all_notes_off(&mut self) {
+        self.record(TraceEvent::AllNotesOff);
+        self.release_all(None);
+    }
+
+    /// Immediately silences every voice on every channel, fading them out over a few milliseconds
+    /// to avoid clicks.
+    ///
+    /// An all sound off message (CC120) does the same, but only for the voices on its channel.
+    pub fn panic(&mut self) {
+        self.record(TraceEvent::Panic);
+        self.silence_all(None);
+    }
+
+    /// Releases every held note, or only those on the given MIDI channel.
+    fn release_all(&mut self, channel: Option<u8>) {
+        let ctx = self.voice_ctx();
+        let on_channel = |held: &HeldNote| channel.is_none_or(|c| held.channel.get() == c);
+        self.held.retain(|held| !on_channel(held));
+
+        // In monophonic mode, fall back to a note which is still held on another channel
+        let fall_back = self.opts.mono && self.voices[0].held().is_some_and(|h| on_channel(&h));
+        if let Some(next) = self.mono_note().filter(|_| fall_back) {
+            self.trigger_voice(next);
+        }
+
+        for voice in &mut self.voices {
+            if voice.held().is_some_and(|held| on_channel(&held)) {
+                voice.release(Velocity::new(0), &ctx);
+            }
+        }
+        self.counter += 1;
+    }
+
+    /// Silences every voice, or only those playing notes on the given MIDI channel,
+    /// fading them out.
+    fn silence_all(&mut self, channel: Option<u8>) {
+        let on_channel = |held: &HeldNote| channel.is_none_or(|c| held.channel.get() == c);
+        self.held.retain(|held| !on_channel(held));
+        self.portamento_source = None;
+        for voice in &mut self.voices {
+            if voice.note().is_some_and(|held| on_channel(&held)) {
+                notify(&mut self.on_voice_end, voice.note());
+                self.fade_out.add_voice(|buf| voice.process(&self.pitch_ratio, self.position, buf));
+                voice.reset();
+            }
+        }
+
+        // In monophonic mode, play a note which is still held on another channel
+        if self.opts.mono && !self.voices[0].active() {
+            if let Some(next) = self.mono_note() {
+                self.trigger_voice(next);
+            }
+        }
+    }
+
+    /// Interpolates a velocity towards the fixed velocity according to the velocity sensitivity.
+    fn apply_sensitivity(&self, velocity: Velocity) -> Velocity {
+        let sensitivity = self.opts.velocity_sensitivity.clamp(0.0, 1.0);
+        let fixed = FIXED_VELOCITY as f32;
+        let velocity = fixed + sensitivity * (velocity.get() as f32 - fixed);
+        Velocity::new(velocity.round() as u8)
+    }
+
+    /// Updates the length and shape of the fade applied to stolen voices.
+    fn update_fade(&mut self) {
+        let len = self.opts.fade_time.max(0.0) * self.sample_rate as f32;
+        self.fade_out.set_fade(len as usize, self.opts.fade_curve);
+    }
+
+    /// Validates the synthesiser options.
+    fn validate_opts(opts: &SynthOpts, capacity: &SynthCapacity) {
+        if opts.max_voices == 0 {
+            panic!("Synth must have at least one voice.");
+        }
+        if capacity.max_block_size == 0 {
+            panic!("Synth must have a block size of at least one sample.");
+        }
+        if opts.max_voices > capacity.max_voices {
+            panic!("Synth cannot use more voices than it has capacity for.");
+        }
+        if opts.reserved_voices >= opts.max_voices {
+            panic!("Synth cannot reserve every voice.");
+        }
+        if opts.max_voices_per_note == Some(0) {
+            panic!("Synth must allow at least one voice per note.");
+        }
+    }
+
+    /// Gets the context to pass to a voice being triggered/released.
+    fn voice_ctx(&self) -> VoiceCtx {
+        VoiceCtx {
+            sample_rate: self.sample_rate,
+            portamento: match (self.portamento_on, self.opts.portamento, self.portamento_time) {
+                (false, ..) => Portamento::Off,
+                (true, Portamento::Fixed(_), Some(time)) => Portamento::Fixed(time),
+                (true, Portamento::Variable(_), Some(time)) => Portamento::Variable(time),
+                (true, Portamento::Glissando(_), Some(time)) => Portamento::Glissando(time),
+                (true, portamento, _) => portamento,
+            },
+            channel: 0,
+            id: NoteId(0),
+            velocity: 0,
+            legato: self.opts.mono && self.legato_switch.unwrap_or(self.opts.legato),
+            resume_tail: false,
+            glide_from: self.portamento_source.map(|note| self.opts.tuning.pitch(note)),
+            tuning: matches!(self.opts.portamento, Portamento::Glissando(_))
+                .then(|| self.opts.tuning.clone()),
+            pan: 0.0,
+            gain: 1.0,
+            width: self.opts.width,
+            counter: self.counter,
+            position: self.position,
+            freeze_release_pitch: self.opts.freeze_release_pitch,
+            pitch_ratio: self.pitch_ratio,
+        }
+    }
+}
+
+impl<V: Voice> VoiceHandle<V> {
+    #[cfg_attr(not(feature = "catch-unwind"), allow(unused_variables))]
+    fn new(voice: V, max_block_size: usize) -> Self {
+        Self {
+            voice,
+            phase: VoicePhase::Off,
+            pitch: 0.0,
+            channel: 0,
+            id: NoteId(0),
+            velocity: 0,
+            played_velocity: 0,
+            start: 0,
+            pan: Smoothed::new(0.0),
+            gain: Smoothed::new(1.0),
+            width: 1.0,
+            bend: 1.0,
+            bus: 0,
+            glide: None,
+            counter: 0,
+            disabled: false,
+            frozen: false,
+            peak: 0.0,
+            rms: 0.0,
+            #[cfg(feature = "catch-unwind")]
+            last: [vec![0.0; max_block_size], vec![0.0; max_block_size]],
+            #[cfg(feature = "catch-unwind")]
+            last_len: 0,
+        }
+    }
+
+    /// Returns `true` if the voice is not producing silence.
+    fn active(&self) -> bool {
+        self.phase != VoicePhase::Off
+    }
+
+    /// Gets the note that the voice is currently playing, if it is in the `On` phase.
+    fn note_on(&self) -> Option<Note> {
+        if let VoicePhase::On(note) = self.phase {
+            Some(note)
+        } else {
+            None
+        }
+    }
+
+    /// Gets the note which is held down on this voice, if any.
+    fn held(&self) -> Option<HeldNote> {
+        self.note().filter(|_| matches!(self.phase, VoicePhase::On(_)))
+    }
+
+    /// Gets the note which this voice is playing, if any, whether or not it is still held.
+    fn note(&self) -> Option<HeldNote> {
+        let note = match self.phase {
+            VoicePhase::On(note) | VoicePhase::Released(note) => note,
+            VoicePhase::Off => return None,
+        };
+        Some(HeldNote {
+            id: self.id,
+            channel: Channel::new(self.channel),
+            note,
+            velocity: Velocity::new(self.played_velocity),
+            start: self.start,
+        })
+    }
+
+    /// Gets the priority used for voice allocation, with the lowest priority being preferred.
+    ///
+    /// # Parameters
+    /// * `channel` - The MIDI channel of the note being triggered.
+    /// * `note` - The MIDI note being triggered.
+    /// * `policy` - The policy for choosing which voice to steal.
+    /// * `nearest` - The pitch of the triggered note in Hz, if the released voice nearest
+    ///   in pitch should be preferred.
+    fn priority(
+        &self,
+        channel: u8,
+        note: Note,
+        policy: StealPolicy,
+        nearest: Option<f32>,
+    ) -> (usize, usize) {
+        // Only the same note on the same channel counts as the same key, as each note of an
+        // MPE controller is played on a channel of its own
+        let same_key = |n: Note| n == note && self.channel == channel;
+        if self.disabled {
+            return (usize::MAX, usize::MAX);
+        }
+        if let (VoicePhase::Released(_), Some(level)) = (self.phase, self.voice.current_level()) {
+            // Released voices which are nearly silent are stolen before any audible voice
+            if level.abs() < SILENT_LEVEL {
+                return (2, level.abs().to_bits() as usize);
+            }
+        }
+        if let (VoicePhase::Released(n), Some(pitch)) = (self.phase, nearest) {
+            if policy != StealPolicy::SameNoteFirst || !same_key(n) {
+                // Rank released voices by their distance from the new note in cents
+                let cents = 1200.0 * (self.pitch() / pitch).log2().abs();
+                return (3, cents as usize);
+            }
+        }
+        if policy == StealPolicy::SameNoteFirst {
+            return match self.phase {
+                // Note has been re-triggered
+                VoicePhase::On(n) if same_key(n) => (0, 0),
+                // Unused voice
+                VoicePhase::Off => (1, 0),
+                // Released voice for the same note
+                VoicePhase::Released(n) if same_key(n) => (2, 0),
+                // Oldest released note
+                VoicePhase::Released(_) => (3, self.counter),
+                // Oldest triggered note
+                VoicePhase::On(_) => (4, self.counter),
+            };
+        }
+
+        // Unused voices are preferred, then released voices, then triggered voices
+        let (class, n) = match self.phase {
+            VoicePhase::Off => return (1, 0),
+            VoicePhase::Released(n) => (3, n),
+            VoicePhase::On(n) => (4, n),
+        };
+
+        let key = match policy {
+            StealPolicy::Oldest | StealPolicy::SameNoteFirst => self.counter,
+            StealPolicy::Newest => usize::MAX - self.counter,
+            StealPolicy::LowestNote => n as usize,
+            StealPolicy::HighestNote => 127 - n.min(127) as usize,
+            StealPolicy::Quietest => self.velocity as usize,
+            // The bits of a non-negative float are ordered in the same way as its value
+            StealPolicy::LowestLevel => {
+                self.voice.current_level().unwrap_or(self.rms).abs().to_bits() as usize
+            }
+        };
+        (class, key)
+    }
+
+    /// Sets the sample rate.
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.voice.set_sample_rate(sample_rate);
+    }
+
+    /// Resets the voice.
+    fn reset(&mut self) {
+        self.voice.reset();
+        self.phase = VoicePhase::Off;
+    }
+
+    /// Triggers a note.
+    ///
+    /// # Parameters
+    /// * `note` - The note which was played, used to identify the note when it is released.
+    /// * `sounding` - The note passed to the voice, after transposition.
+    /// * `velocity` - The velocity passed to the voice, after velocity sensitivity.
+    /// * `pitch` - The pitch of the sounding note in Hz.
+    /// * `ctx` - The context from the synth.
+    fn trigger(&mut self, note: Note, sounding: Note, velocity: u8, pitch: f32, ctx: &VoiceCtx) {
+        if self.disabled {
+            return;
+        }
+        self.glide = self.calc_glide(pitch, ctx);
+        if ctx.legato && matches!(self.phase, VoicePhase::On(_)) {
+            self.voice.glide(sounding, Velocity::new(velocity));
+        } else if ctx.resume_tail && matches!(self.phase, VoicePhase::Released(_)) {
+            // Move smoothly to the new note's settings, as the voice is still sounding
+            self.voice.retrigger(sounding, Velocity::new(velocity));
+            self.pan.set_target(ctx.pan);
+            self.gain.set_target(ctx.gain);
+            self.width = ctx.width;
+        } else {
+            self.voice.trigger(sounding, Velocity::new(velocity));
+            self.pan = Smoothed::new(ctx.pan);
+            self.pan.set_time(SMOOTHING_TIME, ctx.sample_rate);
+            self.gain = Smoothed::new(ctx.gain);
+            self.gain.set_time(SMOOTHING_TIME, ctx.sample_rate);
+            self.width = ctx.width;
+        }
+
+        self.pitch = pitch;
+        self.bend = 1.0;
+        self.channel = ctx.channel;
+        self.id = ctx.id;
+        self.velocity = velocity;
+        self.played_velocity = ctx.velocity;
+        self.start = ctx.position;
+        self.phase = VoicePhase::On(note);
+        self.frozen = false;
+        self.counter = ctx.counter;
+    }
+
+    /// Releases the current note.
+    pub fn release(&mut self, velocity: Velocity, ctx: &VoiceCtx) {
         let note = match self.phase {
             VoicePhase::On(note) => note,
             VoicePhase::Released(note) => note,
             VoicePhase::Off => return,
         };
 
-        self.voice.release();
+        // Fold the current glide and pitch bend into the pitch of the voice, the first time it
+        // is released
+        if ctx.freeze_release_pitch && !self.frozen {
+            self.pitch = self.pitch() * ctx.pitch_ratio[self.channel as usize];
+            self.glide = None;
+            self.frozen = true;
+        }
+
+        self.voice.release(velocity);
         self.phase = VoicePhase::Released(note);
         self.counter = ctx.counter;
     }
 
     /// Processes the voice into the provided output buffer.
-    fn process(&mut self, pitch_bend: f32, output: [&mut [f32]; 2]) {
-        let num_samples = output[0].len();
+    ///
+    /// # Parameters
+    /// * `pitch_ratio` - The pitch ratio of each MIDI channel, including pitch bend.
+    /// * `position` - The position of the first sample of the block, in samples since the synth
+    ///   was created.
+    /// * `output` - The left and right audio buffers for writing the output.
+    fn process(&mut self, pitch_ratio: &[f32; 16], position: u64, output: [&mut [f32]; 2]) {
+        let [left, right] = output;
+        let num_samples = left.len();
 
         // Process audio
-        let active = self.voice.process(self.pitch() * pitch_bend, output);
+        let pitch = self.rendered_pitch(pitch_ratio);
+        #[cfg(not(feature = "catch-unwind"))]
+        let active = {
+            self.voice.set_position(position);
+            self.voice.process(pitch, [left, right])
+        };
+        #[cfg(feature = "catch-unwind")]
+        let active = {
+            let process = || {
+                self.voice.set_position(position);
+                self.voice.process(pitch, [&mut *left, &mut *right])
+            };
+            match std::panic::catch_unwind(std::panic::AssertUnwindSafe(process)) {
+                Ok(active) => {
+                    // Keep the end of the output, in case the voice panics in the next block
+                    let len = num_samples.min(self.last[0].len());
+                    for (last, output) in self.last.iter_mut().zip([&*left, &*right]) {
+                        last[..len].copy_from_slice(&output[num_samples - len..]);
+                    }
+                    self.last_len = len;
+                    active
+                }
+                Err(_) => {
+                    // The voice may have been left in an inconsistent state, so silence it for
+                    // good, fading out from the previous output played backwards, which
+                    // continues smoothly from the last sample heard
+                    let len = num_samples.min(self.last_len);
+                    for (last, output) in self.last.iter().zip([&mut *left, &mut *right]) {
+                        let reversed = last[..self.last_len].iter().rev();
+                        for (i, (sample, &last)) in output.iter_mut().zip(reversed).enumerate() {
+                            *sample = last * (1.0 - i as f32 / len as f32);
+                        }
+                        output[len..].fill(0.0);
+                    }
+                    self.disabled = true;
+                    false
+                }
+            }
+        };
         if !active {
             self.phase = VoicePhase::Off;
         }
 
+        // Apply stereo width by scaling the side signal
+        if self.width != 1.0 {
+            for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                let mid = 0.5 * (*l + *r);
+                let side = 0.5 * self.width * (*l - *r);
+                *l = mid + side;
+                *r = mid - side;
+            }
+        }
+
+        // Apply panning, attenuating the channel opposite the pan direction
+        if self.pan.is_settled() {
+            let pan = self.pan.target();
+            if pan < 0.0 {
+                right.iter_mut().for_each(|s| *s *= 1.0 + pan);
+            } else if pan > 0.0 {
+                left.iter_mut().for_each(|s| *s *= 1.0 - pan);
+            }
+        } else {
+            for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                let pan = self.pan.next();
+                *l *= 1.0 - pan.max(0.0);
+                *r *= 1.0 + pan.min(0.0);
+            }
+        }
+
+        // Apply the gain of the note
+        self.gain.apply_gain([&mut *left, &mut *right]);
+
+        // Measure the output level
+        let (mut peak, mut sum) = (0f32, 0f32);
+        for &s in left.iter().chain(right.iter()) {
+            peak = peak.max(s.abs());
+            sum += s * s;
+        }
+        self.peak = peak;
+        self.rms = if num_samples > 0 { (sum / (2 * num_samples) as f32).sqrt() } else { 0.0 };
+
         // Update glide state
         if let Some(glide) = &mut self.glide {
             glide.time += num_samples;
@@ -379,11 +2370,25 @@ impl<V: Voice> VoiceHandle<V> {
         }
     }
 
+    /// Calculates the pitch the voice is rendered at, accounting for glide, pitch bend and the
+    /// pitch expression of the note.
+    fn rendered_pitch(&self, pitch_ratio: &[f32; 16]) -> f32 {
+        if self.frozen {
+            self.pitch * self.bend
+        } else {
+            self.pitch() * pitch_ratio[self.channel as usize] * self.bend
+        }
+    }
+
     /// Calculates the current pitch, accounting for glide but not pitch bend.
     fn pitch(&self) -> f32 {
-        if let Some(glide) = self.glide {
+        if let Some(glide) = &self.glide {
             let t = (glide.time as f32) / (glide.duration as f32);
-            2_f32.powf(glide.start + t * (glide.target - glide.start))
+            let pitch = 2_f32.powf(glide.start + t * (glide.target - glide.start));
+            match &glide.tuning {
+                Some(tuning) => tuning.pitch(tuning.nearest_note(pitch)),
+                None => pitch,
+            }
         } else {
             self.pitch
         }
@@ -395,34 +2400,528 @@ impl<V: Voice> VoiceHandle<V> {
     /// * `target_pitch` - Pitch of the triggered note in Hz.
     /// * `ctx` - The context from the synth.
     fn calc_glide(&self, target_pitch: f32, ctx: &VoiceCtx) -> Option<GlideState> {
-        // Only glide when a note is triggered while another is playing
-        if !matches!(self.phase, VoicePhase::On(_)) {
-            return None;
-        }
-
-        match ctx.portamento {
-            Portamento::Fixed(time) => {
-                let start = self.pitch().log2();
-                let target = target_pitch.log2();
-                let duration = (time * ctx.sample_rate as f32) as usize;
-                println!("{} {} {}", start, target, duration);
-                Some(GlideState { start, target, time: 0, duration })
-            }
-            Portamento::Variable(rate) => {
-                let start = self.pitch().log2();
-                let target = target_pitch.log2();
+        let start = match ctx.glide_from {
+            // Portamento control specifies the note to glide from
+            Some(pitch) => pitch.log2(),
+            // Otherwise, only glide when a note is triggered while another is playing
+            None if matches!(self.phase, VoicePhase::On(_)) => self.pitch().log2(),
+            None => return None,
+        };
+
+        let target = target_pitch.log2();
+        let duration = match ctx.portamento {
+            Portamento::Fixed(time) => (time * ctx.sample_rate as f32) as usize,
+            Portamento::Variable(rate) | Portamento::Glissando(rate) => {
                 let distance = (start - target).abs();
-                let duration = (rate * distance * ctx.sample_rate as f32) as usize;
-                Some(GlideState { start, target, time: 0, duration })
+                (rate * distance * ctx.sample_rate as f32) as usize
+            }
+            Portamento::Off => return None,
+        };
+
+        // A glide of zero length is just a jump to the target pitch
+        (duration > 0).then(|| GlideState {
+            start,
+            target,
+            time: 0,
+            duration,
+            tuning: ctx.tuning.clone(),
+        })
+    }
+}
+
+/// Solo and mute controls, which can be used from any thread.
+impl VoiceDebug {
+    /// Creates a handle for the given number of voices, none of which are soloed or muted.
+    fn new(num_voices: usize) -> Self {
+        Self((0..num_voices).map(|_| AtomicU8::new(0)).collect())
+    }
+
+    /// Mutes or unmutes the voice with the given index.
+    pub fn set_muted(&self, voice: usize, muted: bool) {
+        self.set_flag(voice, MUTE, muted);
+    }
+
+    /// Solos or unsolos the voice with the given index.
+    pub fn set_soloed(&self, voice: usize, soloed: bool) {
+        self.set_flag(voice, SOLO, soloed);
+    }
+
+    /// Returns `true` if the voice with the given index is muted.
+    pub fn muted(&self, voice: usize) -> bool {
+        self.flags(voice) & MUTE != 0
+    }
+
+    /// Returns `true` if the voice with the given index is soloed.
+    pub fn soloed(&self, voice: usize) -> bool {
+        self.flags(voice) & SOLO != 0
+    }
+
+    /// Unmutes and unsolos every voice.
+    pub fn clear(&self) {
+        for flags in self.0.iter() {
+            flags.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns `true` if any voice is soloed.
+    fn any_soloed(&self) -> bool {
+        self.0.iter().any(|flags| flags.load(Ordering::Relaxed) & SOLO != 0)
+    }
+
+    /// Returns `true` if the voice with the given index should be heard,
+    /// given whether any voice is soloed.
+    fn heard(&self, voice: usize, any_soloed: bool) -> bool {
+        let flags = self.flags(voice);
+        flags & MUTE == 0 && (!any_soloed || flags & SOLO != 0)
+    }
+
+    /// Gets the flags of the voice with the given index, which are zero if it doesn't exist.
+    fn flags(&self, voice: usize) -> u8 {
+        self.0.get(voice).map_or(0, |flags| flags.load(Ordering::Relaxed))
+    }
+
+    /// Sets or clears a flag of the voice with the given index, if it exists.
+    fn set_flag(&self, voice: usize, flag: u8, set: bool) {
+        if let Some(flags) = self.0.get(voice) {
+            if set {
+                flags.fetch_or(flag, Ordering::Relaxed);
+            } else {
+                flags.fetch_and(!flag, Ordering::Relaxed);
             }
-            Portamento::Off => None
         }
     }
 }
 
+/// Sends a record to the logger, if there is one.
+fn log(
+    logger: &mut Option<Logger>,
+    level: LogLevel,
+    message: &'static str,
+    value: Option<f32>,
+    position: u64,
+) {
+    if let Some(logger) = logger {
+        logger.log(LogRecord { level, message, value, position });
+    }
+}
+
+/// Calls a voice callback, if it is set, with the given note, if any.
+fn notify(callback: &mut VoiceCallback, note: Option<HeldNote>) {
+    if let (Some(callback), Some(note)) = (callback, note) {
+        callback(note);
+    }
+}
+
+/// Calculates the gain trim which compensates for the nominal level of a voice.
+fn trim(voice: &impl Voice) -> f32 {
+    let level = voice.nominal_level();
+    if level > 0.0 { level.recip() } else { 1.0 }
+}
+
+/// Generates a pseudo-random number between 0 and 1 using a xorshift generator.
+pub(crate) fn random(state: &mut u32) -> f32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    (*state >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Interleaves the two channels of a stereo signal.
+fn interleave(left: &[f32], right: &[f32], output: &mut [f32]) {
+    for (frame, (&l, &r)) in output.chunks_exact_mut(2).zip(left.iter().zip(right)) {
+        frame[0] = l;
+        frame[1] = r;
+    }
+}
+
+fn scale_buffer(buffer: &mut [f32], gain: f32) {
+    if gain != 1.0 {
+        buffer.iter_mut().for_each(|s| *s *= gain);
+    }
+}
+
 fn add_buffers(dst: &mut [f32], src: &[f32]) {
     assert_eq!(src.len(), dst.len());
     for i in 0..src.len() {
         dst[i] += src[i];
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// A call made to a [TestVoice].
+    #[derive(Copy, Clone, PartialEq, Debug)]
+    enum Call {
+        Trigger(Note),
+        Retrigger(Note),
+        Glide(Note),
+        Release,
+        Reset,
+    }
+
+    /// A voice which outputs a constant level until released, and records the calls made to it.
+    #[derive(Clone, Default)]
+    struct TestVoice {
+        /// The calls made to every clone of the voice, in order.
+        calls: Arc<Mutex<Vec<Call>>>,
+        /// Whether a note is playing.
+        on: bool,
+    }
+
+    impl TestVoice {
+        /// Takes the calls made to the voice and its clones so far.
+        fn take_calls(&self) -> Vec<Call> {
+            std::mem::take(&mut self.calls.lock().unwrap())
+        }
+    }
+
+    impl Voice for TestVoice {
+        fn set_sample_rate(&mut self, _sample_rate: u32) {}
+
+        fn reset(&mut self) {
+            self.calls.lock().unwrap().push(Call::Reset);
+            self.on = false;
+        }
+
+        fn trigger(&mut self, note: Note, _velocity: Velocity) {
+            self.calls.lock().unwrap().push(Call::Trigger(note));
+            self.on = true;
+        }
+
+        fn retrigger(&mut self, note: Note, _velocity: Velocity) {
+            self.calls.lock().unwrap().push(Call::Retrigger(note));
+            self.on = true;
+        }
+
+        fn glide(&mut self, note: Note, _velocity: Velocity) {
+            self.calls.lock().unwrap().push(Call::Glide(note));
+        }
+
+        fn release(&mut self, _velocity: Velocity) {
+            self.calls.lock().unwrap().push(Call::Release);
+            self.on = false;
+        }
+
+        fn process(&mut self, _pitch: f32, output: [&mut [f32]; 2]) -> bool {
+            let level = if self.on { 0.5 } else { 0.0 };
+            output.into_iter().for_each(|channel| channel.fill(level));
+            self.on
+        }
+    }
+
+    /// Creates a synth with room for eight voices, returning it with its prototype voice.
+    fn synth(opts: SynthOpts) -> (Synth<TestVoice>, TestVoice) {
+        let capacity = SynthCapacity { max_block_size: 64, max_voices: 8, max_buses: 2 };
+        let voice = TestVoice::default();
+        let mut synth = Synth::new(capacity, opts, voice.clone());
+        synth.set_sample_rate(48000);
+        (synth, voice)
+    }
+
+    #[test]
+    fn high_resolution_controllers_outside_the_msb_range_are_ignored() {
+        let (mut synth, _) = synth(SynthOpts::default());
+        let channel = Channel::new(0);
+        for controller in [32, 96, 100, 127] {
+            synth.midi_event(MidiEvent::ControlChange14 { channel: 0, controller, value: 16383 });
+        }
+        assert_eq!(synth.program(channel), Program::default());
+
+        // Bank select is still paired with its LSB
+        let bank = (3 << 7) | 5;
+        synth.midi_event(MidiEvent::ControlChange14 { channel: 0, controller: 0, value: bank });
+        synth.program_change(channel, 9);
+        assert_eq!(synth.program(channel), Program { bank, program: 9 });
+    }
+
+    /// Counts the voices playing a held note.
+    fn held_voices(synth: &Synth<TestVoice>) -> usize {
+        synth.voice_status().filter(|status| matches!(status.phase, VoicePhase::On(_))).count()
+    }
+
+    #[test]
+    fn same_note_on_another_channel_takes_a_voice_of_its_own() {
+        let (mut synth, _) = synth(SynthOpts::default());
+        synth.note_on(Channel::new(1), 60, Velocity::new(100));
+        synth.note_on(Channel::new(2), 60, Velocity::new(100));
+        assert_eq!(held_voices(&synth), 2);
+
+        // Retriggering the same note on the same channel still reuses its voice
+        synth.note_on(Channel::new(2), 60, Velocity::new(100));
+        assert_eq!(held_voices(&synth), 2);
+    }
+
+    #[test]
+    fn mono_same_note_on_another_channel_doesnt_retrigger() {
+        let opts = SynthOpts { mono: true, note_priority: NotePriority::Low, ..Default::default() };
+        let (mut synth, voice) = synth(opts);
+        synth.note_on(Channel::new(1), 60, Velocity::new(100));
+        assert_eq!(voice.take_calls(), [Call::Trigger(60)]);
+
+        // The older note on channel 1 keeps priority, and is already playing
+        synth.note_on(Channel::new(2), 60, Velocity::new(100));
+        assert_eq!(voice.take_calls(), []);
+        assert_eq!(synth.held_notes().count(), 2);
+    }
+
+    #[test]
+    fn mono_overlapping_notes_retrigger_only_without_legato_or_portamento() {
+        let mono = SynthOpts { mono: true, legato: false, ..Default::default() };
+        let cases = [
+            (Portamento::Off, false, vec![Call::Reset, Call::Trigger(64)]),
+            (Portamento::Off, true, vec![Call::Glide(64)]),
+            (Portamento::Fixed(0.1), false, vec![Call::Glide(64)]),
+            (Portamento::Fixed(0.1), true, vec![Call::Glide(64)]),
+        ];
+        for (case, (portamento, legato, calls)) in cases.into_iter().enumerate() {
+            let (mut synth, voice) = synth(SynthOpts { portamento, legato, ..mono.clone() });
+            synth.trigger(60, Velocity::new(100));
+            synth.trigger(64, Velocity::new(100));
+            assert_eq!(voice.take_calls()[1..], calls, "case {case}");
+        }
+    }
+
+    #[test]
+    fn voice_steal_hook_is_only_called_when_a_voice_is_reassigned() {
+        use std::sync::atomic::AtomicUsize;
+
+        let cases = [
+            (SynthOpts { mono: true, legato: true, ..Default::default() }, 0),
+            (SynthOpts { mono: true, portamento: Portamento::Fixed(0.1), ..Default::default() }, 0),
+            (SynthOpts { mono: true, legato: false, ..Default::default() }, 1),
+            (SynthOpts { max_voices: 1, ..Default::default() }, 1),
+        ];
+        for (case, (opts, expected)) in cases.into_iter().enumerate() {
+            let (mut synth, _) = synth(opts);
+            let steals = Arc::new(AtomicUsize::new(0));
+            let counter = steals.clone();
+            synth.on_voice_steal(move |_| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            });
+            synth.trigger(60, Velocity::new(100));
+            synth.trigger(64, Velocity::new(100));
+            assert_eq!(steals.load(Ordering::Relaxed), expected, "case {case}");
+        }
+    }
+
+    #[test]
+    fn channel_mode_messages_only_affect_their_own_channel() {
+        let (mut synth, _) = synth(SynthOpts::default());
+        let cc = |channel, controller| MidiEvent::ControlChange { channel, controller, value: 0 };
+        synth.note_on(Channel::new(1), 60, Velocity::new(100));
+        synth.note_on(Channel::new(2), 64, Velocity::new(100));
+        synth.note_on(Channel::new(3), 67, Velocity::new(100));
+
+        // All notes off releases the notes on its channel
+        synth.midi_event(cc(1, 123));
+        let held: Vec<_> = synth.held_notes().map(|held| held.note).collect();
+        assert_eq!(held, [64, 67]);
+
+        // All sound off silences the voices on its channel
+        synth.midi_event(cc(2, 120));
+        let active = synth.voice_status().filter(|status| status.phase != VoicePhase::Off);
+        let notes: Vec<_> = active.map(|status| status.phase).collect();
+        assert_eq!(notes, [VoicePhase::Released(60), VoicePhase::On(67)]);
+
+        // The methods still affect every channel
+        synth.all_notes_off();
+        assert_eq!(synth.held_notes().count(), 0);
+    }
+
+    #[test]
+    fn mono_all_notes_off_falls_back_to_a_note_on_another_channel() {
+        let (mut synth, voice) = synth(SynthOpts { mono: true, ..Default::default() });
+        synth.note_on(Channel::new(1), 60, Velocity::new(100));
+        synth.note_on(Channel::new(2), 64, Velocity::new(100));
+        voice.take_calls();
+
+        synth.control_change(Channel::new(2), 123, 0);
+        let held: Vec<_> = synth.held_notes().map(|held| held.note).collect();
+        assert_eq!(held, [60]);
+        assert_eq!(voice.take_calls(), [Call::Glide(60)]);
+    }
+
+    #[test]
+    fn portamento_control_is_ignored_without_portamento() {
+        let poly = SynthOpts { portamento: Portamento::Fixed(0.1), ..Default::default() };
+        let (mut plain, _) = synth(poly.clone());
+        plain.control_change(Channel::new(0), 84, 48);
+        plain.trigger(60, Velocity::new(100));
+        let pitch = plain.voice_pitch(0).unwrap();
+        assert_eq!(pitch, Tuning::concert_pitch().pitch(60));
+
+        // With poly portamento, the note glides from the source note
+        let (mut glide, _) = synth(SynthOpts { poly_portamento: true, ..poly });
+        glide.control_change(Channel::new(0), 84, 48);
+        glide.trigger(60, Velocity::new(100));
+        let pitch = glide.voice_pitch(0).unwrap();
+        assert_eq!(pitch, Tuning::concert_pitch().pitch(48));
+    }
+
+    #[test]
+    fn stolen_voices_fade_out_before_the_sample_rate_is_set() {
+        let capacity = SynthCapacity { max_block_size: 64, max_voices: 1, max_buses: 1 };
+        let opts = SynthOpts { max_voices: 1, ..Default::default() };
+        let mut synth = Synth::new(capacity, opts, TestVoice::default());
+        let (mut left, mut right) = ([0.0; 64], [0.0; 64]);
+
+        synth.trigger(60, Velocity::new(100));
+        synth.process([&mut left, &mut right]);
+        synth.trigger(64, Velocity::new(100));
+        synth.process([&mut left, &mut right]);
+
+        // The stolen voice is mixed with the new one as it fades out
+        assert!(left[0] > 0.9 && left[63] < left[0]);
+    }
+
+    #[cfg(feature = "catch-unwind")]
+    #[test]
+    fn panicking_voice_fades_out_and_can_be_revived() {
+        /// A voice which outputs a rising ramp, and panics in its third block.
+        #[derive(Clone, Default)]
+        struct FragileVoice {
+            blocks: usize,
+            on: bool,
+        }
+
+        impl Voice for FragileVoice {
+            fn set_sample_rate(&mut self, _sample_rate: u32) {}
+
+            fn reset(&mut self) {
+                self.on = false;
+            }
+
+            fn trigger(&mut self, _note: Note, _velocity: Velocity) {
+                self.on = true;
+            }
+
+            fn release(&mut self, _velocity: Velocity) {
+                self.on = false;
+            }
+
+            fn process(&mut self, _pitch: f32, output: [&mut [f32]; 2]) -> bool {
+                self.blocks += 1;
+                assert!(self.blocks != 3, "voice failed");
+                for channel in output {
+                    for (i, sample) in channel.iter_mut().enumerate() {
+                        *sample = 0.5 + 0.001 * i as f32;
+                    }
+                }
+                self.on
+            }
+        }
+
+        let capacity = SynthCapacity { max_block_size: 64, max_voices: 1, max_buses: 1 };
+        let opts = SynthOpts { max_voices: 1, ..Default::default() };
+        let mut synth = Synth::new(capacity, opts, FragileVoice::default());
+        let mut blocks = [[0.0; 64]; 4];
+        let mut right = [0.0; 64];
+        synth.trigger(60, Velocity::new(100));
+        for block in &mut blocks {
+            synth.process([block, &mut right]);
+        }
+
+        // The voice fades out from where it left off, rather than cutting to silence
+        assert_eq!(blocks[2][0], blocks[1][63]);
+        assert!(blocks[2].windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(blocks[2][63] < 0.01);
+        assert!(blocks[3].iter().all(|&sample| sample == 0.0));
+
+        // The disabled voice plays again once it is revived
+        synth.trigger(62, Velocity::new(100));
+        assert!(synth.voice_pitch(0).is_none());
+        synth.revive_voices();
+        synth.trigger(62, Velocity::new(100));
+        assert!(synth.voice_pitch(0).is_some());
+    }
+
+    /// Processes enough blocks for smoothed parameters to settle, returning the peak level of
+    /// the first voice.
+    fn settle(synth: &mut Synth<TestVoice>) -> f32 {
+        let (mut left, mut right) = ([0.0; 64], [0.0; 64]);
+        for _ in 0..200 {
+            synth.process([&mut left, &mut right]);
+        }
+        synth.voice_status().next().unwrap().peak
+    }
+
+    #[test]
+    fn mpe_channel_volume_and_pan_apply_to_the_notes_on_the_channel() {
+        let (mut synth, _) = synth(SynthOpts { mpe: true, ..Default::default() });
+        synth.control_change(Channel::new(1), 7, 127);
+        synth.note_on(Channel::new(1), 60, Velocity::new(100));
+        synth.note_on(Channel::new(2), 64, Velocity::new(100));
+        synth.control_change(Channel::new(2), 7, 64);
+        assert_eq!(settle(&mut synth), 0.5);
+        let peaks: Vec<_> = synth.voice_status().map(|status| status.peak).collect();
+        assert!((peaks[1] - 0.5 * (64.0f32 / 127.0).powi(2)).abs() < 1e-4);
+
+        // A note triggered on the channel starts at the channel's volume
+        synth.control_change(Channel::new(3), 7, 0);
+        synth.note_on(Channel::new(3), 67, Velocity::new(100));
+        settle(&mut synth);
+        assert_eq!(synth.voice_status().nth(2).unwrap().peak, 0.0);
+    }
+
+    #[test]
+    fn channel_volume_doesnt_affect_notes_without_mpe() {
+        let (mut synth, _) = synth(SynthOpts::default());
+        synth.note_on(Channel::new(1), 60, Velocity::new(100));
+        synth.control_change(Channel::new(1), 7, 0);
+        assert_eq!(settle(&mut synth), 0.5);
+    }
+
+    #[test]
+    fn per_note_controllers_affect_a_single_note() {
+        let (mut synth, _) = synth(SynthOpts::default());
+        synth.note_on(Channel::new(0), 60, Velocity::new(100));
+        synth.note_on(Channel::new(0), 64, Velocity::new(100));
+        synth.per_note_controller(Channel::new(0), 64, 7, 0);
+        synth.per_note_controller(Channel::new(0), 60, 10, u32::MAX);
+        settle(&mut synth);
+        let peaks: Vec<_> = synth.voice_status().map(|status| status.peak).collect();
+        assert_eq!(peaks[..2], [0.5, 0.0]);
+
+        // The first note is panned hard right
+        let (mut left, mut right) = ([0.0; 64], [0.0; 64]);
+        synth.process([&mut left, &mut right]);
+        assert_eq!((left[0], right[0]), (0.0, 0.5));
+    }
+
+    #[test]
+    fn buses_are_routed_by_channel_in_blocks_with_the_output_stage() {
+        let opts = SynthOpts {
+            bus_routing: BusRouting::Channel,
+            limiter: Limiter::SoftClip,
+            ..Default::default()
+        };
+        let (mut synth, _) = synth(opts);
+        synth.set_gain(8.0);
+        synth.note_on(Channel::new(1), 60, Velocity::new(100));
+        let (mut main_left, mut main_right) = ([0.0; 150], [0.0; 150]);
+        let (mut bus_left, mut bus_right) = ([0.0; 150], [0.0; 150]);
+        let mut render = |synth: &mut Synth<TestVoice>| {
+            synth.process_buses(&mut [
+                [&mut main_left, &mut main_right],
+                [&mut bus_left, &mut bus_right],
+            ]);
+            (main_left, bus_left)
+        };
+
+        // The whole block is rendered to the bus of the channel, and limited
+        let (main, bus) = render(&mut synth);
+        assert!(main.iter().all(|&sample| sample == 0.0));
+        assert!(bus.iter().all(|&sample| sample > 0.0 && sample <= 1.0));
+
+        // Notes on a channel without a bus are rendered to the first bus, unless moved
+        let id = synth.note_on(Channel::new(5), 64, Velocity::new(100));
+        let (main, _) = render(&mut synth);
+        assert!(main.iter().all(|&sample| sample > 0.0 && sample <= 1.0));
+        synth.set_note_bus(id, 1);
+        let (main, _) = render(&mut synth);
+        assert!(main.iter().all(|&sample| sample == 0.0));
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{Channel, MidiEvent, NoteId, Synth, SynthOpts, Velocity, Voice};
+
+/// A log of everything which happened to a [Synth], for reproducing glitches deterministically.
+///
+/// A trace is recorded by passing it to [Synth::start_trace], which captures every note, MIDI
+/// event, settings change and block boundary along with the sample at which it happened.
+/// Once retrieved with [Synth::take_trace], it can be replayed into a freshly created synth
+/// with the same voice, which then produces exactly the same audio.
+///
+/// The trace never allocates while recording, so it can be used on the audio thread.
+/// Once it is full, further events are dropped and counted by [dropped](Self::dropped).
+#[derive(Clone)]
+pub struct Trace {
+    /// The recorded events, along with the sample at which they happened.
+    events: Vec<(u64, TraceEvent)>,
+    /// Snapshots of the settings, referred to by [TraceEvent::Opts].
+    opts: Vec<SynthOpts>,
+    /// The number of events which were dropped because the trace was full.
+    dropped: usize,
+}
+
+/// An event recorded in a [Trace].
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum TraceEvent {
+    /// A MIDI event, including notes triggered and released through the synth's methods.
+    Midi(MidiEvent),
+    /// A note released by its identifier, with the given velocity.
+    ReleaseId(NoteId, u8),
+    /// The gain of the given note was set.
+    NoteGain(NoteId, f32),
+    /// The stereo position of the given note was set.
+    NotePan(NoteId, f32),
+    /// The pitch of the given note was bent, in semitones.
+    NotePitch(NoteId, f32),
+    /// The pressure of the given note was set.
+    NotePressure(NoteId, f32),
+    /// The timbre of the given note was set.
+    NoteTimbre(NoteId, f32),
+    /// A MIDI 2.0 per-note controller of the given channel and note was set to the given value.
+    PerNoteController(u8, u8, u8, u32),
+    /// The pitch bend of every channel was set, in semitones.
+    PitchBend(f32),
+    /// The pitch bend of the given channel was set, in semitones.
+    ChannelPitchBend(u8, f32),
+    /// Every held note was released.
+    AllNotesOff,
+    /// Every voice was silenced.
+    Panic,
+    /// The synth was reset to its initial state.
+    Reset,
+    /// The master gain was set.
+    Gain(f32),
+    /// The sample rate was set.
+    SampleRate(u32),
+    /// The settings were changed, to the snapshot with the given index.
+    Opts(usize),
+    /// A block of the given length was processed.
+    Block(usize),
+}
+
+impl Trace {
+    /// Creates an empty trace with room for the given number of events,
+    /// of which at most `max_opts` can be changes of settings.
+    pub fn new(max_events: usize, max_opts: usize) -> Self {
+        Self {
+            events: Vec::with_capacity(max_events),
+            opts: Vec::with_capacity(max_opts),
+            dropped: 0,
+        }
+    }
+
+    /// Gets the number of recorded events.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Returns `true` if no events have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Gets the number of events which were dropped because the trace was full.
+    ///
+    /// A trace which has dropped events can't be replayed faithfully.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Forgets every recorded event, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.opts.clear();
+        self.dropped = 0;
+    }
+
+    /// Replays the trace into a synth, calling `output` with the audio of each block.
+    ///
+    /// For the audio to match the original, the synth should be freshly created with the same
+    /// voice and capacity as the synth the trace was recorded from. Blocks processed into
+    /// several buses with [Synth::process_buses] are replayed as a single stereo output.
+    /// This allocates memory, so shouldn't be called from the audio thread.
+    pub fn replay<V: Voice + Clone>(
+        &self,
+        synth: &mut Synth<V>,
+        mut output: impl FnMut(&[f32], &[f32]),
+    ) {
+        let max_block_size = synth.capacity().max_block_size;
+        let (mut left, mut right) = (vec![0.0; max_block_size], vec![0.0; max_block_size]);
+
+        for &(_, event) in &self.events {
+            match event {
+                TraceEvent::Midi(MidiEvent::NoteOn { channel, note, velocity }) => {
+                    synth.note_on(channel.into(), note, velocity.into());
+                }
+                TraceEvent::Midi(event) => synth.midi_event(event),
+                TraceEvent::ReleaseId(id, velocity) => {
+                    synth.release_id(id, Velocity::new(velocity))
+                }
+                TraceEvent::NoteGain(id, gain) => synth.set_note_gain(id, gain),
+                TraceEvent::NotePan(id, pan) => synth.set_note_pan(id, pan),
+                TraceEvent::NotePitch(id, semitones) => synth.set_note_pitch(id, semitones),
+                TraceEvent::NotePressure(id, pressure) => synth.set_note_pressure(id, pressure),
+                TraceEvent::NoteTimbre(id, timbre) => synth.set_note_timbre(id, timbre),
+                TraceEvent::PerNoteController(channel, note, controller, value) => {
+                    synth.per_note_controller(Channel::new(channel), note, controller, value)
+                }
+                TraceEvent::PitchBend(semitones) => synth.set_pitch_bend(semitones),
+                TraceEvent::ChannelPitchBend(channel, semitones) => {
+                    synth.set_channel_pitch_bend(Channel::new(channel), semitones)
+                }
+                TraceEvent::AllNotesOff => synth.all_notes_off(),
+                TraceEvent::Panic => synth.panic(),
+                TraceEvent::Reset => synth.reset(),
+                TraceEvent::Gain(gain) => synth.set_gain(gain),
+                TraceEvent::SampleRate(sample_rate) => synth.set_sample_rate(sample_rate),
+                TraceEvent::Opts(index) => {
+                    let opts = &self.opts[index];
+                    synth.update_opts(|o| *o = opts.clone());
+                }
+                TraceEvent::Block(len) => {
+                    let (left, right) = (&mut left[..len], &mut right[..len]);
+                    synth.process([&mut *left, &mut *right]);
+                    output(left, right);
+                }
+            }
+        }
+    }
+
+    /// Records an event which happened at the given sample, if there is room for it.
+    pub(crate) fn record(&mut self, position: u64, event: TraceEvent) {
+        if self.events.len() < self.events.capacity() {
+            self.events.push((position, event));
+        } else {
+            self.dropped += 1;
+        }
+    }
+
+    /// Records a change of settings at the given sample, if there is room for it.
+    pub(crate) fn record_opts(&mut self, position: u64, opts: &SynthOpts) {
+        if self.opts.len() < self.opts.capacity() {
+            self.opts.push(opts.clone());
+            self.record(position, TraceEvent::Opts(self.opts.len() - 1));
+        } else {
+            self.dropped += 1;
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::{Note, Tuning};
+
+/// Detects the dominant pitch of an audio signal, such as the output of the synth,
+/// and reports its deviation from the nearest note of a [Tuning].
+///
+/// Audio is accumulated with [push](Self::push), which is cheap enough to call from the audio
+/// thread, while [reading](Self::reading) performs the analysis and should be called elsewhere.
+pub struct Tuner {
+    /// The sample rate in Hz.
+    sample_rate: u32,
+    /// The most recent samples, stored as a ring buffer.
+    buffer: Vec<f32>,
+    /// The index in `buffer` of the oldest sample.
+    index: usize,
+    /// The window in chronological order, used for the analysis.
+    signal: Vec<f32>,
+    /// The cumulative mean normalised difference for each period, used for the analysis.
+    cmnd: Vec<f32>,
+}
+
+/// The result of a pitch analysis by a [Tuner].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TunerReading {
+    /// The detected pitch in Hz.
+    pub pitch: f32,
+    /// The note of the tuning whose pitch is nearest to the detected pitch.
+    pub note: Note,
+    /// The deviation of the detected pitch from the pitch of `note`, in cents.
+    pub cents: f32,
+}
+
+/// The threshold of the normalised difference function below which a period is accepted.
+const THRESHOLD: f32 = 0.15;
+
+/// The lowest pitch in Hz which can be detected.
+const MIN_PITCH: f32 = 20.0;
+
+impl Tuner {
+    /// Creates a tuner which analyses the most recent `window` samples.
+    ///
+    /// The window must span at least two periods of the lowest pitch to be detected.
+    pub fn new(sample_rate: u32, window: usize) -> Self {
+        assert!(window > 0, "Tuner window must contain at least one sample.");
+        Self {
+            sample_rate,
+            buffer: vec![0.0; window],
+            index: 0,
+            signal: vec![0.0; window],
+            cmnd: vec![0.0; window / 2 + 1],
+        }
+    }
+
+    /// Sets the sample rate of the analysed audio.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Adds a block of stereo audio to the analysis window.
+    pub fn push(&mut self, input: [&[f32]; 2]) {
+        let [left, right] = input;
+        for (&l, &r) in left.iter().zip(right) {
+            self.buffer[self.index] = 0.5 * (l + r);
+            self.index = (self.index + 1) % self.buffer.len();
+        }
+    }
+
+    /// Analyses the window, returning the detected pitch relative to the given tuning,
+    /// or `None` if there is no clear pitch.
+    pub fn reading(&mut self, tuning: &Tuning) -> Option<TunerReading> {
+        let pitch = self.detect_pitch()?;
+        let note = tuning.nearest_note(pitch);
+        let cents = 1200.0 * (pitch / tuning.pitch(note)).log2();
+        Some(TunerReading { pitch, note, cents })
+    }
+
+    /// Detects the fundamental frequency of the window using the YIN algorithm.
+    fn detect_pitch(&mut self) -> Option<f32> {
+        // Linearise the ring buffer
+        let len = self.buffer.len();
+        let (older, newer) = self.buffer.split_at(self.index);
+        let signal = &mut self.signal;
+        signal[..newer.len()].copy_from_slice(newer);
+        signal[newer.len()..].copy_from_slice(older);
+
+        let max_period = usize::min(len / 2, (self.sample_rate as f32 / MIN_PITCH) as usize);
+        if max_period < 3 {
+            return None;
+        }
+
+        // Calculate the cumulative mean normalised difference for each candidate period
+        let window = len - max_period;
+        let cmnd = &mut self.cmnd;
+        let mut running_sum = 0.0;
+        cmnd[0] = 1.0;
+        for period in 1..=max_period {
+            let d: f32 = (0..window)
+                .map(|i| (signal[i] - signal[i + period]).powi(2))
+                .sum();
+            running_sum += d;
+            cmnd[period] = if running_sum > 0.0 {
+                d * period as f32 / running_sum
+            } else {
+                1.0
+            };
+        }
+
+        // Find the first period below the threshold, then descend to its local minimum
+        let mut period = 2;
+        loop {
+            if period >= max_period {
+                return None;
+            }
+            if cmnd[period] < THRESHOLD {
+                while period + 1 < max_period && cmnd[period + 1] < cmnd[period] {
+                    period += 1;
+                }
+                break;
+            }
+            period += 1;
+        }
+
+        // Refine the period with parabolic interpolation
+        let (a, b, c) = (cmnd[period - 1], cmnd[period], cmnd[period + 1]);
+        let denominator = a - 2.0 * b + c;
+        let offset = if denominator.abs() > f32::EPSILON {
+            (0.5 * (a - c) / denominator).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+
+        Some(self.sample_rate as f32 / (period as f32 + offset))
+    }
+}
@@ -28,4 +28,28 @@ impl Tuning {
         *self.notes.get(note as usize)
             .expect("MIDI note must be between 0 and 127.")
     }
+
+    /// Gets the interval between two notes in cents.
+    pub fn cents(&self, from: Note, to: Note) -> f32 {
+        1200.0 * (self.pitch(to) / self.pitch(from)).log2()
+    }
+
+    /// Gets the note whose pitch is nearest to the provided pitch in Hz, measured logarithmically.
+    ///
+    /// This assumes that the pitches of the tuning ascend with each note.
+    pub fn nearest_note(&self, pitch: f32) -> Note {
+        let index = self.notes.partition_point(|&p| p < pitch);
+        if index == 0 {
+            return 0;
+        }
+        if index == self.notes.len() {
+            return 127;
+        }
+        let (below, above) = (self.notes[index - 1], self.notes[index]);
+        if pitch / below < above / pitch {
+            (index - 1) as Note
+        } else {
+            index as Note
+        }
+    }
 }
\ No newline at end of file
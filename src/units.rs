@@ -0,0 +1,113 @@
+/// The velocity of a note, between 0 and 127.
+///
+/// Converting from a `u8` clamps the value into range.
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Velocity(u8);
+
+/// A MIDI channel, between 0 and 15.
+///
+/// Converting from a `u8` masks the value into range, as when decoding a MIDI status byte.
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Channel(u8);
+
+/// A value between 0 and 1, such as a controller position.
+///
+/// Converting from an `f32` clamps the value into range, with NaN becoming 0.
+#[derive(Copy, Clone, Default, PartialEq, PartialOrd, Debug)]
+pub struct Normalized(f32);
+
+impl Velocity {
+    /// The highest velocity.
+    pub const MAX: Self = Self(127);
+
+    /// Creates a velocity, clamping it to 127.
+    pub const fn new(velocity: u8) -> Self {
+        Self(if velocity > 127 { 127 } else { velocity })
+    }
+
+    /// Gets the velocity as a MIDI value, between 0 and 127.
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+
+    /// Gets the velocity as a value between 0 and 1.
+    pub fn normalized(self) -> Normalized {
+        Normalized(self.0 as f32 / 127.0)
+    }
+}
+
+impl Channel {
+    /// Creates a channel, keeping only its lowest 4 bits.
+    pub const fn new(channel: u8) -> Self {
+        Self(channel & 0x0f)
+    }
+
+    /// Gets the channel number, between 0 and 15.
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl Normalized {
+    /// Creates a normalized value, clamping it between 0 and 1.
+    pub fn new(value: f32) -> Self {
+        Self(if value.is_nan() { 0.0 } else { value.clamp(0.0, 1.0) })
+    }
+
+    /// Gets the value, between 0 and 1.
+    pub const fn get(self) -> f32 {
+        self.0
+    }
+
+    /// Converts a 7-bit MIDI value, between 0 and 127, into a normalized value.
+    pub fn from_midi(value: u8) -> Self {
+        Self(value.min(127) as f32 / 127.0)
+    }
+
+    /// Converts the value into a 7-bit MIDI value, between 0 and 127.
+    pub fn to_midi(self) -> u8 {
+        (self.0 * 127.0).round() as u8
+    }
+}
+
+impl From<u8> for Velocity {
+    fn from(velocity: u8) -> Self {
+        Self::new(velocity)
+    }
+}
+
+impl From<Velocity> for u8 {
+    fn from(velocity: Velocity) -> Self {
+        velocity.0
+    }
+}
+
+impl From<u8> for Channel {
+    fn from(channel: u8) -> Self {
+        Self::new(channel)
+    }
+}
+
+impl From<Channel> for u8 {
+    fn from(channel: Channel) -> Self {
+        channel.0
+    }
+}
+
+impl From<f32> for Normalized {
+    fn from(value: f32) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<Normalized> for f32 {
+    fn from(value: Normalized) -> Self {
+        value.0
+    }
+}
+
+impl From<Velocity> for Normalized {
+    fn from(velocity: Velocity) -> Self {
+        velocity.normalized()
+    }
+}
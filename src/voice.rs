@@ -1,4 +1,4 @@
-use crate::Note;
+use crate::{Note, Resources, Velocity};
 
 /// An individual voice used to synthesize audio for a single note.
 pub trait Voice {
@@ -12,6 +12,38 @@ pub trait Voice {
     /// This is invoked on an active or releasing voice that is stolen for a new note.
     fn reset(&mut self);
 
+    /// Gives the voice access to large, immutable data shared between voices,
+    /// such as wavetables or samples.
+    ///
+    /// This is called on the prototype voice before it is cloned, and on every voice when the
+    /// resources of the synth are replaced. Voices should keep the [Arc](std::sync::Arc) handles
+    /// they need rather than copying the data. The default implementation does nothing.
+    fn set_resources(&mut self, resources: &Resources) {
+        let _ = resources;
+    }
+
+    /// Prepares the voice for processing blocks of up to the given number of samples.
+    ///
+    /// This is called off the audio thread once the voice is configured: on the prototype voice
+    /// before it is cloned, and on every voice after the sample rate is set. Voices can use it
+    /// to precompute tables and allocate scratch buffers, so that their first call to
+    /// [process](Self::process) doesn't cause a glitch. The default implementation does nothing.
+    fn prepare(&mut self, max_block_size: usize) {
+        let _ = max_block_size;
+    }
+
+    /// Informs the voice of the current position of the synth's sample clock, in samples since
+    /// the synth was created.
+    ///
+    /// This is called before each block is processed, with the position of its first sample,
+    /// so voices can implement behaviour tied to absolute time, such as free-running LFOs which
+    /// stay in phase across notes. Combinators may process a block in several smaller pieces,
+    /// so voices which track the time should also advance it by the length of each buffer they
+    /// [process](Self::process). The default implementation does nothing.
+    fn set_position(&mut self, position: u64) {
+        let _ = position;
+    }
+
     /// Triggers a note to be played.
     ///
     /// If the voice is still active, then [reset] is invoked immediately before this
@@ -21,8 +53,22 @@ pub trait Voice {
     ///
     /// # Parameters
     /// * `note` - The MIDI note being triggered, between 0 and 127.
-    /// * `velocity` - The velocity of the note, between 0 and 127.
-    fn trigger(&mut self, note: Note, velocity: u8);
+    /// * `velocity` - The velocity of the note.
+    fn trigger(&mut self, note: Note, velocity: Velocity);
+
+    /// Triggers a note on a voice which is still releasing the previous note.
+    ///
+    /// This is used in monophonic mode with [MonoTail::Legato](crate::MonoTail::Legato), so that
+    /// voices can continue their envelopes from the current level rather than restarting from
+    /// silence. The default implementation resets the voice and then triggers the note.
+    ///
+    /// # Parameters
+    /// * `note` - The MIDI note being triggered, between 0 and 127.
+    /// * `velocity` - The velocity of the note.
+    fn retrigger(&mut self, note: Note, velocity: Velocity) {
+        self.reset();
+        self.trigger(note, velocity);
+    }
 
     /// Triggers a note to be glided to.
     ///
@@ -31,13 +77,52 @@ pub trait Voice {
     ///
     /// # Parameters
     /// * `note` - The MIDI note being triggered, between 0 and 127.
-    /// * `velocity` - The velocity of the note, between 0 and 127.
-    fn glide(&mut self, note: Note, velocity: u8) {
+    /// * `velocity` - The velocity of the note.
+    fn glide(&mut self, note: Note, velocity: Velocity) {
         let _ = (note, velocity);
     }
 
+    /// Sets the pressure applied to the playing note, such as from polyphonic aftertouch or an
+    /// expression curve, from 0 to 1.
+    ///
+    /// Each note starts with a pressure of 0. The default implementation does nothing.
+    fn set_pressure(&mut self, pressure: f32) {
+        let _ = pressure;
+    }
+
+    /// Sets the timbre of the playing note, such as from the slide dimension of an MPE
+    /// controller or an expression curve, from 0 to 1.
+    ///
+    /// Each note starts with a timbre of 0.5. The default implementation does nothing.
+    fn set_timbre(&mut self, timbre: f32) {
+        let _ = timbre;
+    }
+
+    /// Gets the nominal output level of the voice, as a linear gain.
+    ///
+    /// The synth divides the output of its voices by this level, so that switching between
+    /// voices with different loudness doesn't result in large jumps in volume.
+    /// The default implementation returns 1, which applies no trim.
+    fn nominal_level(&self) -> f32 {
+        1.0
+    }
+
+    /// Gets the current output level of the voice as a linear amplitude, if the voice is able
+    /// to report it, such as from the value of its amplitude envelope.
+    ///
+    /// This is used when stealing voices, so that released voices which are nearly silent are
+    /// stolen before audible ones. It is called on the audio thread whenever a voice is
+    /// allocated, so should be cheap. The default implementation returns `None`.
+    fn current_level(&self) -> Option<f32> {
+        None
+    }
+
     /// Releases the currently playing note.
-    fn release(&mut self);
+    ///
+    /// # Parameters
+    /// * `velocity` - The release velocity of the note,
+    ///   which indicates how quickly the key was lifted.
+    fn release(&mut self, velocity: Velocity);
 
     /// Synthesizes audio in stereo.
     ///
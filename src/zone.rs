@@ -0,0 +1,76 @@
+use crate::{Channel, MidiEvent, Note};
+
+/// A region of the keyboard, such as the left hand of a split keyboard,
+/// whose notes are routed to a synth of its own.
+///
+/// Each synth can have its own voice and settings, so a single keyboard can play bass with
+/// the left hand and lead with the right. Zones may overlap, in which case notes in the overlap
+/// are routed to every zone containing them, layering the synths.
+#[derive(Copy, Clone, Debug)]
+pub struct Zone {
+    /// The lowest note of the zone.
+    pub low: Note,
+    /// The highest note of the zone.
+    pub high: Note,
+    /// The MIDI channel the zone listens to, or `None` to listen to every channel.
+    pub channel: Option<Channel>,
+    /// The number of semitones by which notes in the zone are transposed.
+    pub transpose: i8,
+}
+
+impl Zone {
+    /// Creates a zone spanning the given range of notes on every channel, without transposition.
+    pub fn new(low: Note, high: Note) -> Self {
+        Self {
+            low,
+            high,
+            channel: None,
+            transpose: 0,
+        }
+    }
+
+    /// Returns `true` if a note played on the given channel falls within the zone.
+    pub fn contains(&self, channel: Channel, note: Note) -> bool {
+        (self.low..=self.high).contains(&note) && self.listens_to(channel)
+    }
+
+    /// Routes a MIDI event to the zone.
+    ///
+    /// # Return
+    /// Returns the event to pass to the zone's synth, with any transposition applied,
+    /// or `None` if the event is for a note outside the zone or a channel it doesn't listen to.
+    /// Events other than notes, such as pitch bends and control changes, are routed to every
+    /// zone listening to their channel.
+    pub fn route(&self, event: MidiEvent) -> Option<MidiEvent> {
+        match event {
+            MidiEvent::NoteOn { channel, note, velocity } => {
+                let note = self.transposed(Channel::new(channel), note)?;
+                Some(MidiEvent::NoteOn { channel, note, velocity })
+            }
+            MidiEvent::NoteOff { channel, note, velocity } => {
+                let note = self.transposed(Channel::new(channel), note)?;
+                Some(MidiEvent::NoteOff { channel, note, velocity })
+            }
+            MidiEvent::PitchBend { channel, .. }
+            | MidiEvent::ControlChange { channel, .. }
+            | MidiEvent::ProgramChange { channel, .. }
+            | MidiEvent::ControlChange14 { channel, .. } => {
+                self.listens_to(Channel::new(channel)).then_some(event)
+            }
+        }
+    }
+
+    /// Returns `true` if the zone listens to the given channel.
+    fn listens_to(&self, channel: Channel) -> bool {
+        self.channel.is_none_or(|c| c == channel)
+    }
+
+    /// Gets the transposed note, if the note falls within the zone and the MIDI range.
+    fn transposed(&self, channel: Channel, note: Note) -> Option<Note> {
+        if !self.contains(channel, note) {
+            return None;
+        }
+        let note = note as i32 + self.transpose as i32;
+        (0..=127).contains(&note).then_some(note as Note)
+    }
+}